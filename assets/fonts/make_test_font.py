@@ -0,0 +1,72 @@
+#!/usr/bin/env python3
+# Build SiliconTest-Regular.ttf, a minimal font for the tests of font fallback.
+# It has a rectangle reaching below the baseline, mapped to U+4E2D (中) and `M`, an empty
+# narrow glyph mapped to `i`, so that it's not monospaced, and metrics different from Hack.
+#
+# Usage: python3 make_test_font.py SiliconTest-Regular.ttf
+import struct, sys
+
+UPEM = 1000
+CHAR = 0x4E2D
+WIDE, NARROW = ord('M'), ord('i')
+
+def checksum(data):
+    data += b'\0' * (-len(data) % 4)
+    return sum(struct.unpack('>%dI' % (len(data) // 4), data)) & 0xFFFFFFFF
+
+head = struct.pack('>IIIIHHqqhhhhHHhhh', 0x00010000, 0x00010000, 0, 0x5F0F3CF5, 0x000B, UPEM,
+                   0, 0, 100, -100, 900, 700, 0, 8, 2, 0, 0)
+hhea = struct.pack('>IhhhHhhhhhhhhhhhH', 0x00010000, 800, -200, 0, 1000, 0, 0, 900, 1, 0, 0,
+                   0, 0, 0, 0, 0, 3)
+maxp = struct.pack('>IHHHHHHHHHHHHHH', 0x00010000, 3, 4, 1, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0)
+os2 = struct.pack('>HhHHHhhhhhhhhhhh10sIIII4sHHHhhhHHIIhhHHH',
+                  4, 1000, 400, 5, 0, 650, 600, 0, 75, 650, 600, 0, 350, 50, 250, 0,
+                  b'\0' * 10, 0, 0, 0, 0, b'TEST', 0x0040, WIDE, CHAR, 800, -200, 0, 800, 200,
+                  1, 0, 500, 700, 0, 0x20, 1)
+assert len(os2) == 96
+hmtx = struct.pack('>HhHhHh', 500, 0, 1000, 100, 300, 0)
+# (char, glyph) of each segment, the last one is required
+segments = [(WIDE, 1), (NARROW, 2), (CHAR, 1), (0xFFFF, 0)]
+cmap4 = struct.pack('>HHHHHHH', 4, 16 + 8 * len(segments), 0, 8, 8, 2, 0)
+cmap4 += struct.pack('>4H', *(c for c, _ in segments)) + b'\0\0'
+cmap4 += struct.pack('>4H', *(c for c, _ in segments))
+cmap4 += struct.pack('>4H', *((g - c) & 0xFFFF if c != 0xFFFF else 1 for c, g in segments))
+cmap4 += struct.pack('>4H', 0, 0, 0, 0)
+assert len(cmap4) == 48
+cmap = struct.pack('>HHHHI', 0, 1, 3, 1, 12) + cmap4
+# a clockwise rectangle from (100, -100) to (900, 700)
+glyph = struct.pack('>hhhhhHH', 1, 100, -100, 900, 700, 3, 0)
+glyph += bytes([1, 1, 1, 1])
+glyph += struct.pack('>hhhh', 100, 0, 800, 0) + struct.pack('>hhhh', -100, 800, 0, -800)
+glyph += b'\0' * (-len(glyph) % 4)
+glyf = glyph
+# the glyph of `i` has no outline
+loca = struct.pack('>HHHH', 0, 0, len(glyph) // 2, len(glyph) // 2)
+names = [(1, 'Silicon Test'), (2, 'Regular'), (4, 'Silicon Test Regular'),
+         (6, 'SiliconTest-Regular')]
+strings = b''
+records = b''
+for name_id, text in names:
+    data = text.encode('utf-16-be')
+    records += struct.pack('>HHHHHH', 3, 1, 0x409, name_id, len(data), len(strings))
+    strings += data
+name = struct.pack('>HHH', 0, len(names), 6 + len(records)) + records + strings
+post = struct.pack('>IIhhIIIII', 0x00030000, 0, -100, 50, 0, 0, 0, 0, 0)
+
+tables = {b'OS/2': os2, b'cmap': cmap, b'glyf': glyf, b'head': head, b'hhea': hhea,
+          b'hmtx': hmtx, b'loca': loca, b'maxp': maxp, b'name': name, b'post': post}
+tags = sorted(tables)
+num = len(tags)
+offset = 12 + 16 * num
+directory = struct.pack('>IHHHH', 0x00010000, num, 128, 3, num * 16 - 128)
+body = b''
+for tag in tags:
+    data = tables[tag]
+    directory += struct.pack('>4sIII', tag, checksum(data), offset + len(body), len(data))
+    body += data + b'\0' * (-len(data) % 4)
+font = bytearray(directory + body)
+# checkSumAdjustment of head
+head_offset = offset + sum(len(tables[t]) + (-len(tables[t]) % 4) for t in tags[:tags.index(b'head')])
+adjust = (0xB1B0AFBA - checksum(bytes(font))) & 0xFFFFFFFF
+font[head_offset + 8:head_offset + 12] = struct.pack('>I', adjust)
+open(sys.argv[1], 'wb').write(font)
@@ -0,0 +1,241 @@
+//! Convert text with ANSI escape sequences (eg. the output of `ls --color`) into highlighted lines
+//!
+//! # Example
+//!
+//! ```
+//! use silicon::ansi::highlight_ansi;
+//! use silicon::assets::HighlightingAssets;
+//!
+//! let ha = HighlightingAssets::new();
+//! let theme = &ha.theme_set.themes["Dracula"];
+//! let highlight = highlight_ansi("\x1b[1;31merror\x1b[0m: oops\n", theme);
+//! ```
+use crate::formatter::{HighlightedLines, DEFAULT_BACKGROUND, DEFAULT_FOREGROUND};
+use syntect::highlighting::{Color, FontStyle, Style, Theme};
+
+/// The 16 basic colors (same as xterm)
+const BASIC_COLORS: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0xcd, 0x00, 0x00),
+    (0x00, 0xcd, 0x00),
+    (0xcd, 0xcd, 0x00),
+    (0x00, 0x00, 0xee),
+    (0xcd, 0x00, 0xcd),
+    (0x00, 0xcd, 0xcd),
+    (0xe5, 0xe5, 0xe5),
+    (0x7f, 0x7f, 0x7f),
+    (0xff, 0x00, 0x00),
+    (0x00, 0xff, 0x00),
+    (0xff, 0xff, 0x00),
+    (0x5c, 0x5c, 0xff),
+    (0xff, 0x00, 0xff),
+    (0x00, 0xff, 0xff),
+    (0xff, 0xff, 0xff),
+];
+
+/// Split the text into lines of styled spans according to the SGR sequences in it.
+///
+/// The colors that are not specified by the text are taken from the theme.
+/// Other escape sequences are removed.
+pub fn highlight_ansi<'a>(text: &'a str, theme: &Theme) -> HighlightedLines<'a> {
+    let default = Style {
+        foreground: theme.settings.foreground.unwrap_or(DEFAULT_FOREGROUND),
+        background: theme.settings.background.unwrap_or(DEFAULT_BACKGROUND),
+        font_style: FontStyle::empty(),
+    };
+    let mut style = default;
+
+    text.split_inclusive('\n')
+        .map(|line| {
+            let mut spans = vec![];
+            let mut rest = line;
+            while let Some(start) = rest.find('\x1b') {
+                if start > 0 {
+                    spans.push((style, &rest[..start]));
+                }
+                let (len, sgr) = parse_escape(&rest[start..]);
+                if let Some(params) = sgr {
+                    apply_sgr(&mut style, &default, params);
+                }
+                rest = &rest[start + len..];
+            }
+            if !rest.is_empty() {
+                spans.push((style, rest));
+            }
+            spans
+        })
+        .collect()
+}
+
+/// Remove the escape sequences from the text, keeping only the plain text.
+///
+/// ```
+/// use silicon::ansi::strip_ansi;
+///
+/// assert_eq!(strip_ansi("\x1b[1;31merror\x1b[0m: oops"), "error: oops");
+/// ```
+pub fn strip_ansi(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('\x1b') {
+        result.push_str(&rest[..start]);
+        let (len, _) = parse_escape(&rest[start..]);
+        rest = &rest[start + len..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Return the length of the escape sequence at the start of `s`,
+/// and the parameters of it if it's a SGR sequence
+fn parse_escape(s: &str) -> (usize, Option<&str>) {
+    let bytes = s.as_bytes();
+    match bytes.get(1) {
+        // CSI: ESC [ <params> <final byte>
+        Some(b'[') => match bytes[2..].iter().position(|b| (0x40..=0x7e).contains(b)) {
+            Some(end) => {
+                let params = &s[2..2 + end];
+                let sgr = if bytes[2 + end] == b'm' {
+                    Some(params)
+                } else {
+                    None
+                };
+                (end + 3, sgr)
+            }
+            None => (s.len(), None),
+        },
+        // OSC: ESC ] ... (BEL | ESC \)
+        Some(b']') => {
+            let end = match (s.find('\x07'), s.find("\x1b\\")) {
+                (Some(bel), Some(st)) if st < bel => st + 2,
+                (Some(bel), _) => bel + 1,
+                (None, Some(st)) => st + 2,
+                (None, None) => s.len(),
+            };
+            (end, None)
+        }
+        Some(_) => (1 + s[1..].chars().next().map_or(0, char::len_utf8), None),
+        None => (1, None),
+    }
+}
+
+fn apply_sgr(style: &mut Style, default: &Style, params: &str) {
+    // an empty parameter means 0, so `ESC [ m` is the same as `ESC [ 0 m`
+    let mut params = params.split(';').map(|p| p.parse::<u8>().unwrap_or(0));
+    while let Some(n) = params.next() {
+        match n {
+            0 => *style = *default,
+            1 => style.font_style |= FontStyle::BOLD,
+            3 => style.font_style |= FontStyle::ITALIC,
+            4 => style.font_style |= FontStyle::UNDERLINE,
+            22 => style.font_style.remove(FontStyle::BOLD),
+            23 => style.font_style.remove(FontStyle::ITALIC),
+            24 => style.font_style.remove(FontStyle::UNDERLINE),
+            30..=37 => style.foreground = color_256(n - 30),
+            38 => {
+                if let Some(color) = parse_extended_color(&mut params) {
+                    style.foreground = color;
+                }
+            }
+            39 => style.foreground = default.foreground,
+            40..=47 => style.background = color_256(n - 40),
+            48 => {
+                if let Some(color) = parse_extended_color(&mut params) {
+                    style.background = color;
+                }
+            }
+            49 => style.background = default.background,
+            90..=97 => style.foreground = color_256(n - 90 + 8),
+            100..=107 => style.background = color_256(n - 100 + 8),
+            _ => (),
+        }
+    }
+}
+
+/// Parse the rest of `38;5;n` or `38;2;r;g;b`
+fn parse_extended_color<I: Iterator<Item = u8>>(params: &mut I) -> Option<Color> {
+    match params.next()? {
+        5 => params.next().map(color_256),
+        2 => {
+            let (r, g, b) = (params.next()?, params.next()?, params.next()?);
+            Some(Color { r, g, b, a: 0xff })
+        }
+        _ => None,
+    }
+}
+
+/// Get the color in the xterm 256-color palette
+fn color_256(n: u8) -> Color {
+    let (r, g, b) = match n {
+        0..=15 => BASIC_COLORS[n as usize],
+        16..=231 => {
+            let level = |i: u8| if i == 0 { 0 } else { 55 + i * 40 };
+            let n = n - 16;
+            (level(n / 36), level(n / 6 % 6), level(n % 6))
+        }
+        _ => {
+            let gray = 8 + (n - 232) * 10;
+            (gray, gray, gray)
+        }
+    };
+    Color { r, g, b, a: 0xff }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sgr() {
+        let theme = Theme::default();
+        let lines = highlight_ansi(
+            "\x1b[1;31merror\x1b[0m: \x1b[Kok\n\x1b[3;38;2;1;2;3mx\x1b[38;5;196my\n",
+            &theme,
+        );
+
+        let red = color_256(1);
+        assert_eq!(lines.len(), 2);
+        let texts = lines[0].iter().map(|(_, text)| *text).collect::<Vec<_>>();
+        assert_eq!(texts, vec!["error", ": ", "ok\n"]);
+        assert_eq!(lines[0][0].0.foreground, red);
+        assert_eq!(lines[0][0].0.font_style, FontStyle::BOLD);
+        // the theme doesn't specify the colors, fall back to the ones of ImageFormatter
+        assert_eq!(lines[0][1].0.foreground, DEFAULT_FOREGROUND);
+        assert_eq!(lines[0][1].0.background, DEFAULT_BACKGROUND);
+        assert_eq!(lines[0][1].0.font_style, FontStyle::empty());
+
+        let (style, text) = lines[1][0];
+        assert_eq!(text, "x");
+        assert_eq!(
+            style.foreground,
+            Color {
+                r: 1,
+                g: 2,
+                b: 3,
+                a: 0xff
+            }
+        );
+        assert_eq!(style.font_style, FontStyle::ITALIC);
+        assert_eq!(
+            lines[1][1].0.foreground,
+            Color {
+                r: 0xff,
+                g: 0,
+                b: 0,
+                a: 0xff
+            }
+        );
+    }
+
+    #[test]
+    fn strip() {
+        // a line of `ls --color=always -l`
+        let line = "drwxr-xr-x 2 user user 4096 Jan  1 00:00 \x1b[0m\x1b[01;34msrc\x1b[0m\n";
+        assert_eq!(
+            strip_ansi(line),
+            "drwxr-xr-x 2 user user 4096 Jan  1 00:00 src\n"
+        );
+        assert_eq!(strip_ansi("\x1b[2K\x1b[1Gdone\x1b]0;title\x07"), "done");
+        assert_eq!(strip_ansi("no escapes"), "no escapes");
+    }
+}
@@ -1,10 +1,11 @@
+use std::collections::BTreeMap;
 use std::path::Path;
 
 use crate::directories::PROJECT_DIRS;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use syntect::dumps;
-use syntect::highlighting::ThemeSet;
-use syntect::parsing::SyntaxSet;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::{SyntaxDefinition, SyntaxSet};
 
 const DEFAULT_SYNTAXSET: &[u8] = include_bytes!("../assets/syntaxes.bin");
 const DEFAULT_THEMESET: &[u8] = include_bytes!("../assets/themes.bin");
@@ -28,11 +29,20 @@ impl HighlightingAssets {
         })
     }
 
+    /// Load the dumps from the cache directory of silicon, see `from_cache_dir`
     pub fn from_dump_file() -> Result<Self> {
-        let cache_dir = PROJECT_DIRS.cache_dir();
+        Self::from_cache_dir(PROJECT_DIRS.cache_dir())
+    }
+
+    /// Load `syntaxes.bin` and `themes.bin` dumped by `dump_to_file` from the directory
+    pub fn from_cache_dir<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let syntaxes = dir.as_ref().join("syntaxes.bin");
+        let themes = dir.as_ref().join("themes.bin");
         Ok(Self {
-            syntax_set: dumps::from_dump_file(cache_dir.join("syntaxes.bin"))?,
-            theme_set: dumps::from_dump_file(cache_dir.join("themes.bin"))?,
+            syntax_set: dumps::from_dump_file(&syntaxes)
+                .with_context(|| format!("Cannot load the syntaxes: {}", syntaxes.display()))?,
+            theme_set: dumps::from_dump_file(&themes)
+                .with_context(|| format!("Cannot load the themes: {}", themes.display()))?,
         })
     }
 
@@ -51,9 +61,174 @@ impl HighlightingAssets {
         Ok(())
     }
 
+    /// Add a syntax definition from a `.sublime-syntax` file
+    pub fn add_syntax_from_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        let name = path.file_stem().and_then(|s| s.to_str());
+        let syntax = SyntaxDefinition::load_from_str(&content, true, name)?;
+
+        let mut builder = self.syntax_set.clone().into_builder();
+        builder.add(syntax);
+        self.syntax_set = builder.build();
+        Ok(())
+    }
+
+    /// Add a theme from a `.tmTheme` file, it's named after the file name (without extension)
+    pub fn add_theme_from_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let theme = ThemeSet::get_theme(path)?;
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        self.add_theme(name, theme);
+        Ok(())
+    }
+
+    /// Add a theme loaded by other means, eg. `vscode::load_theme`, so that it can be
+    /// selected by name like the builtin ones. A theme of the same name is replaced.
+    pub fn add_theme<S: Into<String>>(&mut self, name: S, theme: Theme) {
+        self.theme_set.themes.insert(name.into(), theme);
+    }
+
+    /// Get the name and file extensions of each syntax, sorted by name.
+    ///
+    /// The syntaxes with the same name are merged.
+    pub fn languages(&self) -> Vec<(String, Vec<String>)> {
+        let mut languages = BTreeMap::<_, Vec<String>>::new();
+        for syntax in self.syntax_set.syntaxes() {
+            languages
+                .entry((syntax.name.to_lowercase(), syntax.name.clone()))
+                .or_default()
+                .extend(syntax.file_extensions.iter().cloned());
+        }
+        languages
+            .into_iter()
+            .map(|((_, name), mut extensions)| {
+                extensions.sort();
+                extensions.dedup();
+                (name, extensions)
+            })
+            .collect()
+    }
+
     pub fn dump_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         dumps::dump_to_file(&self.syntax_set, path.as_ref().join("syntaxes.bin"))?;
         dumps::dump_to_file(&self.theme_set, path.as_ref().join("themes.bin"))?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntect::parsing::SyntaxSetBuilder;
+
+    #[test]
+    fn cache_dir() {
+        let dir = std::env::temp_dir().join(format!("silicon-test-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // dumping the builtin assets is very slow in a debug build
+        let mut syntaxes = SyntaxSetBuilder::new();
+        syntaxes.add_plain_text_syntax();
+        let mut ha = HighlightingAssets {
+            syntax_set: syntaxes.build(),
+            theme_set: ThemeSet::new(),
+        };
+        ha.add_theme("Test", Theme::default());
+
+        dumps::dump_to_file(&ha.syntax_set, dir.join("syntaxes.bin")).unwrap();
+        let err = HighlightingAssets::from_cache_dir(&dir).err().unwrap();
+        assert!(err.to_string().contains("themes.bin"));
+
+        ha.dump_to_file(&dir).unwrap();
+        let loaded = HighlightingAssets::from_cache_dir(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(loaded.theme_set.themes.contains_key("Test"));
+        assert_eq!(loaded.theme_set.themes.len(), 1);
+        assert_eq!(loaded.syntax_set.syntaxes()[0].name, "Plain Text");
+        assert_eq!(loaded.syntax_set.syntaxes().len(), 1);
+    }
+
+    #[test]
+    fn add_from_file() {
+        let dir = std::env::temp_dir().join(format!("silicon-test-assets-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let syntax = dir.join("test.sublime-syntax");
+        std::fs::write(
+            &syntax,
+            "%YAML 1.2\n---\nname: Silicon Test\nfile_extensions: [silicontest]\n\
+             scope: source.silicontest\ncontexts:\n  main:\n    - match: 'x'\n      scope: keyword\n",
+        )
+        .unwrap();
+        let theme = dir.join("Sample.tmTheme");
+        std::fs::write(
+            &theme,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>settings</key>
+    <array>
+        <dict>
+            <key>settings</key>
+            <dict>
+                <key>background</key>
+                <string>#101010</string>
+            </dict>
+        </dict>
+    </array>
+</dict>
+</plist>"#,
+        )
+        .unwrap();
+
+        let mut ha = HighlightingAssets {
+            syntax_set: SyntaxSetBuilder::new().build(),
+            theme_set: ThemeSet::new(),
+        };
+        ha.add_syntax_from_file(&syntax).unwrap();
+        ha.add_theme_from_file(&theme).unwrap();
+        assert!(ha.add_theme_from_file(dir.join("missing.tmTheme")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let found = ha.syntax_set.find_syntax_by_token("silicontest").unwrap();
+        assert_eq!(found.name, "Silicon Test");
+        // named after the file
+        assert!(ha.theme_set.themes.contains_key("Sample"));
+    }
+
+    #[test]
+    fn languages() {
+        let syntax = |name: &str, extensions: &[&str]| {
+            let yaml = format!(
+                "name: {}\nfile_extensions: [{}]\nscope: source.test\n\
+                 contexts:\n  main: []\n",
+                name,
+                extensions.join(", ")
+            );
+            SyntaxDefinition::load_from_str(&yaml, true, None).unwrap()
+        };
+        let mut builder = SyntaxSetBuilder::new();
+        builder.add(syntax("beta", &["b"]));
+        builder.add(syntax("Alpha", &["z", "a"]));
+        builder.add(syntax("Alpha", &["a", "aa"]));
+        let ha = HighlightingAssets {
+            syntax_set: builder.build(),
+            theme_set: ThemeSet::new(),
+        };
+
+        // sorted case insensitively, the syntaxes of the same name are merged
+        assert_eq!(
+            ha.languages(),
+            vec![
+                (
+                    "Alpha".to_owned(),
+                    vec!["a".to_owned(), "aa".to_owned(), "z".to_owned()]
+                ),
+                ("beta".to_owned(), vec!["b".to_owned()]),
+            ]
+        );
+    }
+}
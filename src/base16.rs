@@ -0,0 +1,209 @@
+//! Convert base16 color schemes (`.yaml`) into syntect themes
+//!
+//! The colors are mapped to the scopes following the
+//! [styling guidelines](https://github.com/chriskempson/base16/blob/main/styling.md) of base16.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use silicon::base16::load_theme;
+//!
+//! let theme = load_theme("base16-tomorrow-night.yaml").unwrap();
+//! ```
+use crate::utils::ToRgba;
+use anyhow::{format_err, Context, Result};
+use std::path::Path;
+use std::str::FromStr;
+use syntect::highlighting::{
+    Color, FontStyle, ScopeSelectors, StyleModifier, Theme, ThemeItem, ThemeSettings,
+};
+use yaml_rust::{Yaml, YamlLoader};
+
+/// The scopes of each color, `base00`..`base07` are the shades from background to foreground
+const SCOPES: &[(usize, &str, Option<FontStyle>)] = &[
+    (
+        0x03,
+        "comment, punctuation.definition.comment",
+        Some(FontStyle::ITALIC),
+    ),
+    (0x05, "punctuation, keyword.operator", None),
+    (
+        0x08,
+        "variable, entity.name.tag, markup.deleted, markup.list",
+        None,
+    ),
+    (
+        0x09,
+        "constant, entity.other.attribute-name, markup.underline.link",
+        None,
+    ),
+    (
+        0x0A,
+        "entity.name.type, entity.name.class, support.type, support.class",
+        None,
+    ),
+    (0x0A, "markup.bold", Some(FontStyle::BOLD)),
+    (
+        0x0B,
+        "string, entity.other.inherited-class, markup.inserted, markup.raw",
+        None,
+    ),
+    (
+        0x0C,
+        "support.function, string.regexp, constant.character.escape, markup.quote",
+        None,
+    ),
+    (
+        0x0D,
+        "entity.name.function, meta.function-call, support.function.builtin, markup.heading",
+        None,
+    ),
+    (0x0E, "keyword, storage, markup.changed", None),
+    (0x0E, "markup.italic", Some(FontStyle::ITALIC)),
+    (
+        0x0F,
+        "invalid.deprecated, punctuation.section.embedded",
+        None,
+    ),
+];
+
+/// Load a base16 scheme from a `.yaml` file
+pub fn load_theme<P: AsRef<Path>>(path: P) -> Result<Theme> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Cannot read the theme: {}", path.display()))?;
+    let mut theme = theme_from_str(&content)
+        .with_context(|| format!("Cannot load the theme: {}", path.display()))?;
+    if theme.name.is_none() {
+        theme.name = path.file_stem().map(|s| s.to_string_lossy().into_owned());
+    }
+    Ok(theme)
+}
+
+/// Convert the content of a base16 scheme into a syntect theme
+///
+/// Both the classic format with `baseXX` at the top level and the newer one with them in
+/// `palette` are supported.
+pub fn theme_from_str(s: &str) -> Result<Theme> {
+    let yaml = YamlLoader::load_from_str(s)?
+        .into_iter()
+        .next()
+        .unwrap_or(Yaml::Null);
+    let palette = match &yaml["palette"] {
+        Yaml::Hash(_) => &yaml["palette"],
+        _ => &yaml,
+    };
+
+    let mut colors = [Color::BLACK; 16];
+    for (i, color) in colors.iter_mut().enumerate() {
+        let key = format!("base{:02X}", i);
+        let value = palette[key.as_str()]
+            .as_str()
+            .ok_or_else(|| format_err!("Missing color: {}", key))?;
+        *color =
+            to_color(value).ok_or_else(|| format_err!("Invalid color of {}: `{}`", key, value))?;
+    }
+
+    let settings = ThemeSettings {
+        foreground: Some(colors[0x05]),
+        background: Some(colors[0x00]),
+        caret: Some(colors[0x05]),
+        line_highlight: Some(colors[0x01]),
+        selection: Some(colors[0x02]),
+        gutter_foreground: Some(colors[0x03]),
+        ..Default::default()
+    };
+
+    let scopes = SCOPES
+        .iter()
+        .map(|&(i, scope, font_style)| ThemeItem {
+            scope: ScopeSelectors::from_str(scope).unwrap(),
+            style: StyleModifier {
+                foreground: Some(colors[i]),
+                background: None,
+                font_style,
+            },
+        })
+        .collect();
+
+    let name = yaml["scheme"].as_str().or_else(|| yaml["name"].as_str());
+    Ok(Theme {
+        name: name.map(str::to_owned),
+        author: yaml["author"].as_str().map(str::to_owned),
+        settings,
+        scopes,
+    })
+}
+
+/// The colors are hex strings, with or without the leading `#`
+fn to_color(s: &str) -> Option<Color> {
+    let s = s.trim_start_matches('#');
+    if s.len() != 6 {
+        return None;
+    }
+    format!("#{}", s).to_rgba().ok().map(|c| Color {
+        r: c.0[0],
+        g: c.0[1],
+        b: c.0[2],
+        a: c.0[3],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntect::highlighting::Highlighter;
+    use syntect::parsing::Scope;
+
+    const SCHEME: &str = r#"
+scheme: "Test"
+author: "Someone"
+base00: "1d1f21"
+base01: "282a2e"
+base02: "373b41"
+base03: "969896"
+base04: "b4b7b4"
+base05: "c5c8c6"
+base06: "e0e0e0"
+base07: "ffffff"
+base08: "cc6666"
+base09: "de935f"
+base0A: "f0c674"
+base0B: "b5bd68"
+base0C: "8abeb7"
+base0D: "81a2be"
+base0E: "b294bb"
+base0F: "a3685a"
+"#;
+
+    #[test]
+    fn convert_theme() {
+        let theme = theme_from_str(SCHEME).unwrap();
+        assert_eq!(theme.name.as_deref(), Some("Test"));
+        assert_eq!(theme.settings.background, to_color("1d1f21"));
+        assert_eq!(theme.settings.foreground, to_color("#c5c8c6"));
+
+        let highlighter = Highlighter::new(&theme);
+        let style = |scope| highlighter.style_for_stack(&[Scope::new(scope).unwrap()]);
+        assert_eq!(
+            Some(style("keyword.control").foreground),
+            to_color("b294bb")
+        );
+        assert_eq!(Some(style("string.quoted").foreground), to_color("b5bd68"));
+        assert_eq!(style("comment.line").font_style, FontStyle::ITALIC);
+    }
+
+    #[test]
+    fn palette() {
+        let scheme = SCHEME
+            .replace("scheme:", "name:")
+            .replace("\nbase", "\n  base")
+            .replace("author: \"Someone\"", "palette:");
+        let theme = theme_from_str(&scheme).unwrap();
+        assert_eq!(theme.name.as_deref(), Some("Test"));
+        assert_eq!(theme.settings.selection, to_color("373b41"));
+
+        assert!(theme_from_str("base00: \"000000\"").is_err());
+        assert!(theme_from_str(&SCHEME.replace("cc6666", "red")).is_err());
+    }
+}
@@ -1,21 +1,34 @@
 use anyhow::{Context, Error};
 use clipboard::{ClipboardContext, ClipboardProvider};
 use image::Rgba;
+use lazy_static::lazy_static;
+use silicon::ansi::strip_ansi;
+use silicon::assets::HighlightingAssets;
+use silicon::blur::BlurKind;
 use silicon::directories::PROJECT_DIRS;
-use silicon::font::FontCollection;
-use silicon::formatter::{ImageFormatter, ImageFormatterBuilder};
-use silicon::utils::{Background, ShadowAdder, ToRgba};
+use silicon::font::{FontCollection, FontRegistry, Hinting};
+use silicon::formatter::{
+    AnimationMode, ImageFormatter, ImageFormatterBuilder, LineNumberAlign, LineNumberMode,
+    LineNumberSide, TabStyle, TextDirection,
+};
+use silicon::utils::{Background, BackgroundFit, Corners, ShadowAdder, ToRgba};
 use std::ffi::OsString;
 use std::fs::File;
 use std::io::{stdin, Read};
 use std::num::ParseIntError;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 use structopt::clap::AppSettings::ColoredHelp;
 use structopt::StructOpt;
 use syntect::highlighting::{Theme, ThemeSet};
-use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::parsing::{Scope, SyntaxReference, SyntaxSet, SyntaxSetBuilder};
 
-pub fn config_file() -> PathBuf {
+/// Get the path of config file, `explicit` (given by `--config`) takes precedence
+/// over `SILICON_CONFIG_PATH` and the default one.
+pub fn config_file(explicit: Option<&Path>) -> PathBuf {
+    if let Some(path) = explicit {
+        return path.to_owned();
+    }
     std::env::var("SILICON_CONFIG_PATH")
         .ok()
         .map(PathBuf::from)
@@ -23,8 +36,25 @@ pub fn config_file() -> PathBuf {
         .unwrap_or_else(|| PROJECT_DIRS.config_dir().join("config"))
 }
 
-pub fn get_args_from_config_file() -> Vec<OsString> {
-    let args = std::fs::read_to_string(config_file())
+/// Find the value of `--config` in the command line arguments.
+///
+/// The config file must be read before the arguments are parsed, so it's scanned manually.
+pub fn find_config_arg(args: &[OsString]) -> Option<PathBuf> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            break;
+        } else if arg == "--config" {
+            return iter.next().map(PathBuf::from);
+        } else if let Some(path) = arg.to_str().and_then(|s| s.strip_prefix("--config=")) {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+pub fn get_args_from_config_file(path: &Path) -> Vec<OsString> {
+    let args = std::fs::read_to_string(path)
         .ok()
         .and_then(|content| {
             content
@@ -39,11 +69,314 @@ pub fn get_args_from_config_file() -> Vec<OsString> {
     args.iter().flatten().map(OsString::from).collect()
 }
 
+/// Replace `{theme}`, `{lang}` and `{date}` (the UTC time like `20240102-030405`) in the
+/// output path, eg. `out-{theme}.png`. A path without placeholders is kept as it is, even if
+/// it isn't valid UTF-8.
+pub fn expand_output_template(path: &Path, theme: &str, lang: &str, now: SystemTime) -> PathBuf {
+    let text = path.to_string_lossy();
+    if !text.contains('{') {
+        return path.to_path_buf();
+    }
+    let secs = now
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    // the names may contain a slash, eg. `Objective-C/C++`
+    text.replace("{theme}", &theme.replace('/', "-"))
+        .replace("{lang}", &lang.replace('/', "-"))
+        .replace("{date}", &format_date(secs))
+        .into()
+}
+
+/// Format the seconds since the UNIX epoch like `20240102-030405`
+fn format_date(secs: u64) -> String {
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let (era, doe) = (z / 146_097, z % 146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+lazy_static! {
+    /// The builtin syntax set doesn't contain the plain text syntax
+    static ref PLAIN_TEXT: SyntaxSet = {
+        let mut builder = SyntaxSetBuilder::new();
+        builder.add_plain_text_syntax();
+        builder.build()
+    };
+}
+
 fn parse_str_color(s: &str) -> Result<Rgba<u8>, Error> {
     s.to_rgba()
         .map_err(|_| format_err!("Invalid color: `{}`", s))
 }
 
+fn parse_background_fit(s: &str) -> Result<BackgroundFit, Error> {
+    match s {
+        "stretch" => Ok(BackgroundFit::Stretch),
+        "tile" => Ok(BackgroundFit::Tile),
+        "center" => Ok(BackgroundFit::Center),
+        "cover" => Ok(BackgroundFit::Cover),
+        _ => Err(format_err!("Invalid background image fit: `{}`", s)),
+    }
+}
+
+fn parse_color_or_transparent(s: &str) -> Result<Rgba<u8>, Error> {
+    match s {
+        "transparent" => Ok(Rgba([0, 0, 0, 0])),
+        _ => parse_str_color(s),
+    }
+}
+
+fn parse_quality(s: &str) -> Result<u8, Error> {
+    match s.parse::<u8>() {
+        Ok(quality) if (1..=100).contains(&quality) => Ok(quality),
+        _ => Err(format_err!("Invalid quality: `{}` (expected 1-100)", s)),
+    }
+}
+
+fn parse_softness(s: &str) -> Result<f32, Error> {
+    match s.parse::<f32>() {
+        Ok(softness) if (0.0..=1.0).contains(&softness) => Ok(softness),
+        _ => Err(format_err!(
+            "Invalid shadow softness: `{}` (expected 0-1)",
+            s
+        )),
+    }
+}
+
+fn parse_opacity(s: &str) -> Result<f32, Error> {
+    match s.parse::<f32>() {
+        Ok(opacity) if (0.0..=1.0).contains(&opacity) => Ok(opacity),
+        _ => Err(format_err!("Invalid opacity: `{}` (expected 0-1)", s)),
+    }
+}
+
+fn parse_scale(s: &str) -> Result<f32, Error> {
+    match s.parse::<f32>() {
+        Ok(scale) if scale > 0.0 && scale.is_finite() => Ok(scale),
+        _ => Err(format_err!(
+            "Invalid scale: `{}` (expected a positive number)",
+            s
+        )),
+    }
+}
+
+fn parse_font_size(s: &str) -> Result<f32, Error> {
+    match s.parse::<f32>() {
+        Ok(size) if size > 0.0 && size.is_finite() => Ok(size),
+        _ => Err(format_err!(
+            "Invalid font size: `{}` (expected a positive number)",
+            s
+        )),
+    }
+}
+
+fn parse_line_height(s: &str) -> Result<f32, Error> {
+    match s.parse::<f32>() {
+        Ok(height) if height >= 1.0 && height.is_finite() => Ok(height),
+        _ => Err(format_err!(
+            "Invalid line height: `{}` (expected a number not less than 1.0)",
+            s
+        )),
+    }
+}
+
+fn parse_corners(s: &str) -> Result<Corners, Error> {
+    let mut corners = Corners::NONE;
+    for corner in s.split(',') {
+        corners = corners
+            | match corner.trim() {
+                "tl" => Corners::TOP_LEFT,
+                "tr" => Corners::TOP_RIGHT,
+                "bl" => Corners::BOTTOM_LEFT,
+                "br" => Corners::BOTTOM_RIGHT,
+                "all" => Corners::ALL,
+                _ => return Err(format_err!("Invalid corner: `{}`", corner)),
+            };
+    }
+    Ok(corners)
+}
+
+fn parse_line_number_align(s: &str) -> Result<LineNumberAlign, Error> {
+    match s {
+        "left" => Ok(LineNumberAlign::Left),
+        "right" => Ok(LineNumberAlign::Right),
+        _ => Err(format_err!("Invalid alignment: `{}`", s)),
+    }
+}
+
+fn parse_line_number_side(s: &str) -> Result<LineNumberSide, Error> {
+    match s {
+        "left" => Ok(LineNumberSide::Left),
+        "right" => Ok(LineNumberSide::Right),
+        _ => Err(format_err!("Invalid side: `{}`", s)),
+    }
+}
+
+fn parse_text_direction(s: &str) -> Result<TextDirection, Error> {
+    match s {
+        "ltr" => Ok(TextDirection::Ltr),
+        "rtl" => Ok(TextDirection::Rtl),
+        "auto" => Ok(TextDirection::Auto),
+        _ => Err(format_err!("Invalid text direction: `{}`", s)),
+    }
+}
+
+fn parse_animation_mode(s: &str) -> Result<AnimationMode, Error> {
+    match s {
+        "typing" => Ok(AnimationMode::Typing),
+        "lines" => Ok(AnimationMode::Lines),
+        _ => Err(format_err!("Invalid animation mode: `{}`", s)),
+    }
+}
+
+fn parse_fps(s: &str) -> Result<u32, Error> {
+    match s.parse::<u32>() {
+        Ok(fps) if (1..=50).contains(&fps) => Ok(fps),
+        _ => Err(format_err!("Invalid fps: `{}` (expected 1-50)", s)),
+    }
+}
+
+fn parse_hinting(s: &str) -> Result<Hinting, Error> {
+    match s {
+        "none" => Ok(Hinting::None),
+        "vertical" => Ok(Hinting::Vertical),
+        "full" => Ok(Hinting::Full),
+        _ => Err(format_err!("Invalid hinting: `{}`", s)),
+    }
+}
+
+fn parse_blur_kind(s: &str) -> Result<BlurKind, Error> {
+    match s {
+        "box" => Ok(BlurKind::BoxApprox),
+        "gaussian" => Ok(BlurKind::TrueGaussian),
+        _ => Err(format_err!("Invalid blur kind: `{}`", s)),
+    }
+}
+
+fn parse_output_size(s: &str) -> Result<(u32, u32), Error> {
+    let size = s
+        .split_once(['x', 'X'])
+        .and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)));
+    match size {
+        Some((w, h)) if w > 0 && h > 0 => Ok((w, h)),
+        _ => Err(format_err!("Invalid size: `{}` (expected WIDTHxHEIGHT)", s)),
+    }
+}
+
+fn parse_tab_style(s: &str) -> Result<TabStyle, Error> {
+    match s {
+        "spaces" => Ok(TabStyle::Spaces),
+        "arrow" => Ok(TabStyle::Arrow),
+        "dot" => Ok(TabStyle::Dot),
+        _ => Err(format_err!("Invalid tab style: `{}`", s)),
+    }
+}
+
+/// The background around the code, which goes with the theme.
+/// `#aaaaff` if the theme doesn't specify its background.
+fn theme_padding_color(theme: &Theme) -> Rgba<u8> {
+    let bg = match theme.settings.background {
+        Some(bg) => bg,
+        None => return Rgba([0xaa, 0xaa, 0xff, 0xff]),
+    };
+    let luma = (299 * u32::from(bg.r) + 587 * u32::from(bg.g) + 114 * u32::from(bg.b)) / 1000;
+    // move 30% toward white for the dark themes, 15% toward black for the light ones
+    let shift = |c: u8| -> u8 {
+        let c = u32::from(c);
+        if luma < 0x80 {
+            (c + (0xff - c) * 30 / 100) as u8
+        } else {
+            (c - c * 15 / 100) as u8
+        }
+    };
+    Rgba([shift(bg.r), shift(bg.g), shift(bg.b), 0xff])
+}
+
+/// Whether `--theme auto` should pick a light theme.
+///
+/// `variant` is the value of `SILICON_THEME_VARIANT` (`light` or `dark`), which takes precedence.
+/// `colorfgbg` is the value of `COLORFGBG` (eg. `0;15`), whose last field is the background color.
+/// Dark is assumed if neither of them gives a hint.
+fn prefers_light_theme(variant: Option<&str>, colorfgbg: Option<&str>) -> bool {
+    match variant.map(str::trim) {
+        Some(v) if v.eq_ignore_ascii_case("light") => return true,
+        Some(v) if v.eq_ignore_ascii_case("dark") => return false,
+        _ => (),
+    }
+    // 7 (white) and 15 (bright white) are the light backgrounds
+    colorfgbg
+        .and_then(|s| s.rsplit(';').next())
+        .is_some_and(|bg| matches!(bg.trim(), "7" | "15"))
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = if ca == cb {
+                prev
+            } else {
+                1 + prev.min(cur).min(row[j])
+            };
+            prev = cur;
+        }
+    }
+    row[b.len()]
+}
+
+/// Find at most three theme names similar to `name`, quoted and sorted by similarity
+fn suggest_themes(name: &str, ts: &ThemeSet) -> Vec<String> {
+    let name = name.to_lowercase();
+    let max_distance = (name.chars().count() / 3).max(2);
+    let mut candidates = ts
+        .themes
+        .keys()
+        .map(|theme| (edit_distance(&name, &theme.to_lowercase()), theme))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .collect::<Vec<_>>();
+    candidates.sort();
+    candidates
+        .into_iter()
+        .take(3)
+        .map(|(_, theme)| format!("'{}'", theme))
+        .collect()
+}
+
+/// Find the syntax by a file extension or name (`find_syntax_by_token`), a full scope
+/// (eg. `source.rust`) or the exact name
+fn find_syntax<'a>(ps: &'a SyntaxSet, language: &str) -> Result<&'a SyntaxReference, Error> {
+    ps.find_syntax_by_token(language)
+        .or_else(|| ps.find_syntax_by_scope(Scope::new(language).ok()?))
+        .or_else(|| ps.find_syntax_by_name(language))
+        .ok_or_else(|| {
+            format_err!(
+                "Unsupported language: {} (not an extension, a name or a scope of any syntax)",
+                language
+            )
+        })
+}
+
 fn parse_font_str(s: &str) -> Vec<(String, f32)> {
     let mut result = vec![];
     for font in s.split(';') {
@@ -58,67 +391,327 @@ fn parse_font_str(s: &str) -> Vec<(String, f32)> {
     result
 }
 
-fn parse_line_range(s: &str) -> Result<Vec<u32>, ParseIntError> {
+fn parse_font_features(s: &str) -> Vec<String> {
+    s.split(';')
+        .map(str::trim)
+        .filter(|feature| !feature.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Parse the line ranges like `1-3;5;7-`, an open end means the start / end of the code
+fn parse_line_range(s: &str) -> Result<Lines, ParseIntError> {
     let mut result = vec![];
     for range in s.split(';') {
-        let range: Vec<u32> = range
-            .split('-')
-            .map(|s| s.parse::<u32>())
-            .collect::<Result<Vec<_>, _>>()?;
-        if range.len() == 1 {
-            result.push(range[0])
-        } else {
-            for i in range[0]..=range[1] {
-                result.push(i);
+        match range.split_once('-') {
+            Some((start, end)) => {
+                let start = if start.is_empty() { 1 } else { start.parse()? };
+                let end = if end.is_empty() {
+                    u32::MAX
+                } else {
+                    end.parse()?
+                };
+                result.push((start, end));
+            }
+            None => {
+                let line = range.parse()?;
+                result.push((line, line));
             }
         }
     }
     Ok(result)
 }
 
+/// Parse the mapping like `h:C++`, the leading dot of the extension is optional
+fn parse_extension_mapping(s: &str) -> Result<(String, String), Error> {
+    match s.split_once(':') {
+        Some((ext, language)) if !ext.is_empty() && !language.is_empty() => {
+            Ok((ext.trim_start_matches('.').to_owned(), language.to_owned()))
+        }
+        _ => Err(format_err!(
+            "Invalid extension mapping: `{}` (expected EXT:LANGUAGE)",
+            s
+        )),
+    }
+}
+
+fn parse_accent(s: &str) -> Result<(String, Rgba<u8>), Error> {
+    match s.rsplit_once('=') {
+        Some((text, color)) if !text.is_empty() => Ok((text.to_owned(), parse_str_color(color)?)),
+        _ => Err(format_err!("Invalid accent: `{}` (expected TEXT=COLOR)", s)),
+    }
+}
+
+fn parse_highlight_group(s: &str) -> Result<(Lines, Rgba<u8>), Error> {
+    let err = || format_err!("Invalid highlight group: `{}` (expected LINES:COLOR)", s);
+    let (lines, color) = s.rsplit_once(':').ok_or_else(err)?;
+    Ok((
+        parse_line_range(lines).map_err(|_| err())?,
+        parse_str_color(color)?,
+    ))
+}
+
+fn parse_column_ranges(s: &str) -> Result<Vec<(u32, u32, u32)>, Error> {
+    let mut result = vec![];
+    for range in s.split(';') {
+        let err = || format_err!("Invalid range: `{}` (expected LINE:START-END)", range);
+        let (line, cols) = range.trim().split_once(':').ok_or_else(err)?;
+        let (start, end) = cols.split_once('-').unwrap_or((cols, cols));
+        result.push((
+            line.parse().map_err(|_| err())?,
+            start.parse().map_err(|_| err())?,
+            end.parse().map_err(|_| err())?,
+        ));
+    }
+    Ok(result)
+}
+
+fn parse_selection(s: &str) -> Result<(u32, u32, u32, u32), Error> {
+    let err = || format_err!("Invalid selection: `{}` (expected LINE:COL-LINE:COL)", s);
+    let position = |s: &str| -> Result<(u32, u32), Error> {
+        let (line, col) = s.trim().split_once(':').ok_or_else(err)?;
+        match (line.parse(), col.parse()) {
+            (Ok(line), Ok(col)) if line > 0 && col > 0 => Ok((line, col)),
+            _ => Err(err()),
+        }
+    };
+    let (start, end) = s.split_once('-').ok_or_else(err)?;
+    let (start, end) = (position(start)?, position(end)?);
+    if end < start {
+        return Err(err());
+    }
+    Ok((start.0, start.1, end.0, end.1))
+}
+
 // https://github.com/TeXitoi/structopt/blob/master/CHANGELOG.md#support-optional-vectors-of-arguments-for-distinguishing-between--o-1-2--o-and-no-option-provided-at-all-by-sphynx-180
 type FontList = Vec<(String, f32)>;
-type Lines = Vec<u32>;
+type FontFeatures = Vec<String>;
+/// Inclusive ranges of lines
+type Lines = Vec<(u32, u32)>;
+type ColumnRanges = Vec<(u32, u32, u32)>;
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "silicon")]
 #[structopt(global_setting(ColoredHelp))]
 pub struct Config {
+    /// Write a GIF animation which reveals the code progressively: 'typing' or 'lines'
+    #[structopt(
+        long,
+        value_name = "MODE",
+        conflicts_with_all = &["layers", "to-clipboard"],
+        parse(try_from_str = parse_animation_mode)
+    )]
+    pub animate: Option<AnimationMode>,
+
     /// Background image
     #[structopt(long, value_name = "IMAGE", conflicts_with = "background")]
     pub background_image: Option<PathBuf>,
 
-    /// Background color of the image
+    /// How the background image fits the image: 'stretch', 'tile', 'center' or 'cover'
+    #[structopt(
+        long,
+        value_name = "FIT",
+        default_value = "stretch",
+        parse(try_from_str = parse_background_fit)
+    )]
+    pub background_image_fit: BackgroundFit,
+
+    /// How much of `--background-image` is kept (0-1), the rest is blended toward
+    /// `--background-image-tint` so the code stands out.
+    #[structopt(
+        long,
+        value_name = "OPACITY",
+        default_value = "1",
+        parse(try_from_str = parse_opacity)
+    )]
+    pub background_image_opacity: f32,
+
+    /// The color which the background image is blended toward by `--background-image-opacity`.
     #[structopt(
         long,
-        short,
         value_name = "COLOR",
-        default_value = "#aaaaff",
+        default_value = "#000",
         parse(try_from_str = parse_str_color)
     )]
-    pub background: Rgba<u8>,
+    pub background_image_tint: Rgba<u8>,
+
+    /// Background color of the image, or 'transparent'.
+    /// Defaults to a lighter shade of the background of theme (darker for the light themes).
+    #[structopt(
+        long,
+        short,
+        value_name = "COLOR",
+        parse(try_from_str = parse_color_or_transparent)
+    )]
+    pub background: Option<Rgba<u8>>,
+
+    /// Background color of the code area, or 'transparent'. Defaults to the background of theme.
+    #[structopt(long, value_name = "COLOR", parse(try_from_str = parse_color_or_transparent))]
+    pub code_background: Option<Rgba<u8>>,
+
+    /// Fill the code area with the `--background` color instead of the background of theme.
+    #[structopt(long, conflicts_with_all = &["code-background", "background-image"])]
+    pub use_background_for_code: bool,
 
     /// Show the path of silicon config file
     #[structopt(long)]
     pub config_file: bool,
 
+    /// Print the layout metrics of the image to stderr, without rendering or saving it.
+    #[structopt(long)]
+    pub dry_run: bool,
+
+    /// Read the arguments from this config file, instead of `SILICON_CONFIG_PATH` or the default one.
+    #[structopt(long, value_name = "PATH", parse(from_os_str))]
+    pub config: Option<PathBuf>,
+
     /// Read input from clipboard.
     #[structopt(long)]
     pub from_clipboard: bool,
 
     /// File to read. If not set, stdin will be use.
+    /// Multiple files will be stacked vertically into one image.
     #[structopt(value_name = "FILE", parse(from_os_str))]
-    pub file: Option<PathBuf>,
+    pub file: Vec<PathBuf>,
+
+    /// The frame rate of the animation given by `--animate`
+    #[structopt(
+        long,
+        value_name = "FPS",
+        default_value = "20",
+        parse(try_from_str = parse_fps)
+    )]
+    pub fps: u32,
+
+    /// The maximum number of frames of the animation given by `--animate`.
+    /// Above it, each frame reveals several characters or lines.
+    #[structopt(long, value_name = "N", default_value = "200")]
+    pub max_frames: usize,
 
-    /// The fallback font list. eg. 'Hack; SimSun=31'
+    /// The fallback font list, tried in the listed order for every character. eg. 'Hack; SimSun=31'
+    ///
+    /// A weight can be given after the name, eg. 'JetBrains Mono:300=28'. Each font is drawn
+    /// at its own size, so a fallback font can be sized to match the x-height of the first one.
     #[structopt(long, short, value_name = "FONT", parse(from_str = parse_font_str))]
     pub font: Option<FontList>,
 
-    /// Lines to highlight. eg. '1-3;4'
-    #[structopt(long, value_name = "LINES", parse(try_from_str = parse_line_range))]
+    /// Load fonts from the directory, they take precedence over the system fonts.
+    #[structopt(long, value_name = "DIR", parse(from_os_str), number_of_values = 1)]
+    pub font_dir: Vec<PathBuf>,
+
+    /// Use the Hack installed in the system instead of the bundled one, if there is one.
+    #[structopt(long)]
+    pub prefer_system_fonts: bool,
+
+    /// Fail if the font isn't monospaced, instead of printing a warning.
+    #[structopt(long)]
+    pub strict_monospace: bool,
+
+    /// Fit the glyphs to the pixel grid to sharpen small text: 'none', 'vertical' or 'full'
+    #[structopt(
+        long,
+        value_name = "HINTING",
+        default_value = "none",
+        parse(try_from_str = parse_hinting)
+    )]
+    pub hinting: Hinting,
+
+    /// Font features applied by harfbuzz, eg. 'calt=0;liga=0' to disable the ligatures,
+    /// or 'ss01' to enable a stylistic set.
+    #[structopt(long, value_name = "FEATURES", parse(from_str = parse_font_features))]
+    pub font_features: Option<FontFeatures>,
+
+    /// Slant and thicken the regular glyphs for the italic and bold text,
+    /// if the font doesn't have such faces.
+    #[structopt(long)]
+    pub synthesize_styles: bool,
+
+    /// Lines to highlight. eg. '1-3;4', '5-' means from line 5 to the end.
+    ///
+    /// The numbers are the positions of the rendered lines, starting from 1 regardless of
+    /// `--line-offset`. See `--highlight-lines-absolute`.
+    #[structopt(
+        long,
+        value_name = "LINES",
+        allow_hyphen_values = true,
+        parse(try_from_str = parse_line_range)
+    )]
     pub highlight_lines: Option<Lines>,
 
-    /// The language for syntax highlighting. You can use full name ("Rust") or file extension ("rs").
+    /// Read more lines to highlight from a file, one line or range per line in the syntax
+    /// of `--highlight-lines`. Empty lines are ignored.
+    #[structopt(long, value_name = "PATH", parse(from_os_str))]
+    pub highlight_lines_file: Option<PathBuf>,
+
+    /// Match `--highlight-lines` against the line numbers shown in the gutter,
+    /// eg. '--line-offset 100 --highlight-lines 102' highlights the third line.
+    #[structopt(long)]
+    pub highlight_lines_absolute: bool,
+
+    /// Lines to focus on, the other lines will be faded. eg. '1-3;4', '-5' means the first 5 lines.
+    #[structopt(
+        long,
+        value_name = "LINES",
+        allow_hyphen_values = true,
+        parse(try_from_str = parse_line_range)
+    )]
+    pub focus_lines: Option<Lines>,
+
+    /// Line of the cursor, highlighted more strongly than --highlight-lines.
+    #[structopt(long, value_name = "LINE")]
+    pub cursor_line: Option<u32>,
+
+    /// Color of the cursor line. Defaults to the foreground of theme.
+    #[structopt(long, value_name = "COLOR", parse(try_from_str = parse_str_color))]
+    pub cursor_line_color: Option<Rgba<u8>>,
+
+    /// Color of the highlighted lines, blended over the background (e.g. '#ffffff30').
+    /// Derived from the background if not set.
+    #[structopt(long, value_name = "COLOR", parse(try_from_str = parse_str_color))]
+    pub highlight_color: Option<Rgba<u8>>,
+
+    /// Lines to highlight with their own color, eg. '1-3:#ff000033'. Can be given multiple
+    /// times, the colors of overlapping groups are blended.
+    #[structopt(
+        long,
+        value_name = "LINES:COLOR",
+        number_of_values = 1,
+        allow_hyphen_values = true,
+        parse(try_from_str = parse_highlight_group)
+    )]
+    pub highlight_group: Vec<(Lines, Rgba<u8>)>,
+
+    /// Column ranges to highlight. eg. '7:10-15;8:3'
+    #[structopt(long, value_name = "RANGES", parse(try_from_str = parse_column_ranges))]
+    pub highlight_ranges: Option<ColumnRanges>,
+
+    /// Recolor the tokens which are exactly the text, eg. 'main=#ff0000'. Can be given multiple times.
+    #[structopt(
+        long,
+        value_name = "TEXT=COLOR",
+        number_of_values = 1,
+        parse(try_from_str = parse_accent)
+    )]
+    pub accent: Vec<(String, Rgba<u8>)>,
+
+    /// Draw a box behind every occurrence of the text, like a search. Can be given multiple times.
+    #[structopt(long, value_name = "TEXT", number_of_values = 1)]
+    pub mark: Vec<String>,
+
+    /// Color of the boxes of `--mark`. Defaults to the find highlight color of theme.
+    #[structopt(long, value_name = "COLOR", parse(try_from_str = parse_str_color))]
+    pub mark_color: Option<Rgba<u8>>,
+
+    /// Draw a text selection from LINE:COL to LINE:COL (inclusive), eg. '3:5-7:12'.
+    #[structopt(long, value_name = "RANGE", parse(try_from_str = parse_selection))]
+    pub selection: Option<(u32, u32, u32, u32)>,
+
+    /// Color of `--selection`. Defaults to the selection color of theme.
+    #[structopt(long, value_name = "COLOR", parse(try_from_str = parse_str_color))]
+    pub selection_color: Option<Rgba<u8>>,
+
+    /// The language for syntax highlighting. You can use full name ("Rust"), file extension ("rs")
+    /// or scope ("source.rust").
     #[structopt(short, value_name = "LANG", long)]
     pub language: Option<String>,
 
@@ -126,31 +719,89 @@ pub struct Config {
     #[structopt(long, value_name = "PAD", default_value = "2")]
     pub line_pad: u32,
 
+    /// Height of lines as a multiple of the font height, eg. 1.4. Overrides `--line-pad`.
+    #[structopt(long, value_name = "MULTIPLIER", parse(try_from_str = parse_line_height))]
+    pub line_height: Option<f32>,
+
     /// Add PAD padding to the right of the code.
     #[structopt(long, value_name = "PAD", default_value = "25")]
     pub code_pad_right: u32,
 
-    /// Line number offset
-    #[structopt(long, value_name = "OFFSET", default_value = "1")]
+    /// Fit the width to the visible code, ignoring the trailing whitespace and the minimum width.
+    #[structopt(long)]
+    pub crop_to_content: bool,
+
+    /// Number of the first line, eg. 0 for a snippet starting at line 0.
+    #[structopt(
+        long,
+        visible_alias = "line-number-start",
+        value_name = "N",
+        default_value = "1"
+    )]
     pub line_offset: u32,
 
+    /// Only show every Nth line number, besides the first and the last one, eg. 5.
+    #[structopt(long, value_name = "N", default_value = "1")]
+    pub line_number_step: u32,
+
+    /// Reserve the gutter for at least N digits, even with --no-line-number,
+    /// so that the code lines up across a set of images.
+    #[structopt(long, value_name = "N")]
+    pub line_number_chars: Option<u32>,
+
+    /// Also write the panel and the shadow as separate PNGs (`<OUTPUT>.panel.png`, `<OUTPUT>.shadow.png`)
+    #[structopt(long, conflicts_with = "to-clipboard")]
+    pub layers: bool,
+
     /// List all themes.
     #[structopt(long)]
     pub list_themes: bool,
 
+    /// With `--list-themes`, also print the background and foreground colors of each theme.
+    #[structopt(long, requires = "list-themes")]
+    pub detailed: bool,
+
+    /// List all supported languages and their file extensions.
+    #[structopt(long)]
+    pub list_languages: bool,
+
     /// List all available fonts in your system
     #[structopt(long)]
     pub list_fonts: bool,
 
-    /// Write output image to specific location instead of cwd.
+    /// With `--list-fonts`, also print which of the regular, bold, italic and bold italic faces
+    /// each font has. The missing ones are drawn with the regular face.
+    #[structopt(long, requires = "list-fonts")]
+    pub styles: bool,
+
+    /// Write output image to specific location instead of cwd. Use `-` to write PNG to stdout.
+    ///
+    /// `{theme}`, `{lang}` and `{date}` in the path are replaced with the name of theme,
+    /// the language and the current time, eg. 'out-{theme}.png'.
     #[structopt(
         short,
         long,
         value_name = "PATH",
-        required_unless_one = &["config-file", "list-fonts", "list-themes", "to-clipboard", "build-cache"]
+        required_unless_one = &["config-file", "list-fonts", "list-themes", "list-languages", "to-clipboard", "build-cache", "dry-run"]
     )]
     pub output: Option<PathBuf>,
 
+    /// Put the image at the center of a canvas of exactly this size, eg. '1280x720'.
+    /// The image is scaled down if it doesn't fit.
+    #[structopt(
+        long,
+        visible_alias = "output-dimensions",
+        value_name = "WxH",
+        conflicts_with = "layers",
+        parse(try_from_str = parse_output_size)
+    )]
+    pub output_size: Option<(u32, u32)>,
+
+    /// Refuse to render an image larger than BYTES (4 bytes per pixel), eg. of a minified file.
+    /// 0 means no limit.
+    #[structopt(long, value_name = "BYTES", default_value = "268435456")]
+    pub max_image_bytes: u64,
+
     /// Hide the window controls.
     #[structopt(long)]
     pub no_window_controls: bool,
@@ -159,14 +810,73 @@ pub struct Config {
     #[structopt(long, value_name = "WINDOW_TITLE")]
     pub window_title: Option<String>,
 
+    /// Show a caption in a bar below the code, eg. a file path or attribution
+    #[structopt(long, value_name = "CAPTION")]
+    pub caption: Option<String>,
+
+    /// Color of the window title. Defaults to the foreground of theme.
+    #[structopt(long, value_name = "COLOR", parse(try_from_str = parse_str_color))]
+    pub window_title_color: Option<Rgba<u8>>,
+
+    /// Font size of the window title. Defaults to the size of the first font.
+    #[structopt(long, value_name = "SIZE", parse(try_from_str = parse_font_size))]
+    pub window_title_size: Option<f32>,
+
+    /// Gap between the window controls and the window title.
+    #[structopt(long, value_name = "PAD", default_value = "15")]
+    pub title_controls_gap: u32,
+
     /// Hide the line number.
     #[structopt(long)]
     pub no_line_number: bool,
 
+    /// Color of the line number. Derived from the theme foreground if not set.
+    #[structopt(long, value_name = "COLOR", parse(try_from_str = parse_str_color))]
+    pub line_number_color: Option<Rgba<u8>>,
+
+    /// Draw a vertical line between the line numbers and the code.
+    #[structopt(long)]
+    pub line_number_separator: bool,
+
+    /// Draw a vertical line at each indentation level.
+    #[structopt(long)]
+    pub indent_guides: bool,
+
+    /// Show line numbers relative to the first highlighted line (or the middle line).
+    #[structopt(long)]
+    pub relative_line_numbers: bool,
+
+    /// Alignment of the line numbers: 'left' or 'right'
+    #[structopt(
+        long,
+        value_name = "ALIGN",
+        default_value = "right",
+        parse(try_from_str = parse_line_number_align)
+    )]
+    pub line_number_align: LineNumberAlign,
+
+    /// Which side of the code the line numbers are on: 'left' or 'right'
+    #[structopt(
+        long,
+        value_name = "SIDE",
+        default_value = "left",
+        parse(try_from_str = parse_line_number_side)
+    )]
+    pub line_number_side: LineNumberSide,
+
     /// Don't round the corner
     #[structopt(long)]
     pub no_round_corner: bool,
 
+    /// Corners to round, any of 'tl', 'tr', 'bl', 'br' or 'all'. eg. 'tl,tr'
+    #[structopt(
+        long,
+        value_name = "CORNERS",
+        default_value = "all",
+        parse(try_from_str = parse_corners)
+    )]
+    pub round_corners: Corners,
+
     /// Pad horiz
     #[structopt(long, value_name = "PAD", default_value = "80")]
     pub pad_horiz: u32,
@@ -175,35 +885,186 @@ pub struct Config {
     #[structopt(long, value_name = "PAD", default_value = "100")]
     pub pad_vert: u32,
 
-    /// Color of shadow
+    /// Padding above the code, overriding `--pad-vert`.
+    #[structopt(long, value_name = "PAD")]
+    pub pad_top: Option<u32>,
+
+    /// Padding below the code, overriding `--pad-vert`.
+    #[structopt(long, value_name = "PAD")]
+    pub pad_bottom: Option<u32>,
+
+    /// Padding on the left of the code, overriding `--pad-horiz`.
+    #[structopt(long, value_name = "PAD")]
+    pub pad_left: Option<u32>,
+
+    /// Padding on the right of the code, overriding `--pad-horiz`.
+    #[structopt(long, value_name = "PAD")]
+    pub pad_right: Option<u32>,
+
+    /// Quality of JPEG/WebP output (1-100). Ignored for other formats.
     #[structopt(
         long,
-        value_name = "COLOR",
-        default_value = "#555555",
-        parse(try_from_str = parse_str_color)
+        value_name = "QUALITY",
+        default_value = "90",
+        parse(try_from_str = parse_quality)
     )]
-    pub shadow_color: Rgba<u8>,
+    pub quality: u8,
+
+    /// Don't write the version of silicon, the theme and the fonts into the PNG images.
+    #[structopt(long)]
+    pub no_metadata: bool,
+
+    /// Color of shadow. If not set, a color contrasting with the background will be used.
+    #[structopt(long, value_name = "COLOR", parse(try_from_str = parse_str_color))]
+    pub shadow_color: Option<Rgba<u8>>,
 
-    /// Blur radius of the shadow. (set it to 0 to hide shadow)
+    /// Don't draw the shadow, the background and paddings are kept.
+    #[structopt(long)]
+    pub no_shadow: bool,
+
+    /// Don't add the background and paddings around the window, implies --no-shadow.
+    #[structopt(long, conflicts_with_all = &["background-image", "layers"])]
+    pub no_background: bool,
+
+    /// Blur radius of the shadow, 0 draws a sharp shadow. There is no shadow if neither the
+    /// radius nor the offsets are set, as it would be hidden behind the window.
     #[structopt(long, value_name = "R", default_value = "0")]
     pub shadow_blur_radius: f32,
 
+    /// Number of box blur passes for the shadow. Fewer passes are faster but less smooth.
+    #[structopt(long, value_name = "N", default_value = "3")]
+    pub shadow_blur_passes: usize,
+
+    /// How to blur the shadow, `box` (approximate but fast) or `gaussian` (exact but slower
+    /// with a big radius). --shadow-blur-passes only applies to `box`.
+    #[structopt(
+        long,
+        value_name = "KIND",
+        default_value = "box",
+        parse(try_from_str = parse_blur_kind)
+    )]
+    pub shadow_blur_kind: BlurKind,
+
+    /// How much the shadow fades out radially from its center before blurring, from 0 to 1.
+    /// Higher values give a softer penumbra.
+    #[structopt(
+        long,
+        value_name = "S",
+        default_value = "0",
+        parse(try_from_str = parse_softness)
+    )]
+    pub shadow_softness: f32,
+
     /// Shadow's offset in Y axis
-    #[structopt(long, value_name = "Y", default_value = "0")]
+    #[structopt(
+        long,
+        value_name = "Y",
+        default_value = "0",
+        allow_hyphen_values = true
+    )]
     pub shadow_offset_y: i32,
 
     /// Shadow's offset in X axis
-    #[structopt(long, value_name = "X", default_value = "0")]
+    #[structopt(
+        long,
+        value_name = "X",
+        default_value = "0",
+        allow_hyphen_values = true
+    )]
     pub shadow_offset_x: i32,
 
+    /// Scale the font sizes, paddings and radii of the image, eg. 2 for high DPI screens.
+    #[structopt(
+        long,
+        value_name = "FACTOR",
+        default_value = "1",
+        parse(try_from_str = parse_scale)
+    )]
+    pub scale: f32,
+
+    /// Also write an image at twice the scale, named like `main@2x.png`.
+    /// The image at the normal scale is downsampled from it.
+    #[structopt(long, conflicts_with_all = &["animate", "layers", "to-clipboard"])]
+    pub retina: bool,
+
+    /// Remove the blank lines at the end of the code.
+    #[structopt(long)]
+    pub trim_trailing_blank_lines: bool,
+
+    /// Render the colors of ANSI escape sequences in the input instead of highlighting it.
+    #[structopt(long)]
+    pub ansi: bool,
+
+    /// Don't highlight the code, draw all of it with the foreground of theme.
+    /// The language isn't detected, so any file can be rendered.
+    #[structopt(long, conflicts_with = "ansi")]
+    pub plain: bool,
+
+    /// Remove the ANSI escape sequences from the input, and highlight it as usual.
+    #[structopt(long, conflicts_with = "ansi")]
+    pub strip_ansi: bool,
+
+    /// Show non-printable control characters as Unicode control pictures (eg. ␀, ␛)
+    #[structopt(long)]
+    pub show_control_chars: bool,
+
     /// Tab width
     #[structopt(long, value_name = "WIDTH", default_value = "4")]
     pub tab_width: u8,
 
-    /// The syntax highlight theme. It can be a theme name or path to a .tmTheme file.
+    /// How to render tabs: 'spaces', 'arrow' (→) or 'dot' (·)
+    #[structopt(
+        long,
+        value_name = "STYLE",
+        default_value = "spaces",
+        parse(try_from_str = parse_tab_style)
+    )]
+    pub tab_style: TabStyle,
+
+    /// Base direction of the lines: 'ltr', 'rtl' or 'auto' (detected by the first letter of each line).
+    /// Right-to-left lines are right aligned.
+    #[structopt(
+        long,
+        value_name = "DIRECTION",
+        default_value = "ltr",
+        parse(try_from_str = parse_text_direction)
+    )]
+    pub text_direction: TextDirection,
+
+    /// The name of the syntax highlight theme. See `--list-themes`.
+    ///
+    /// 'auto' picks a light or dark theme according to `SILICON_THEME_VARIANT` (light|dark)
+    /// or `COLORFGBG`, and defaults to dark.
     #[structopt(long, value_name = "THEME", default_value = "Dracula")]
     pub theme: String,
 
+    /// Load the syntax highlight theme from a .tmTheme file and use it instead of `--theme`.
+    /// base16 color schemes (.yaml) are also accepted.
+    #[structopt(long, value_name = "FILE", parse(from_os_str))]
+    pub theme_file: Option<PathBuf>,
+
+    /// Load an extra syntax definition (.sublime-syntax). Can be given multiple times.
+    #[structopt(long, value_name = "FILE", parse(from_os_str), number_of_values = 1)]
+    pub extra_syntax: Vec<PathBuf>,
+
+    /// Use the given language for the files with the extension, eg. 'h:C++'. Can be given multiple times.
+    #[structopt(
+        long,
+        value_name = "EXT:LANGUAGE",
+        number_of_values = 1,
+        parse(try_from_str = parse_extension_mapping)
+    )]
+    pub map_extension: Vec<(String, String)>,
+
+    /// Load an extra theme (.tmTheme), named after the file name. Can be given multiple times.
+    #[structopt(long, value_name = "FILE", parse(from_os_str), number_of_values = 1)]
+    pub extra_theme: Vec<PathBuf>,
+
+    /// Load a VS Code theme (.json) and use it instead of `--theme`.
+    #[cfg(feature = "vscode")]
+    #[structopt(long, value_name = "FILE", parse(from_os_str))]
+    pub theme_from_vscode: Option<PathBuf>,
+
     /// Copy the output image to clipboard.
     #[structopt(short = "c", long)]
     pub to_clipboard: bool,
@@ -216,14 +1077,74 @@ pub struct Config {
 }
 
 impl Config {
+    fn get_language<'a>(&self, ps: &'a SyntaxSet) -> Option<Result<&'a SyntaxReference, Error>> {
+        match &self.language {
+            Some(language) => Some(find_syntax(ps, language)),
+            // the input won't be highlighted by syntect
+            None if self.ansi || self.plain => Some(Ok(PLAIN_TEXT.find_syntax_plain_text())),
+            None => None,
+        }
+    }
+
+    /// Get the language given by `--map-extension` for the file
+    fn get_mapped_language<'a>(
+        &self,
+        ps: &'a SyntaxSet,
+        path: &Path,
+    ) -> Option<Result<&'a SyntaxReference, Error>> {
+        let extension = path.extension()?.to_str()?;
+        // the last mapping of an extension wins
+        let (_, language) = self
+            .map_extension
+            .iter()
+            .rev()
+            .find(|(ext, _)| ext == extension)?;
+        Some(
+            ps.find_syntax_by_name(language)
+                .map_or_else(|| find_syntax(ps, language), Ok),
+        )
+    }
+
+    fn read_file<'a>(
+        &self,
+        ps: &'a SyntaxSet,
+        path: &Path,
+    ) -> Result<(&'a SyntaxReference, String), Error> {
+        let mut s = String::new();
+        let mut file = File::open(path)?;
+        file.read_to_string(&mut s)?;
+        let s = self.strip_escapes(s);
+
+        let language = self
+            .get_language(ps)
+            .or_else(|| self.get_mapped_language(ps, path))
+            .unwrap_or_else(|| {
+                ps.find_syntax_for_file(path)?
+                    .ok_or_else(|| format_err!("Failed to detect the language"))
+            })?;
+
+        Ok((language, s))
+    }
+
+    /// Read all the files given, return `(name, syntax, code)` of each file
+    pub fn get_source_codes<'a>(
+        &self,
+        ps: &'a SyntaxSet,
+    ) -> Result<Vec<(String, &'a SyntaxReference, String)>, Error> {
+        self.file
+            .iter()
+            .map(|path| {
+                let (language, code) = self.read_file(ps, path)?;
+                Ok((path.to_string_lossy().into_owned(), language, code))
+            })
+            .collect()
+    }
+
     pub fn get_source_code<'a>(
         &self,
         ps: &'a SyntaxSet,
     ) -> Result<(&'a SyntaxReference, String), Error> {
-        let possible_language = self.language.as_ref().map(|language| {
-            ps.find_syntax_by_token(language)
-                .ok_or_else(|| format_err!("Unsupported language: {}", language))
-        });
+        let possible_language = self.get_language(ps);
 
         if self.from_clipboard {
             let mut ctx = ClipboardContext::new()
@@ -231,6 +1152,7 @@ impl Config {
             let code = ctx
                 .get_contents()
                 .map_err(|e| format_err!("failed to access clipboard: {}", e))?;
+            let code = self.strip_escapes(code);
 
             let language = possible_language.unwrap_or_else(|| {
                 ps.find_syntax_by_first_line(&code)
@@ -240,22 +1162,14 @@ impl Config {
             return Ok((language, code));
         }
 
-        if let Some(path) = &self.file {
-            let mut s = String::new();
-            let mut file = File::open(path)?;
-            file.read_to_string(&mut s)?;
-
-            let language = possible_language.unwrap_or_else(|| {
-                ps.find_syntax_for_file(path)?
-                    .ok_or_else(|| format_err!("Failed to detect the language"))
-            })?;
-
-            return Ok((language, s));
+        if let Some(path) = self.file.first() {
+            return self.read_file(ps, path);
         }
 
         let mut stdin = stdin();
         let mut s = String::new();
         stdin.read_to_string(&mut s)?;
+        let s = self.strip_escapes(s);
 
         let language = possible_language.unwrap_or_else(|| {
             ps.find_syntax_by_first_line(&s)
@@ -265,46 +1179,275 @@ impl Config {
         Ok((language, s))
     }
 
+    /// Remove the escape sequences if `--strip-ansi` is given
+    fn strip_escapes(&self, code: String) -> String {
+        if self.strip_ansi {
+            strip_ansi(&code)
+        } else {
+            code
+        }
+    }
+
+    /// Add the syntaxes and themes given by `--extra-syntax`, `--extra-theme` and
+    /// `--theme-from-vscode`
+    pub fn load_extra_assets(&self, ha: &mut HighlightingAssets) -> Result<(), Error> {
+        for path in &self.extra_syntax {
+            ha.add_syntax_from_file(path)
+                .with_context(|| format!("Cannot load the syntax: {}", path.display()))?;
+        }
+        for path in &self.extra_theme {
+            ha.add_theme_from_file(path)
+                .with_context(|| format!("Cannot load the theme: {}", path.display()))?;
+        }
+        #[cfg(feature = "vscode")]
+        if let Some(path) = &self.theme_from_vscode {
+            let theme = silicon::vscode::load_theme(path)?;
+            ha.add_theme(self.theme_name(), theme);
+        }
+        Ok(())
+    }
+
     pub fn theme(&self, ts: &ThemeSet) -> Result<Theme, Error> {
-        if let Some(theme) = ts.themes.get(&self.theme) {
-            Ok(theme.clone())
+        // registered under its file name by `load_extra_assets`
+        #[cfg(feature = "vscode")]
+        if self.theme_from_vscode.is_some() {
+            let name = self.theme_name();
+            return ts
+                .themes
+                .get(&name)
+                .cloned()
+                .ok_or_else(|| format_err!("The VS Code theme '{}' isn't loaded", name));
+        }
+
+        if let Some(path) = &self.theme_file {
+            #[cfg(feature = "base16")]
+            if path.extension().is_some_and(|ext| {
+                ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml")
+            }) {
+                return silicon::base16::load_theme(path);
+            }
+            return ThemeSet::get_theme(path)
+                .with_context(|| format!("Cannot load the theme file: {}", path.display()));
+        }
+
+        let name = self.builtin_theme_name();
+        match ts.themes.get(name) {
+            Some(theme) => Ok(theme.clone()),
+            None => {
+                let suggestions = suggest_themes(name, ts);
+                if suggestions.is_empty() {
+                    Err(format_err!("Unknown theme '{}'", name))
+                } else {
+                    Err(format_err!(
+                        "Unknown theme '{}'; did you mean {}?",
+                        name,
+                        suggestions.join(", ")
+                    ))
+                }
+            }
+        }
+    }
+
+    /// The name of `--theme`, with 'auto' resolved
+    fn builtin_theme_name(&self) -> &str {
+        if self.theme == "auto" {
+            let variant = std::env::var("SILICON_THEME_VARIANT").ok();
+            let colorfgbg = std::env::var("COLORFGBG").ok();
+            if prefers_light_theme(variant.as_deref(), colorfgbg.as_deref()) {
+                "GitHub"
+            } else {
+                "Dracula"
+            }
         } else {
-            ThemeSet::get_theme(&self.theme)
-                .context(format!("Cannot load the theme: {}", self.theme))
+            &self.theme
         }
     }
 
-    pub fn get_formatter(&self) -> Result<ImageFormatter<FontCollection>, Error> {
-        let formatter = ImageFormatterBuilder::new()
+    /// The name of the theme used by `theme`, a theme file is named after the file name
+    pub fn theme_name(&self) -> String {
+        #[cfg(feature = "vscode")]
+        let file = self.theme_from_vscode.as_ref().or(self.theme_file.as_ref());
+        #[cfg(not(feature = "vscode"))]
+        let file = self.theme_file.as_ref();
+
+        match file {
+            Some(path) => path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            None => self.builtin_theme_name().to_owned(),
+        }
+    }
+
+    /// The text chunks written into the PNG images, unless `--no-metadata` is given
+    pub fn get_metadata(&self) -> Vec<(String, String)> {
+        if self.no_metadata {
+            return vec![];
+        }
+        let fonts = match &self.font {
+            Some(fonts) if !fonts.is_empty() => fonts
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+                .join("; "),
+            _ => "Hack".to_owned(),
+        };
+        vec![
+            (
+                "Software".to_owned(),
+                format!("silicon v{}", env!("CARGO_PKG_VERSION")),
+            ),
+            ("Theme".to_owned(), self.theme_name()),
+            ("Font".to_owned(), fonts),
+        ]
+    }
+
+    pub fn get_font_registry(&self) -> Result<FontRegistry, Error> {
+        let mut registry = FontRegistry::default();
+        registry.set_prefer_system_fonts(self.prefer_system_fonts);
+        for dir in &self.font_dir {
+            registry
+                .add_from_folder(dir)
+                .with_context(|| format!("Cannot load fonts from {}", dir.display()))?;
+        }
+        Ok(registry)
+    }
+
+    pub fn get_formatter(&self, theme: &Theme) -> Result<ImageFormatter<FontCollection>, Error> {
+        let mut formatter = ImageFormatterBuilder::new()
             .line_pad(self.line_pad)
+            .line_height(self.line_height)
             .window_controls(!self.no_window_controls)
             .window_title(self.window_title.clone())
+            .title_controls_gap(self.title_controls_gap)
+            .caption(self.caption.clone())
+            .title_color(self.window_title_color)
+            .title_font_size(self.window_title_size)
             .line_number(!self.no_line_number)
+            .line_number_mode(if self.relative_line_numbers {
+                LineNumberMode::Relative
+            } else {
+                LineNumberMode::Absolute
+            })
+            .line_number_align(self.line_number_align)
+            .line_number_side(self.line_number_side)
+            .line_number_color(self.line_number_color)
+            .line_number_separator(self.line_number_separator)
+            .indent_guides(self.indent_guides)
             .font(self.font.clone().unwrap_or_default())
+            .font_registry(self.get_font_registry()?)
+            .strict_monospace(self.strict_monospace)
+            .crop_to_content(self.crop_to_content)
+            .max_image_bytes(Some(self.max_image_bytes).filter(|&max| max > 0))
+            .hinting(self.hinting)
+            .font_features(self.font_features.clone().unwrap_or_default())
+            .synthesize_styles(self.synthesize_styles)
             .round_corner(!self.no_round_corner)
-            .shadow_adder(self.get_shadow_adder()?)
+            .round_corners(self.round_corners)
             .tab_width(self.tab_width)
-            .highlight_lines(self.highlight_lines.clone().unwrap_or_default())
+            .tab_style(self.tab_style)
+            .text_direction(self.text_direction)
+            .control_pictures(self.show_control_chars)
+            .max_frames(self.max_frames)
+            .code_background(if self.use_background_for_code {
+                Some(self.background_color(theme))
+            } else {
+                self.code_background
+            })
+            .highlight_line_ranges(self.get_highlight_lines()?)
+            .highlight_lines_absolute(self.highlight_lines_absolute)
+            .highlight_color(self.highlight_color)
+            .highlight_groups(self.highlight_group.clone())
+            .cursor_line(self.cursor_line)
+            .cursor_line_color(self.cursor_line_color)
+            .focus_line_ranges(self.focus_lines.clone().unwrap_or_default())
+            .highlight_ranges(self.highlight_ranges.clone().unwrap_or_default())
+            .accents(self.accent.clone())
+            .marks(self.mark.clone())
+            .mark_color(self.mark_color)
+            .selection(self.selection)
+            .selection_color(self.selection_color)
             .line_offset(self.line_offset)
-            .code_pad_right(self.code_pad_right);
+            .line_number_step(self.line_number_step)
+            .min_line_number_chars(self.line_number_chars)
+            .code_pad_right(self.code_pad_right)
+            .scale(if self.retina {
+                self.scale * 2.0
+            } else {
+                self.scale
+            });
+        if !self.no_background {
+            formatter = formatter.shadow_adder(self.get_shadow_adder(theme)?);
+        }
 
         Ok(formatter.build()?)
     }
 
-    pub fn get_shadow_adder(&self) -> Result<ShadowAdder, Error> {
-        Ok(ShadowAdder::new()
-            .background(match &self.background_image {
-                Some(path) => Background::Image(image::open(path)?.to_rgba8()),
-                None => Background::Solid(self.background),
-            })
-            .shadow_color(self.shadow_color)
+    /// Get the color given by `--background`, or the one which goes with the theme
+    pub fn background_color(&self, theme: &Theme) -> Rgba<u8> {
+        self.background
+            .unwrap_or_else(|| theme_padding_color(theme))
+    }
+
+    /// Get the background given by `--background` or `--background-image`
+    pub fn get_background(&self, theme: &Theme) -> Result<Background, Error> {
+        Ok(match &self.background_image {
+            Some(path) => {
+                Background::Image(image::open(path)?.to_rgba8(), self.background_image_fit)
+                    .tint(self.background_image_tint, self.background_image_opacity)
+            }
+            None => Background::Solid(self.background_color(theme)),
+        })
+    }
+
+    /// Get the lines given by `--highlight-lines` and `--highlight-lines-file`
+    pub fn get_highlight_lines(&self) -> Result<Lines, Error> {
+        let mut lines = self.highlight_lines.clone().unwrap_or_default();
+        if let Some(path) = &self.highlight_lines_file {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("Cannot read the lines: {}", path.display()))?;
+            for (i, line) in content.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                lines.extend(parse_line_range(line).map_err(|_| {
+                    format_err!("Invalid lines at {}:{}: `{}`", path.display(), i + 1, line)
+                })?);
+            }
+        }
+        Ok(lines)
+    }
+
+    pub fn get_shadow_adder(&self, theme: &Theme) -> Result<ShadowAdder, Error> {
+        let mut adder = ShadowAdder::new();
+        if let Some(color) = self.shadow_color {
+            adder = adder.shadow_color(color);
+        }
+        // a sharp shadow right behind the window would only show at its round corners
+        let visible =
+            self.shadow_blur_radius > 0.0 || self.shadow_offset_x != 0 || self.shadow_offset_y != 0;
+        Ok(adder
+            .enabled(!self.no_shadow && visible)
+            .background(self.get_background(theme)?)
             .blur_radius(self.shadow_blur_radius)
-            .pad_horiz(self.pad_horiz)
-            .pad_vert(self.pad_vert)
+            .blur_passes(self.shadow_blur_passes)
+            .blur_kind(self.shadow_blur_kind)
+            .shadow_softness(self.shadow_softness)
+            .pad_top(self.pad_top.unwrap_or(self.pad_vert))
+            .pad_bottom(self.pad_bottom.unwrap_or(self.pad_vert))
+            .pad_left(self.pad_left.unwrap_or(self.pad_horiz))
+            .pad_right(self.pad_right.unwrap_or(self.pad_horiz))
             .offset_x(self.shadow_offset_x)
             .offset_y(self.shadow_offset_y))
     }
 
+    /// Whether the output image should be written to stdout
+    pub fn output_to_stdout(&self) -> bool {
+        self.output.as_deref() == Some(Path::new("-"))
+    }
+
+    /// Get the output path with `~` expanded. See `expand_output_template` for the placeholders.
     pub fn get_expanded_output(&self) -> Option<PathBuf> {
         let need_expand = self.output.as_ref().map(|p| p.starts_with("~")) == Some(true);
 
@@ -317,3 +1460,389 @@ impl Config {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntect::highlighting::{Color, ThemeSettings};
+
+    #[test]
+    fn line_range() {
+        assert_eq!(parse_line_range("3-7"), Ok(vec![(3, 7)]));
+        assert_eq!(parse_line_range("5-"), Ok(vec![(5, u32::MAX)]));
+        assert_eq!(parse_line_range("-5"), Ok(vec![(1, 5)]));
+        assert_eq!(parse_line_range("1;3-4"), Ok(vec![(1, 1), (3, 4)]));
+        assert!(parse_line_range("a-3").is_err());
+    }
+
+    #[test]
+    fn blur_kind() {
+        let config = Config::from_iter_safe(&["silicon", "a.rs", "-o", "a.png"]).unwrap();
+        assert_eq!(config.shadow_blur_kind, BlurKind::BoxApprox);
+        let config = Config::from_iter_safe(&[
+            "silicon",
+            "a.rs",
+            "-o",
+            "a.png",
+            "--shadow-blur-kind",
+            "gaussian",
+        ])
+        .unwrap();
+        assert_eq!(config.shadow_blur_kind, BlurKind::TrueGaussian);
+        assert!(parse_blur_kind("triangle").is_err());
+    }
+
+    #[test]
+    fn extension_mapping() {
+        assert_eq!(
+            parse_extension_mapping(".h:C++").unwrap(),
+            ("h".to_owned(), "C++".to_owned())
+        );
+        assert!(parse_extension_mapping("h").is_err());
+        assert!(parse_extension_mapping("h:").is_err());
+
+        let ps = HighlightingAssets::new().syntax_set;
+        let config = Config::from_iter_safe(&[
+            "silicon",
+            "-o",
+            "a.png",
+            "--map-extension",
+            "h:C",
+            "--map-extension",
+            "h:C++",
+        ])
+        .unwrap();
+        let language = |path: &str| {
+            config
+                .get_mapped_language(&ps, Path::new(path))
+                .map(|syntax| syntax.unwrap().name.as_str())
+        };
+        assert_eq!(language("a.h"), Some("C++"));
+        assert_eq!(language("a.c"), None);
+        assert_eq!(language("a"), None);
+    }
+
+    #[test]
+    fn window_title_size() {
+        assert_eq!(parse_font_size("18.5").unwrap(), 18.5);
+        for size in ["0", "-3", "NaN", "inf", "big"] {
+            assert!(parse_font_size(size).is_err(), "{}", size);
+        }
+        let args = ["silicon", "a.rs", "-o", "a.png", "--window-title-size"];
+        let config = Config::from_iter_safe(args.iter().chain(&["20"])).unwrap();
+        assert_eq!(config.window_title_size, Some(20.0));
+        assert!(Config::from_iter_safe(args.iter().chain(&["0"])).is_err());
+    }
+
+    #[test]
+    fn font_features() {
+        assert_eq!(
+            parse_font_features("calt=0; ss01=1;"),
+            vec!["calt=0", "ss01=1"]
+        );
+        assert!(parse_font_features("").is_empty());
+    }
+
+    #[test]
+    fn accent() {
+        assert_eq!(
+            parse_accent("main=#f00").unwrap(),
+            ("main".to_owned(), Rgba([0xff, 0, 0, 0xff]))
+        );
+        assert_eq!(parse_accent("===#f00").unwrap().0, "==");
+        assert!(parse_accent("main").is_err());
+        assert!(parse_accent("=#f00").is_err());
+    }
+
+    #[test]
+    fn highlight_group() {
+        assert_eq!(
+            parse_highlight_group("1-3;5:#ff000033").unwrap(),
+            (vec![(1, 3), (5, 5)], Rgba([0xff, 0, 0, 0x33]))
+        );
+        assert_eq!(parse_highlight_group("-2:#fff").unwrap().0, vec![(1, 2)]);
+        assert!(parse_highlight_group("1-3").is_err());
+        assert!(parse_highlight_group("a:#fff").is_err());
+
+        let config = Config::from_iter_safe(&[
+            "silicon",
+            "a.rs",
+            "-o",
+            "a.png",
+            "--highlight-group",
+            "1:#f00",
+            "--highlight-group",
+            "2:#0f0",
+        ])
+        .unwrap();
+        assert_eq!(config.highlight_group.len(), 2);
+    }
+
+    #[test]
+    fn find_syntax_by_scope() {
+        let ps = HighlightingAssets::new().syntax_set;
+        assert_eq!(find_syntax(&ps, "rs").unwrap().name, "Rust");
+        assert_eq!(find_syntax(&ps, "source.rust").unwrap().name, "Rust");
+        assert_eq!(find_syntax(&ps, "Rust").unwrap().name, "Rust");
+        let err = find_syntax(&ps, "source.nothing").unwrap_err().to_string();
+        assert!(
+            err.starts_with("Unsupported language: source.nothing"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn detailed() {
+        let config = Config::from_iter_safe(&["silicon", "--list-themes", "--detailed"]).unwrap();
+        assert!(config.list_themes && config.detailed);
+        assert!(Config::from_iter_safe(&["silicon", "a.rs", "-o", "a.png", "--detailed"]).is_err());
+    }
+
+    #[test]
+    fn selection() {
+        assert_eq!(parse_selection("3:5-7:12").unwrap(), (3, 5, 7, 12));
+        assert_eq!(parse_selection("2:1-2:4").unwrap(), (2, 1, 2, 4));
+        assert!(parse_selection("3:5").is_err());
+        assert!(parse_selection("7:12-3:5").is_err());
+        assert!(parse_selection("0:1-2:1").is_err());
+    }
+
+    #[test]
+    fn output_template() {
+        let now = UNIX_EPOCH + std::time::Duration::from_secs(1_704_164_645);
+        assert_eq!(format_date(0), "19700101-000000");
+        assert_eq!(format_date(951_782_400), "20000229-000000");
+        assert_eq!(
+            expand_output_template(Path::new("{theme}-{lang}-{date}.png"), "Nord", "C/C++", now),
+            PathBuf::from("Nord-C-C++-20240102-030405.png")
+        );
+        assert_eq!(
+            expand_output_template(Path::new("out.png"), "Nord", "Rust", now),
+            PathBuf::from("out.png")
+        );
+
+        #[cfg(unix)]
+        {
+            use std::ffi::OsStr;
+            use std::os::unix::ffi::OsStrExt;
+
+            let path = Path::new(OsStr::from_bytes(b"out-\xff.png"));
+            assert_eq!(expand_output_template(path, "Nord", "Rust", now), path);
+        }
+    }
+
+    #[test]
+    fn config_arg() {
+        let args = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+        assert_eq!(
+            find_config_arg(&args(&["a.rs", "--config", "silicon.conf"])),
+            Some(PathBuf::from("silicon.conf"))
+        );
+        assert_eq!(
+            find_config_arg(&args(&["--config=silicon.conf", "a.rs"])),
+            Some(PathBuf::from("silicon.conf"))
+        );
+        assert_eq!(find_config_arg(&args(&["--config-file"])), None);
+        assert_eq!(find_config_arg(&args(&["--", "--config", "a"])), None);
+    }
+
+    #[test]
+    fn quality() {
+        assert_eq!(parse_quality("1").unwrap(), 1);
+        assert_eq!(parse_quality("100").unwrap(), 100);
+        assert!(parse_quality("0").is_err());
+        assert!(parse_quality("101").is_err());
+        assert!(parse_quality("high").is_err());
+
+        let config = Config::from_iter_safe(&["silicon", "a.rs", "-o", "a.jpg"]).unwrap();
+        assert_eq!(config.quality, 90);
+    }
+
+    #[test]
+    fn output_size() {
+        assert_eq!(parse_output_size("1280x720").unwrap(), (1280, 720));
+        assert_eq!(parse_output_size("64X32").unwrap(), (64, 32));
+        assert!(parse_output_size("1280").is_err());
+        assert!(parse_output_size("0x720").is_err());
+    }
+
+    #[test]
+    fn padding_color() {
+        let theme = |background| Theme {
+            settings: ThemeSettings {
+                background,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let dark = Color {
+            r: 0x28,
+            g: 0x2a,
+            b: 0x36,
+            a: 0xff,
+        };
+        let light = Color::WHITE;
+        assert_eq!(
+            theme_padding_color(&theme(Some(dark))),
+            Rgba([0x68, 0x69, 0x72, 0xff])
+        );
+        assert_eq!(
+            theme_padding_color(&theme(Some(light))),
+            Rgba([0xd9, 0xd9, 0xd9, 0xff])
+        );
+        assert_eq!(
+            theme_padding_color(&theme(None)),
+            Rgba([0xaa, 0xaa, 0xff, 0xff])
+        );
+
+        let config = Config::from_iter_safe(&["silicon", "a.rs", "-o", "a.png"]).unwrap();
+        assert_eq!(
+            config.background_color(&theme(Some(light))),
+            Rgba([0xd9, 0xd9, 0xd9, 0xff])
+        );
+        let config =
+            Config::from_iter_safe(&["silicon", "a.rs", "-o", "a.png", "-b", "#fff"]).unwrap();
+        assert_eq!(config.background_color(&theme(Some(dark))), Rgba([0xff; 4]));
+    }
+
+    #[test]
+    fn theme_variant() {
+        assert!(!prefers_light_theme(None, None));
+        assert!(prefers_light_theme(Some("light"), None));
+        assert!(!prefers_light_theme(Some("dark"), Some("0;15")));
+        assert!(prefers_light_theme(None, Some("0;15")));
+        assert!(prefers_light_theme(Some("unknown"), Some("0;default;7")));
+        assert!(!prefers_light_theme(None, Some("15;0")));
+    }
+
+    #[test]
+    fn highlight_lines_file() {
+        let path =
+            std::env::temp_dir().join(format!("silicon-test-lines-{}.txt", std::process::id()));
+        std::fs::write(&path, "2\n\n5-7\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let config = Config::from_iter_safe(&[
+            "silicon",
+            "a.rs",
+            "-o",
+            "a.png",
+            "--highlight-lines",
+            "1",
+            "--highlight-lines-file",
+            path,
+        ])
+        .unwrap();
+        assert_eq!(
+            config.get_highlight_lines().unwrap(),
+            vec![(1, 1), (2, 2), (5, 7)]
+        );
+
+        std::fs::write(path, "2\nthree\n").unwrap();
+        let err = config.get_highlight_lines().unwrap_err();
+        std::fs::remove_file(path).unwrap();
+        assert!(err.to_string().ends_with(":2: `three`"), "{}", err);
+    }
+
+    #[cfg(feature = "vscode")]
+    #[test]
+    fn theme_from_vscode() {
+        let path = std::env::temp_dir().join(format!("silicon-test-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r##"{ "colors": { "editor.background": "#112233" }, "tokenColors": [] }"##,
+        )
+        .unwrap();
+        let config = Config::from_iter_safe(&[
+            "silicon",
+            "a.rs",
+            "-o",
+            "a.png",
+            "--theme-from-vscode",
+            path.to_str().unwrap(),
+        ])
+        .unwrap();
+
+        let mut ha = HighlightingAssets::new();
+        assert!(config.theme(&ha.theme_set).is_err());
+        config.load_extra_assets(&mut ha).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let name = config.theme_name();
+        assert!(ha.theme_set.themes.contains_key(&name));
+        let theme = config.theme(&ha.theme_set).unwrap();
+        assert_eq!(
+            theme.settings.background,
+            Some(Color {
+                r: 0x11,
+                g: 0x22,
+                b: 0x33,
+                a: 0xff
+            })
+        );
+    }
+
+    #[test]
+    fn theme_name_and_file() {
+        let path = std::env::temp_dir().join("silicon-test-Sample.tmTheme");
+        std::fs::write(
+            &path,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>name</key>
+    <string>Sample</string>
+    <key>settings</key>
+    <array>
+        <dict>
+            <key>settings</key>
+            <dict>
+                <key>background</key>
+                <string>#102030</string>
+                <key>foreground</key>
+                <string>#f0f0f0</string>
+            </dict>
+        </dict>
+    </array>
+</dict>
+</plist>"#,
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+        let ts = ThemeSet::new();
+
+        let config =
+            Config::from_iter_safe(&["silicon", "a.rs", "-o", "a.png", "--theme", path]).unwrap();
+        assert!(config.theme(&ts).is_err());
+
+        let config =
+            Config::from_iter_safe(&["silicon", "a.rs", "-o", "a.png", "--theme-file", path])
+                .unwrap();
+        let theme = config.theme(&ts).unwrap();
+        assert_eq!(theme.name.as_deref(), Some("Sample"));
+    }
+
+    #[test]
+    fn theme_suggestions() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("abc", ""), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("dracula", "Dracula"), 1);
+
+        let mut ts = ThemeSet::new();
+        for name in &["Dracula", "Nord", "GitHub", "Solarized (dark)"] {
+            ts.themes.insert(name.to_string(), Theme::default());
+        }
+        // case insensitive, and the unrelated names are left out
+        assert_eq!(suggest_themes("dracla", &ts), vec!["'Dracula'"]);
+        assert_eq!(suggest_themes("nrod", &ts), vec!["'Nord'"]);
+        assert!(suggest_themes("monokai", &ts).is_empty());
+
+        let config =
+            Config::from_iter_safe(&["silicon", "a.rs", "-o", "a.png", "--theme", "Nordd"])
+                .unwrap();
+        let err = config.theme(&ts).unwrap_err().to_string();
+        assert_eq!(err, "Unknown theme 'Nordd'; did you mean 'Nord'?");
+    }
+}
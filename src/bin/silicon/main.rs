@@ -2,10 +2,19 @@
 extern crate anyhow;
 
 use anyhow::Error;
-use image::DynamicImage;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::{WebPEncoder, WebPQuality};
+use image::imageops::FilterType;
+use image::{ColorType, Delay, DynamicImage, Frame, Rgba};
 use std::env;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 use structopt::StructOpt;
 use syntect::easy::HighlightLines;
+use syntect::highlighting::{Color, FontStyle, Style, Theme};
 use syntect::util::LinesWithEndings;
 #[cfg(target_os = "windows")]
 use {
@@ -19,13 +28,19 @@ use {image::ImageOutputFormat, pasteboard::Pasteboard};
 use {image::ImageOutputFormat, std::process::Command};
 
 mod config;
-use crate::config::{config_file, get_args_from_config_file, Config};
+use crate::config::{
+    config_file, expand_output_template, find_config_arg, get_args_from_config_file, Config,
+};
+use silicon::ansi::highlight_ansi;
 use silicon::assets::HighlightingAssets;
 use silicon::directories::PROJECT_DIRS;
+use silicon::font::ImageFont;
+use silicon::formatter::{Formatter, HtmlFormatter, DEFAULT_BACKGROUND, DEFAULT_FOREGROUND};
+use silicon::utils::{fit_to_canvas, Background};
 
 #[cfg(target_os = "linux")]
 pub fn dump_image_to_clipboard(image: &DynamicImage) -> Result<(), Error> {
-    use std::io::{Cursor, Write};
+    use std::io::Cursor;
 
     match std::env::var(r#"XDG_SESSION_TYPE"#).ok() {
         Some(x) if x == "wayland" => {
@@ -101,15 +116,165 @@ pub fn dump_image_to_clipboard(_image: &DynamicImage) -> Result<(), Error> {
     ))
 }
 
+/// Write the image to stdout as PNG
+pub fn dump_image_to_stdout(
+    image: &DynamicImage,
+    metadata: &[(String, String)],
+) -> Result<(), Error> {
+    let mut stdout = std::io::stdout().lock();
+    write_png(image, &mut stdout, metadata)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Encode the image as PNG, with the metadata as text chunks
+pub fn write_png<W: Write>(
+    image: &DynamicImage,
+    writer: W,
+    metadata: &[(String, String)],
+) -> Result<(), Error> {
+    let image = image.to_rgba8();
+    let mut encoder = png::Encoder::new(writer, image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    for (keyword, text) in metadata {
+        // `tEXt` only holds Latin-1, use `iTXt` for the others (eg. the names of CJK fonts)
+        if text.is_ascii() {
+            encoder.add_text_chunk(keyword.clone(), text.clone())?;
+        } else {
+            encoder.add_itxt_chunk(keyword.clone(), text.clone())?;
+        }
+    }
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(image.as_raw())?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Save the image, honoring `quality` for lossy formats (JPEG/WebP).
+/// The metadata is written into the PNG images.
+pub fn save_image(
+    image: &DynamicImage,
+    path: &Path,
+    quality: u8,
+    metadata: &[(String, String)],
+) -> Result<(), Error> {
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+
+    match ext.as_deref() {
+        Some("jpg") | Some("jpeg") => {
+            let mut writer = BufWriter::new(File::create(path)?);
+            // JPEG doesn't support alpha channel
+            let image = image.to_rgb8();
+            JpegEncoder::new_with_quality(&mut writer, quality).encode_image(&image)?;
+        }
+        Some("webp") => {
+            let writer = BufWriter::new(File::create(path)?);
+            let image = image.to_rgba8();
+            #[allow(deprecated)]
+            WebPEncoder::new_with_quality(writer, WebPQuality::lossy(quality)).encode(
+                image.as_raw(),
+                image.width(),
+                image.height(),
+                ColorType::Rgba8,
+            )?;
+        }
+        Some("png") => write_png(image, BufWriter::new(File::create(path)?), metadata)?,
+        _ => image.save(path)?,
+    }
+    Ok(())
+}
+
+/// Encode the frames as an endless GIF animation, the last frame is held for two seconds
+pub fn save_animation<W: Write>(
+    frames: Vec<DynamicImage>,
+    writer: W,
+    fps: u32,
+) -> Result<(), Error> {
+    let mut encoder = GifEncoder::new_with_speed(writer, 10);
+    encoder.set_repeat(Repeat::Infinite)?;
+
+    let count = frames.len();
+    for (i, frame) in frames.into_iter().enumerate() {
+        let delay = if i + 1 == count {
+            Delay::from_numer_denom_ms(2000, 1)
+        } else {
+            Delay::from_numer_denom_ms(1000, fps)
+        };
+        encoder.encode_frame(Frame::from_parts(frame.into_rgba8(), 0, 0, delay))?;
+    }
+    Ok(())
+}
+
+/// The path of the `@2x` image, eg. `main@2x.png` for `main.png`
+fn retina_path(path: &Path) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push("@2x");
+    if let Some(ext) = path.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+    path.with_file_name(name)
+}
+
+/// Remove the trailing whitespace-only lines, but keep at least one line
+fn trim_trailing_blank_lines(code: &str) -> &str {
+    let end = code.trim_end().len();
+    // keep the end of the last non-blank line (or the first line if all lines are blank)
+    match code[end..].find('\n') {
+        Some(i) => &code[..end + i + 1],
+        None => code,
+    }
+}
+
+/// Turn the lone `\r` (the line break of classic Mac OS) into `\n`, `\r\n` is kept
+fn normalize_line_breaks(code: String) -> String {
+    if !code.contains('\r') {
+        return code;
+    }
+    let mut result = String::with_capacity(code.len());
+    let mut chars = code.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\r' && chars.peek() != Some(&'\n') {
+            result.push('\n');
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Split the code into lines of a single span, colored with the foreground of theme
+fn plain_lines<'a>(code: &'a str, theme: &Theme) -> Vec<Vec<(Style, &'a str)>> {
+    let style = Style {
+        foreground: theme.settings.foreground.unwrap_or(DEFAULT_FOREGROUND),
+        background: theme.settings.background.unwrap_or(DEFAULT_BACKGROUND),
+        font_style: FontStyle::empty(),
+    };
+    LinesWithEndings::from(code)
+        .map(|line| vec![(style, line)])
+        .collect()
+}
+
 fn run() -> Result<(), Error> {
-    let mut args = get_args_from_config_file();
-    let mut args_cli = std::env::args_os();
-    args.insert(0, args_cli.next().unwrap());
+    let mut args_cli = std::env::args_os().collect::<Vec<_>>();
+    let explicit_config = find_config_arg(&args_cli[1..]);
+    if let Some(path) = &explicit_config {
+        if !path.is_file() {
+            bail!("Cannot read the config file: {}", path.display());
+        }
+    }
+    let mut args = get_args_from_config_file(&config_file(explicit_config.as_deref()));
+    args.insert(0, args_cli.remove(0));
     args.extend(args_cli);
     let config: Config = Config::from_iter(args);
 
-    let ha = HighlightingAssets::new();
-    let (ps, ts) = (ha.syntax_set, ha.theme_set);
+    let mut ha = HighlightingAssets::new();
+    config.load_extra_assets(&mut ha)?;
+    let (ps, ts) = (&ha.syntax_set, &ha.theme_set);
 
     if let Some(path) = config.build_cache {
         let mut ha = HighlightingAssets::new();
@@ -121,41 +286,237 @@ fn run() -> Result<(), Error> {
         }
         return Ok(());
     } else if config.list_themes {
-        for i in ts.themes.keys() {
-            println!("{}", i);
+        for (name, theme) in &ts.themes {
+            if config.detailed {
+                let hex = |color: Option<Color>| match color {
+                    Some(c) if c.a == 0xff => format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b),
+                    Some(c) => format!("#{:02x}{:02x}{:02x}{:02x}", c.r, c.g, c.b, c.a),
+                    None => "n/a".to_owned(),
+                };
+                println!(
+                    "{}: bg={} fg={}",
+                    name,
+                    hex(theme.settings.background),
+                    hex(theme.settings.foreground)
+                );
+            } else {
+                println!("{}", name);
+            }
+        }
+        return Ok(());
+    } else if config.list_languages {
+        for (name, extensions) in ha.languages() {
+            println!("{}: {}", name, extensions.join(", "));
         }
         return Ok(());
     } else if config.list_fonts {
         let source = font_kit::source::SystemSource::new();
         for font in source.all_families().unwrap_or_default() {
-            println!("{}", font);
+            if config.styles {
+                // classified in the same way as the fonts for drawing
+                let styles = match ImageFont::new(&font, 26.0) {
+                    Ok(image_font) => image_font
+                        .styles()
+                        .iter()
+                        .map(|style| format!("{:?}", style))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    Err(_) => "n/a".to_owned(),
+                };
+                println!("{}: {}", font, styles);
+            } else {
+                println!("{}", font);
+            }
         }
         return Ok(());
     } else if config.config_file {
-        println!("{}", config_file().to_string_lossy());
+        println!(
+            "{}",
+            config_file(config.config.as_deref()).to_string_lossy()
+        );
+        return Ok(());
+    }
+
+    let sources = if config.file.len() > 1 {
+        config.get_source_codes(ps)?
+    } else {
+        let (syntax, code) = config.get_source_code(ps)?;
+        vec![(String::new(), syntax, code)]
+    };
+    let sources = sources
+        .into_iter()
+        .map(|(name, syntax, code)| (name, syntax, normalize_line_breaks(code)))
+        .collect::<Vec<_>>();
+
+    let theme = config.theme(ts)?;
+    // the placeholders can only be expanded after the language is detected
+    let output = config.get_expanded_output().map(|path| {
+        let lang = &sources[0].1.name;
+        expand_output_template(&path, &config.theme_name(), lang, SystemTime::now())
+    });
+
+    let mut blocks = vec![];
+    for (name, syntax, code) in &sources {
+        let code = if config.trim_trailing_blank_lines {
+            trim_trailing_blank_lines(code)
+        } else {
+            code
+        };
+        let highlight = if config.ansi {
+            highlight_ansi(code, &theme)
+        } else if config.plain {
+            plain_lines(code, &theme)
+        } else {
+            let mut h = HighlightLines::new(syntax, &theme);
+            LinesWithEndings::from(code)
+                .map(|line| h.highlight_line(line, ps))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        blocks.push((name.clone(), highlight));
+    }
+
+    let html_output = output.clone().filter(|path| {
+        !config.dry_run
+            && path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("html"))
+    });
+    if let Some(path) = html_output {
+        // drop the weights like `:300`, CSS can't select them per family
+        let fonts = config
+            .font
+            .iter()
+            .flatten()
+            .map(|(name, _)| name.split(':').next().unwrap_or(name).to_owned())
+            .collect();
+        let mut formatter = HtmlFormatter::new().font_family(fonts);
+        let html = blocks
+            .iter()
+            .map(|(_, lines)| formatter.format(lines, &theme))
+            .collect::<String>();
+        std::fs::write(&path, html)
+            .map_err(|e| format_err!("Failed to save HTML to {}: {}", path.display(), e))?;
+        return Ok(());
+    }
+
+    let mut formatter = config.get_formatter(&theme)?;
+    // the formatter renders the `@2x` image, the other one is downsampled from it
+    let output_size = match config.output_size {
+        Some((width, height)) if config.retina => Some((width * 2, height * 2)),
+        size => size,
+    };
+
+    let metrics = if blocks.len() > 1 {
+        formatter.metrics_many(&blocks)
+    } else {
+        formatter.metrics(&blocks[0].1)
+    };
+    let (width, height) = match output_size {
+        Some(size) => size,
+        None => (metrics.width, metrics.height),
+    };
+
+    if config.dry_run {
+        eprintln!("width: {}", width);
+        eprintln!("height: {}", height);
+        eprintln!("lines: {}", metrics.lines);
+        eprintln!("line_number_chars: {}", metrics.line_number_chars);
+        eprintln!("left_pad: {}", metrics.left_pad);
+        eprintln!("font_height: {}", metrics.font_height);
         return Ok(());
     }
+    // check before allocating the image, the code may have a very long line
+    formatter.check_size(metrics.width, metrics.height)?;
+    formatter.check_size(width, height)?;
+
+    if config.layers && config.output_to_stdout() {
+        bail!("--layers cannot be used when writing to stdout");
+    }
+    if config.retina && config.output_to_stdout() {
+        bail!("--retina cannot be used when writing to stdout");
+    }
+
+    // the canvas of `--output-size`, the extra space is filled like the paddings
+    let canvas = match output_size {
+        Some(size) if config.no_background => Some((size, Background::Solid(Rgba([0, 0, 0, 0])))),
+        Some(size) => Some((size, config.get_background(&theme)?)),
+        None => None,
+    };
 
-    let (syntax, code) = config.get_source_code(&ps)?;
+    if let Some(mode) = config.animate {
+        if blocks.len() > 1 {
+            bail!("--animate cannot be used with multiple files");
+        }
+        let mut frames = formatter.format_frames(&blocks[0].1, &theme, mode);
+        if let Some(((width, height), background)) = &canvas {
+            for frame in &mut frames {
+                let image = fit_to_canvas(&frame.to_rgba8(), *width, *height, background);
+                *frame = DynamicImage::ImageRgba8(image);
+            }
+        }
 
-    let theme = config.theme(&ts)?;
+        if config.output_to_stdout() {
+            save_animation(frames, std::io::stdout().lock(), config.fps)?;
+        } else {
+            let path = output.clone().unwrap();
+            let is_gif = path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("gif"));
+            if !is_gif {
+                bail!("--animate can only write GIF images");
+            }
+            let writer = BufWriter::new(File::create(&path)?);
+            save_animation(frames, writer, config.fps)
+                .map_err(|e| format_err!("Failed to save image to {}: {}", path.display(), e))?;
+        }
+        return Ok(());
+    }
 
-    let mut h = HighlightLines::new(syntax, &theme);
-    let highlight = LinesWithEndings::from(&code)
-        .map(|line| h.highlight_line(line, &ps))
-        .collect::<Result<Vec<_>, _>>()?;
+    let image = if config.layers {
+        let panel = if blocks.len() > 1 {
+            formatter.format_many_panel(&blocks, &theme)
+        } else {
+            formatter.format_panel(&blocks[0].1, &theme)
+        };
+        let adder = config.get_shadow_adder(&theme)?;
+        let (panel_layer, shadow_layer) = adder.layers(&panel);
 
-    let mut formatter = config.get_formatter()?;
+        let path = output.clone().unwrap();
+        for (layer, name) in [(panel_layer, "panel.png"), (shadow_layer, "shadow.png")] {
+            let path = path.with_extension(name);
+            layer
+                .save(&path)
+                .map_err(|e| format_err!("Failed to save image to {}: {}", path.display(), e))?;
+        }
 
-    let image = formatter.format(&highlight, &theme);
+        adder.apply_to(&panel)
+    } else if blocks.len() > 1 {
+        formatter.format_many(&blocks, &theme)
+    } else {
+        formatter.format(&blocks[0].1, &theme)
+    };
+    let image = match &canvas {
+        Some(((width, height), background)) => fit_to_canvas(&image, *width, *height, background),
+        None => image,
+    };
     let image = DynamicImage::ImageRgba8(image);
 
     if config.to_clipboard {
         dump_image_to_clipboard(&image)?;
+    } else if config.output_to_stdout() {
+        dump_image_to_stdout(&image, &config.get_metadata())?;
     } else {
-        let path = config.get_expanded_output().unwrap();
-        image
-            .save(&path)
+        let path = output.clone().unwrap();
+        let image = if config.retina {
+            let path = retina_path(&path);
+            save_image(&image, &path, config.quality, &config.get_metadata())
+                .map_err(|e| format_err!("Failed to save image to {}: {}", path.display(), e))?;
+            let (width, height) = (image.width().div_ceil(2), image.height().div_ceil(2));
+            image.resize_exact(width, height, FilterType::Lanczos3)
+        } else {
+            image
+        };
+        save_image(&image, &path, config.quality, &config.get_metadata())
             .map_err(|e| format_err!("Failed to save image to {}: {}", path.display(), e))?;
     }
 
@@ -169,3 +530,68 @@ fn main() {
         eprintln!("[error] {}", e);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_metadata() {
+        let image = DynamicImage::ImageRgba8(image::RgbaImage::new(4, 4));
+        let metadata = vec![
+            ("Software".to_owned(), "silicon v1.0.0".to_owned()),
+            ("Font".to_owned(), "Hack; 思源黑体".to_owned()),
+        ];
+        let mut buffer = vec![];
+        write_png(&image, &mut buffer, &metadata).unwrap();
+
+        let reader = png::Decoder::new(buffer.as_slice()).read_info().unwrap();
+        let info = reader.info();
+        assert_eq!(info.uncompressed_latin1_text.len(), 1);
+        assert_eq!(info.uncompressed_latin1_text[0].keyword, "Software");
+        assert_eq!(info.uncompressed_latin1_text[0].text, "silicon v1.0.0");
+        assert_eq!(info.utf8_text[0].keyword, "Font");
+        assert_eq!(info.utf8_text[0].get_text().unwrap(), "Hack; 思源黑体");
+    }
+
+    #[test]
+    fn plain_lines_fallback() {
+        let lines = plain_lines("a\r\nb", &Theme::default());
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0][0].1, "a\r\n");
+        // the same colors as the panel drawn by ImageFormatter
+        assert_eq!(lines[1][0].0.foreground, DEFAULT_FOREGROUND);
+        assert_eq!(lines[1][0].0.background, DEFAULT_BACKGROUND);
+    }
+
+    #[test]
+    fn retina() {
+        let retina = |path: &str| retina_path(Path::new(path));
+        assert_eq!(retina("main.png"), PathBuf::from("main@2x.png"));
+        assert_eq!(retina("dir/a.b.png"), PathBuf::from("dir/a.b@2x.png"));
+        assert_eq!(retina("dir/main"), PathBuf::from("dir/main@2x"));
+    }
+
+    #[test]
+    fn trailing_blank_lines() {
+        assert_eq!(trim_trailing_blank_lines("a\nb\n\n  \n\t\n"), "a\nb\n");
+        assert_eq!(trim_trailing_blank_lines("a\nb"), "a\nb");
+        assert_eq!(trim_trailing_blank_lines("a\n"), "a\n");
+        // the trailing whitespace of the last line is kept
+        assert_eq!(trim_trailing_blank_lines("a  \n\n"), "a  \n");
+        // at least one line is kept
+        assert_eq!(trim_trailing_blank_lines("\n  \n\n"), "\n");
+        assert_eq!(trim_trailing_blank_lines("  "), "  ");
+        assert_eq!(trim_trailing_blank_lines(""), "");
+    }
+
+    #[test]
+    fn line_breaks() {
+        let normalize = |code: &str| normalize_line_breaks(code.to_owned());
+        assert_eq!(normalize("a\nb\n"), "a\nb\n");
+        assert_eq!(normalize("a\r\nb\r\n"), "a\r\nb\r\n");
+        assert_eq!(normalize("a\rb\r"), "a\nb\n");
+        assert_eq!(normalize("a\r\r\nb\nc\r"), "a\n\r\nb\nc\n");
+        assert_eq!(normalize("\r"), "\n");
+    }
+}
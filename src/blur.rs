@@ -20,7 +20,28 @@ impl SharedMutPtr {
     }
 }
 
-pub fn gaussian_blur(image: RgbaImage, sigma: f32) -> RgbaImage {
+/// How to blur the shadow
+///
+/// Blurring a 1200x900 image on a single core takes about 90ms with `BoxApprox` whatever the
+/// radius, and 200ms, 800ms and 1.9s with `TrueGaussian` for a radius of 5, 20 and 50
+/// (measured by the ignored test `blur::tests::timings` in release mode).
+/// On the other hand, `BoxApprox` is off by up to 10 levels (of 255) from the exact blur with a
+/// radius of 4, which shows as boxy corners, while `TrueGaussian` is within one level.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum BlurKind {
+    /// Approximate the Gaussian blur with several box blurs (`gaussian_blur`).
+    /// The cost doesn't depend on the radius, but a small radius looks boxy.
+    #[default]
+    BoxApprox,
+    /// Convolve with a Gaussian kernel (`true_gaussian_blur`).
+    /// Accurate at any radius, but the cost grows linearly with it.
+    TrueGaussian,
+}
+
+/// Blur the image with `passes` box blurs approximating a Gaussian blur.
+///
+/// More passes are more accurate but slower, 3 passes is usually good enough.
+pub fn gaussian_blur(image: RgbaImage, sigma: f32, passes: usize) -> RgbaImage {
     let (width, height) = image.dimensions();
     let mut raw = image.into_raw();
     let len = raw.len();
@@ -30,7 +51,7 @@ pub fn gaussian_blur(image: RgbaImage, sigma: f32) -> RgbaImage {
         raw.set_len(len / 4);
 
         let ptr = &mut *(&mut raw as *mut Vec<u8> as *mut Vec<[u8; 4]>);
-        gaussian_blur_impl(ptr, width as usize, height as usize, sigma);
+        gaussian_blur_impl(ptr, width as usize, height as usize, sigma, passes);
 
         raw.set_len(len);
     }
@@ -38,31 +59,85 @@ pub fn gaussian_blur(image: RgbaImage, sigma: f32) -> RgbaImage {
     RgbaImage::from_raw(width, height, raw).unwrap()
 }
 
-fn gaussian_blur_impl(data: &mut [[u8; 4]], width: usize, height: usize, blur_radius: f32) {
-    let bxs = create_box_gauss(blur_radius, 3);
+fn gaussian_blur_impl(
+    data: &mut [[u8; 4]],
+    width: usize,
+    height: usize,
+    blur_radius: f32,
+    passes: usize,
+) {
+    let bxs = create_box_gauss(blur_radius, passes.max(1));
     let mut backbuf = data.to_vec();
 
-    box_blur(
-        &mut backbuf,
-        data,
-        width,
-        height,
-        ((bxs[0] - 1) / 2) as usize,
-    );
-    box_blur(
-        &mut backbuf,
-        data,
-        width,
-        height,
-        ((bxs[1] - 1) / 2) as usize,
-    );
-    box_blur(
-        &mut backbuf,
-        data,
-        width,
-        height,
-        ((bxs[2] - 1) / 2) as usize,
-    );
+    for size in bxs {
+        box_blur(&mut backbuf, data, width, height, ((size - 1) / 2) as usize);
+    }
+    // the result of the last pass is in `backbuf`, while `data` misses its vertical blur
+    data.copy_from_slice(&backbuf);
+}
+
+/// Blur the image with a Gaussian kernel, horizontally and then vertically.
+///
+/// The kernel covers 3 sigma on each side, so each pixel costs O(sigma) instead of the O(1)
+/// of `gaussian_blur`, see `BlurKind` for the tradeoff. The pixels beyond the edges repeat the
+/// edge pixels.
+///
+/// The horizontal pass is kept in a buffer of `f32`, which takes 16 bytes per pixel on top of
+/// the image and the result.
+pub fn true_gaussian_blur(image: RgbaImage, sigma: f32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    if sigma <= 0.0 || width == 0 || height == 0 {
+        return image;
+    }
+    let kernel = gaussian_kernel(sigma);
+    let radius = kernel.len() / 2;
+    let (width, height) = (width as usize, height as usize);
+    let stride = width * 4;
+    let src = image.into_raw();
+
+    let mut horz = vec![0.0f32; src.len()];
+    horz.par_chunks_mut(stride)
+        .zip(src.par_chunks(stride))
+        .for_each(|(out, row)| {
+            for (x, pixel) in out.chunks_exact_mut(4).enumerate() {
+                for (k, weight) in kernel.iter().enumerate() {
+                    let sx = (x + k).saturating_sub(radius).min(width - 1);
+                    for (acc, &v) in pixel.iter_mut().zip(&row[sx * 4..sx * 4 + 4]) {
+                        *acc += weight * f32::from(v);
+                    }
+                }
+            }
+        });
+
+    // accumulate whole rows, which is friendlier to the cache than walking the columns
+    let mut result = vec![0u8; src.len()];
+    result
+        .par_chunks_mut(stride)
+        .enumerate()
+        .for_each(|(y, out)| {
+            let mut acc = vec![0.0f32; stride];
+            for (k, weight) in kernel.iter().enumerate() {
+                let sy = (y + k).saturating_sub(radius).min(height - 1);
+                for (acc, &v) in acc.iter_mut().zip(&horz[sy * stride..(sy + 1) * stride]) {
+                    *acc += weight * v;
+                }
+            }
+            for (out, acc) in out.iter_mut().zip(acc) {
+                *out = acc.round().clamp(0.0, 255.0) as u8;
+            }
+        });
+
+    RgbaImage::from_raw(width as u32, height as u32, result).unwrap()
+}
+
+/// The normalized 1D Gaussian kernel covering 3 sigma on each side
+fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    let radius = (sigma * 3.0).ceil() as i32;
+    let kernel = (-radius..=radius)
+        .map(|x| (-(x * x) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect::<Vec<_>>();
+    let sum = kernel.iter().sum::<f32>();
+    kernel.into_iter().map(|w| w / sum).collect()
 }
 
 #[inline]
@@ -100,7 +175,7 @@ fn create_box_gauss(sigma: f32, n: usize) -> Vec<i32> {
     sizes
 }
 
-/// Needs 2x the same image
+/// Needs 2x the same image, the result is written to `backbuf`
 #[inline]
 fn box_blur(
     backbuf: &mut [[u8; 4]],
@@ -373,3 +448,139 @@ fn round(mut x: f32) -> f32 {
     x -= 12_582_912.0;
     x
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{gaussian_blur, true_gaussian_blur};
+    use image::{Rgba, RgbaImage};
+
+    /// The max difference from the exact blur of a vertical edge, ie. 255 * CDF(x / sigma)
+    fn edge_error(blur: impl Fn(RgbaImage, f32) -> RgbaImage) -> f32 {
+        let sigma = 4.0;
+        let image = RgbaImage::from_fn(100, 8, |x, _| {
+            Rgba([0xff, 0xff, 0xff, if x < 50 { 0 } else { 0xff }])
+        });
+        let image = blur(image, sigma);
+
+        // CDF of the standard normal distribution, summed numerically
+        let cdf = |x: f32| {
+            let step = 0.001;
+            let n = ((x + 8.0) / step) as i32;
+            (0..n)
+                .map(|i| -8.0 + (i as f32 + 0.5) * step)
+                .map(|t| (-t * t / 2.0).exp() * step)
+                .sum::<f32>()
+                / (2.0 * std::f32::consts::PI).sqrt()
+        };
+        (30..70)
+            .map(|x| {
+                // the edge is between the pixels 49 and 50
+                let expected = 255.0 * cdf((x as f32 - 49.5) / sigma);
+                (f32::from(image.get_pixel(x, 4).0[3]) - expected).abs()
+            })
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn true_gaussian_is_accurate() {
+        let true_error = edge_error(true_gaussian_blur);
+        let box_error = edge_error(|image, sigma| gaussian_blur(image, sigma, 3));
+        assert!(true_error <= 1.0, "{}", true_error);
+        assert!(box_error <= 10.0, "{}", box_error);
+        assert!(true_error < box_error, "{} {}", true_error, box_error);
+    }
+
+    /// Print the timings quoted in the doc of `BlurKind`, run it with
+    /// `cargo test --release timings -- --ignored --nocapture`
+    #[test]
+    #[ignore = "a benchmark, slow in a debug build"]
+    fn timings() {
+        let image = RgbaImage::from_fn(1200, 900, |x, y| {
+            let inside = (200..1000).contains(&x) && (200..700).contains(&y);
+            Rgba([0, 0, 0, if inside { 0xff } else { 0 }])
+        });
+        let time = |name: &str, sigma: f32, blur: &dyn Fn(RgbaImage, f32) -> RgbaImage| {
+            let start = std::time::Instant::now();
+            blur(image.clone(), sigma);
+            println!("{} (sigma {}): {:?}", name, sigma, start.elapsed());
+        };
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        pool.install(|| {
+            for &sigma in &[5.0, 20.0, 50.0] {
+                time("BoxApprox", sigma, &|image, sigma| {
+                    gaussian_blur(image, sigma, 3)
+                });
+                time("TrueGaussian", sigma, &true_gaussian_blur);
+            }
+        });
+    }
+
+    /// Print the time of 1 and 3 passes of box blur, run it with
+    /// `cargo test --release passes_timings -- --ignored --nocapture`
+    #[test]
+    #[ignore = "a benchmark, slow in a debug build"]
+    fn passes_timings() {
+        let image = RgbaImage::from_fn(2000, 2000, |x, y| {
+            let inside = (500..1500).contains(&x) && (500..1500).contains(&y);
+            Rgba([0, 0, 0, if inside { 0xff } else { 0 }])
+        });
+        for &passes in &[1, 3] {
+            let start = std::time::Instant::now();
+            gaussian_blur(image.clone(), 20.0, passes);
+            println!("{} passes: {:?}", passes, start.elapsed());
+        }
+    }
+
+    fn rect() -> RgbaImage {
+        RgbaImage::from_fn(10, 8, |x, y| {
+            let inside = (2..8).contains(&x) && (3..5).contains(&y);
+            Rgba([0xff, 0xff, 0xff, if inside { 0xff } else { 0 }])
+        })
+    }
+
+    fn alpha(image: &RgbaImage) -> Vec<u8> {
+        image.pixels().map(|p| p.0[3]).collect()
+    }
+
+    #[test]
+    fn default_passes() {
+        // every pass blurs both directions, so the result is symmetric
+        #[rustfmt::skip]
+        let expected = [
+            2, 3, 6, 8, 9, 9, 8, 6, 3, 2,
+            7, 14, 24, 32, 36, 36, 32, 24, 14, 7,
+            15, 31, 54, 73, 82, 82, 73, 54, 31, 15,
+            22, 45, 77, 105, 119, 119, 105, 77, 45, 22,
+            22, 45, 77, 105, 119, 119, 105, 77, 45, 22,
+            15, 31, 54, 73, 82, 82, 73, 54, 31, 15,
+            7, 14, 24, 32, 36, 36, 32, 24, 14, 7,
+            2, 3, 6, 8, 9, 9, 8, 6, 3, 2,
+        ];
+        let result = gaussian_blur(rect(), 1.5, 3);
+        assert_eq!(alpha(&result), expected);
+        assert!(result.pixels().all(|p| p.0[..3] == [0xff; 3]));
+    }
+
+    #[test]
+    fn passes_blur_both_directions() {
+        // a square blurred as much horizontally as vertically stays symmetric, except rounding
+        let square = RgbaImage::from_fn(12, 12, |x, y| {
+            let inside = (4..8).contains(&x) && (4..8).contains(&y);
+            Rgba([0xff, 0xff, 0xff, if inside { 0xff } else { 0 }])
+        });
+        for &passes in &[1, 2, 3, 4] {
+            let result = gaussian_blur(square.clone(), 1.5, passes);
+            for (x, y, pixel) in result.enumerate_pixels() {
+                let transposed = result.get_pixel(y, x);
+                assert!(
+                    pixel.0[3].abs_diff(transposed.0[3]) <= 1,
+                    "{} passes",
+                    passes
+                );
+            }
+        }
+    }
+}
@@ -7,6 +7,10 @@ use std::num::ParseIntError;
 pub enum FontError {
     SelectionError(SelectionError),
     FontLoadingError(FontLoadingError),
+    /// None of the requested fonts could be loaded
+    NoFontLoaded,
+    /// The font isn't monospaced, which makes the code misaligned
+    NotMonospace(String),
 }
 
 impl Error for FontError {}
@@ -16,6 +20,10 @@ impl Display for FontError {
         match self {
             FontError::SelectionError(e) => write!(f, "Font error: {}", e),
             FontError::FontLoadingError(e) => write!(f, "Font error: {}", e),
+            FontError::NoFontLoaded => write!(f, "Font error: none of the fonts can be loaded"),
+            FontError::NotMonospace(name) => {
+                write!(f, "Font error: `{}` is not a monospace font", name)
+            }
         }
     }
 }
@@ -54,3 +62,38 @@ impl From<ParseIntError> for ParseColorError {
         ParseColorError::InvalidDigit
     }
 }
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum FormatError {
+    MissingThemeColors,
+    /// The image would take more memory than allowed
+    ImageTooLarge {
+        width: u32,
+        height: u32,
+        max_bytes: u64,
+    },
+}
+
+impl Error for FormatError {}
+
+impl Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormatError::MissingThemeColors => {
+                write!(
+                    f,
+                    "The theme doesn't specify the foreground or background color"
+                )
+            }
+            FormatError::ImageTooLarge {
+                width,
+                height,
+                max_bytes,
+            } => write!(
+                f,
+                "The image of {}x{} would take more than {} bytes",
+                width, height, max_bytes
+            ),
+        }
+    }
+}
@@ -13,11 +13,14 @@
 //! ```
 use crate::error::FontError;
 #[cfg(feature = "harfbuzz")]
-use crate::hb_wrapper::{feature_from_tag, HBBuffer, HBFont};
+use crate::hb_wrapper::{feature_from_tag, HBBuffer, HBFont, POSITION_SCALE};
 use anyhow::Result;
 use conv::ValueInto;
 use font_kit::canvas::{Canvas, Format, RasterizationOptions};
+use font_kit::error::SelectionError;
+use font_kit::family_handle::FamilyHandle;
 use font_kit::font::Font;
+use font_kit::handle::Handle;
 use font_kit::hinting::HintingOptions;
 use font_kit::properties::{Properties, Style, Weight};
 use font_kit::source::SystemSource;
@@ -25,7 +28,10 @@ use image::{GenericImage, Pixel, Rgba, RgbaImage};
 use imageproc::definitions::Clamp;
 use imageproc::pixelops::weighted_sum;
 use pathfinder_geometry::transform2d::Transform2F;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
 use std::sync::Arc;
 use syntect::highlighting;
 
@@ -65,7 +71,12 @@ impl TextLineDrawer for FontCollection {
         font_style: FontStyle,
         text: &str,
     ) {
-        self.draw_text_mut(image, color, x, y, font_style, text);
+        // blend instead of `weighted_sum`, so that it works on a transparent background
+        self.draw_glyphs(x, y, font_style, text, |x, y, v| {
+            let mut color = color;
+            color.0[3] = (f32::from(color.0[3]) * v).round() as u8;
+            image.get_pixel_mut(x, y).blend(&color);
+        });
     }
 }
 
@@ -94,11 +105,205 @@ impl From<highlighting::FontStyle> for FontStyle {
     }
 }
 
+/// How the glyph outlines are fitted to the pixel grid
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Hinting {
+    /// Keep the exact outlines
+    #[default]
+    None,
+    /// Only fit the vertical metrics, which sharpens the horizontal stems
+    Vertical,
+    /// Fit in both directions
+    Full,
+}
+
+impl Hinting {
+    fn options(self, size: f32) -> HintingOptions {
+        match self {
+            Hinting::None => HintingOptions::None,
+            Hinting::Vertical => HintingOptions::Vertical(size),
+            Hinting::Full => HintingOptions::Full(size),
+        }
+    }
+}
+
+/// How the edges of the glyphs are smoothed
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Antialiasing {
+    /// Grayscale anti-aliasing
+    #[default]
+    Grayscale,
+    /// No anti-aliasing, every pixel is either covered or not
+    None,
+}
+
+impl Antialiasing {
+    fn options(self) -> RasterizationOptions {
+        match self {
+            Antialiasing::Grayscale => RasterizationOptions::GrayscaleAa,
+            Antialiasing::None => RasterizationOptions::Bilevel,
+        }
+    }
+}
+
 use pathfinder_geometry::rect::RectI;
-use pathfinder_geometry::vector::Vector2I;
+use pathfinder_geometry::vector::{Vector2F, Vector2I};
 use FontStyle::*;
 
+/// Fonts loaded from user provided directories, indexed by family name
+///
+/// The fonts in it take precedence over the system fonts with the same family name.
+#[derive(Debug, Default, Clone)]
+pub struct FontRegistry {
+    families: HashMap<String, Vec<Handle>>,
+    /// Look up Hack in the system fonts before using the bundled one
+    prefer_system_fonts: bool,
+}
+
+impl FontRegistry {
+    /// Resolve Hack like any other font, the bundled Hack is used only if it's not installed.
+    /// By default the bundled Hack is always used.
+    pub fn set_prefer_system_fonts(&mut self, prefer: bool) {
+        self.prefer_system_fonts = prefer;
+    }
+
+    /// Whether Hack is looked up in the system fonts first
+    pub fn prefer_system_fonts(&self) -> bool {
+        self.prefer_system_fonts
+    }
+
+    /// Load all the fonts (`.ttf`, `.otf`, `.ttc`) in the directory
+    pub fn add_from_folder<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        for entry in std::fs::read_dir(path)? {
+            let path = entry?.path();
+            let is_font = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .map(|ext| matches!(&*ext.to_lowercase(), "ttf" | "otf" | "ttc"))
+                .unwrap_or(false);
+            if !is_font {
+                continue;
+            }
+            match Font::from_path(&path, 0) {
+                Ok(font) => {
+                    debug!("{:?} - {}", path, font.family_name());
+                    self.families
+                        .entry(font.family_name())
+                        .or_default()
+                        .push(Handle::from_path(path, 0));
+                }
+                Err(err) => eprintln!("[warning] Cannot load font `{}`: {}", path.display(), err),
+            }
+        }
+        Ok(())
+    }
+
+    fn select_family_by_name(&self, name: &str) -> Option<FamilyHandle> {
+        self.families
+            .get(name)
+            .map(|handles| FamilyHandle::from_font_handles(handles.iter().cloned()))
+    }
+
+    /// Find the family in the registry, then in the system fonts.
+    /// `None` means the bundled Hack should be used.
+    fn locate_family(&self, name: &str) -> Result<Option<FamilyHandle>, FontError> {
+        self.locate_family_with(name, |name| SystemSource::new().select_family_by_name(name))
+    }
+
+    /// Like `locate_family`, but look up the system fonts with `system`
+    fn locate_family_with(
+        &self,
+        name: &str,
+        system: impl Fn(&str) -> Result<FamilyHandle, SelectionError>,
+    ) -> Result<Option<FamilyHandle>, FontError> {
+        if let Some(family) = self.select_family_by_name(name) {
+            return Ok(Some(family));
+        }
+        // Silicon already contains Hack font
+        if name == "Hack" {
+            let installed = self.prefer_system_fonts.then(|| system(name).ok());
+            return Ok(installed.flatten());
+        }
+        Ok(Some(system(name)?))
+    }
+}
+
+/// Whether the character is from a right-to-left script (eg. Hebrew, Arabic)
+pub(crate) fn is_rtl(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF | 0x10800..=0x10FFF | 0x1E800..=0x1EFFF
+    )
+}
+
+/// Split the text into runs of the same direction, return whether each run is right-to-left.
+///
+/// It's a simplified version of the Unicode bidi algorithm: letters of RTL scripts are
+/// right-to-left, other letters and digits are left-to-right, and the rest characters are
+/// right-to-left only if they are between two right-to-left characters.
+pub(crate) fn bidi_runs(text: &str) -> Vec<(bool, &str)> {
+    if !text.chars().any(is_rtl) {
+        return vec![(false, text)];
+    }
+
+    let strong = |c: char| {
+        if is_rtl(c) {
+            Some(true)
+        } else if c.is_alphanumeric() {
+            Some(false)
+        } else {
+            None
+        }
+    };
+    let chars = text.char_indices().collect::<Vec<_>>();
+
+    // the direction of the next strong character
+    let mut next = vec![false; chars.len()];
+    let mut dir = false;
+    for (i, &(_, c)) in chars.iter().enumerate().rev() {
+        next[i] = dir;
+        dir = strong(c).unwrap_or(dir);
+    }
+
+    let mut dirs = Vec::with_capacity(chars.len());
+    let mut prev = false;
+    for (i, &(_, c)) in chars.iter().enumerate() {
+        dirs.push(match strong(c) {
+            Some(dir) => {
+                prev = dir;
+                dir
+            }
+            None => prev && next[i],
+        });
+    }
+
+    let mut runs = vec![];
+    let mut start = 0;
+    for i in 1..=chars.len() {
+        if i == chars.len() || dirs[i] != dirs[start] {
+            let end = chars.get(i).map_or(text.len(), |&(pos, _)| pos);
+            runs.push((dirs[start], &text[chars[start].0..end]));
+            start = i;
+        }
+    }
+    runs
+}
+
+/// Split the weight from a font name like `JetBrains Mono:300`
+fn split_weight(name: &str) -> (&str, Option<f32>) {
+    match name.rsplit_once(':') {
+        Some((family, weight)) => match weight.trim().parse::<f32>() {
+            Ok(weight) => (family, Some(weight)),
+            Err(_) => (name, None),
+        },
+        None => (name, None),
+    }
+}
+
 /// A single font with specific size
+/// The size of the default font, Hack
+pub const DEFAULT_FONT_SIZE: f32 = 26.0;
+
 #[derive(Debug)]
 pub struct ImageFont {
     pub fonts: HashMap<FontStyle, Font>,
@@ -106,7 +311,7 @@ pub struct ImageFont {
 }
 
 impl Default for ImageFont {
-    /// It will use Hack font (size: 26.0) by default
+    /// It will use Hack font (size: `DEFAULT_FONT_SIZE`) by default
     fn default() -> Self {
         let l = vec![
             (
@@ -132,24 +337,43 @@ impl Default for ImageFont {
             fonts.insert(style, font);
         }
 
-        Self { fonts, size: 26.0 }
+        Self {
+            fonts,
+            size: DEFAULT_FONT_SIZE,
+        }
     }
 }
 
 impl ImageFont {
     pub fn new(name: &str, size: f32) -> Result<Self, FontError> {
-        // Silicon already contains Hack font
-        if name == "Hack" {
-            let font = ImageFont {
-                size,
-                ..Default::default()
-            };
-            return Ok(font);
-        }
+        Self::with_registry(name, size, &FontRegistry::default())
+    }
+
+    /// Create a font, looking it up in `registry` before the system fonts.
+    ///
+    /// The name can end with `:WEIGHT` (eg. `JetBrains Mono:300`), then the faces whose
+    /// weight is the closest to it are used as the regular and italic fonts.
+    pub fn with_registry(
+        name: &str,
+        size: f32,
+        registry: &FontRegistry,
+    ) -> Result<Self, FontError> {
+        let (name, weight) = split_weight(name);
+        let family = match registry.locate_family(name)? {
+            Some(family) => family,
+            None => {
+                let font = ImageFont {
+                    size,
+                    ..Default::default()
+                };
+                return Ok(font);
+            }
+        };
 
         let mut fonts = HashMap::new();
+        // distance between the weight of the chosen font and the requested weight
+        let mut distances = HashMap::new();
 
-        let family = SystemSource::new().select_family_by_name(name)?;
         let handles = family.fonts();
 
         debug!("{:?}", handles);
@@ -161,6 +385,23 @@ impl ImageFont {
 
             debug!("{:?} - {:?}", font, properties);
 
+            if let Some(weight) = weight {
+                let (regular, bold) = match properties.style {
+                    Style::Normal => (REGULAR, BOLD),
+                    Style::Italic => (ITALIC, BOLDITALIC),
+                    _ => continue,
+                };
+                if properties.weight == Weight::BOLD {
+                    fonts.insert(bold, font.clone());
+                }
+                let distance = (properties.weight.0 - weight).abs();
+                if distances.get(&regular).map_or(true, |&d| distance < d) {
+                    distances.insert(regular, distance);
+                    fonts.insert(regular, font);
+                }
+                continue;
+            }
+
             // cannot use match because `Weight` didn't derive `Eq`
             match properties.style {
                 Style::Normal => {
@@ -195,11 +436,38 @@ impl ImageFont {
             .unwrap_or_else(|| self.fonts.get(&REGULAR).unwrap())
     }
 
+    /// Get the styles which have their own faces, the others fall back to the regular one
+    pub fn styles(&self) -> Vec<FontStyle> {
+        [REGULAR, BOLD, ITALIC, BOLDITALIC]
+            .iter()
+            .filter(|style| self.fonts.contains_key(style))
+            .copied()
+            .collect()
+    }
+
     /// Get the regular font
     pub fn get_regular(&self) -> &Font {
         self.fonts.get(&REGULAR).unwrap()
     }
 
+    /// Whether the regular font is monospaced.
+    ///
+    /// Not all monospace fonts set the flag, so the advances of some ASCII glyphs are compared
+    /// as well. A font without these glyphs (eg. an emoji font) is considered monospaced.
+    pub fn is_monospace(&self) -> bool {
+        let font = self.get_regular();
+        if font.is_monospace() {
+            return true;
+        }
+        let advances = "iMW0."
+            .chars()
+            .filter_map(|c| font.glyph_for_char(c))
+            .filter_map(|id| font.advance(id).ok())
+            .map(|advance| advance.x())
+            .collect::<Vec<_>>();
+        advances.windows(2).all(|w| (w[0] - w[1]).abs() < 1.0)
+    }
+
     /// Get the height of the font
     pub fn get_font_height(&self) -> u32 {
         let font = self.get_regular();
@@ -214,41 +482,207 @@ impl ImageFont {
 #[derive(Debug)]
 pub struct FontCollection {
     fonts: Vec<ImageFont>,
+    /// Rasterized glyphs, indexed by (index of font, style, glyph id)
+    glyph_cache: RefCell<HashMap<(usize, FontStyle, u32), Rc<CachedGlyph>>>,
+    hinting: Hinting,
+    antialiasing: Antialiasing,
+    /// Font features passed to harfbuzz after the default ones
+    #[cfg(feature = "harfbuzz")]
+    features: Vec<String>,
+    /// Fake the italic and bold faces missing from a font
+    synthesize_styles: bool,
 }
 
 impl Default for FontCollection {
     fn default() -> Self {
         Self {
             fonts: vec![ImageFont::default()],
+            glyph_cache: Default::default(),
+            hinting: Hinting::default(),
+            antialiasing: Antialiasing::default(),
+            #[cfg(feature = "harfbuzz")]
+            features: vec![],
+            synthesize_styles: false,
         }
     }
 }
 
 impl FontCollection {
     /// Create a FontCollection with several fonts.
+    ///
+    /// The order of `font_list` is the fallback order: for every character, the first font is
+    /// tried first, then the subsequent fonts in the listed order.
     pub fn new<S: AsRef<str>>(font_list: &[(S, f32)]) -> Result<Self, FontError> {
+        Self::with_registry(font_list, &FontRegistry::default())
+    }
+
+    /// Create a FontCollection, looking the fonts up in `registry` before the system fonts.
+    ///
+    /// The fonts which fail to load are reported to stderr and skipped,
+    /// it fails only if none of the fonts can be loaded.
+    pub fn with_registry<S: AsRef<str>>(
+        font_list: &[(S, f32)],
+        registry: &FontRegistry,
+    ) -> Result<Self, FontError> {
+        let (collection, errors) = Self::load_with_registry(font_list, registry);
+        for (name, err) in &errors {
+            eprintln!("[error] Error occurs when load font `{}`: {}", name, err);
+        }
+        collection.or_first_error(errors)
+    }
+
+    /// Like `new`, but don't print anything.
+    /// Fail with the first error if none of the fonts can be loaded.
+    pub fn try_new<S: AsRef<str>>(font_list: &[(S, f32)]) -> Result<Self, FontError> {
+        let (collection, errors) = Self::load_with_registry(font_list, &FontRegistry::default());
+        collection.or_first_error(errors)
+    }
+
+    /// Load the fonts which can be loaded, and return the names and errors of the others
+    pub fn load_with_registry<S: AsRef<str>>(
+        font_list: &[(S, f32)],
+        registry: &FontRegistry,
+    ) -> (Self, Vec<(String, FontError)>) {
         let mut fonts = vec![];
+        let mut errors = vec![];
         for (name, size) in font_list {
             let name = name.as_ref();
-            match ImageFont::new(name, *size) {
+            match ImageFont::with_registry(name, *size, registry) {
                 Ok(font) => fonts.push(font),
-                Err(err) => eprintln!("[error] Error occurs when load font `{}`: {}", name, err),
+                Err(err) => errors.push((name.to_owned(), err)),
             }
         }
-        Ok(Self { fonts })
+        let collection = Self {
+            fonts,
+            glyph_cache: Default::default(),
+            hinting: Hinting::default(),
+            antialiasing: Antialiasing::default(),
+            #[cfg(feature = "harfbuzz")]
+            features: vec![],
+            synthesize_styles: false,
+        };
+        (collection, errors)
+    }
+
+    /// An empty collection can't draw anything, turn it into an error
+    fn or_first_error(self, errors: Vec<(String, FontError)>) -> Result<Self, FontError> {
+        if !self.fonts.is_empty() {
+            return Ok(self);
+        }
+        Err(errors
+            .into_iter()
+            .next()
+            .map_or(FontError::NoFontLoaded, |(_, err)| err))
     }
 
-    fn glyph_for_char(&self, c: char, style: FontStyle) -> Option<(u32, &ImageFont, &Font)> {
-        for font in &self.fonts {
-            let result = font.get_by_style(style);
-            if let Some(id) = result.glyph_for_char(c) {
-                return Some((id, font, result));
+    /// Change the fallback order.
+    ///
+    /// The fonts at the indices in `order` are moved to the front in the given order,
+    /// the remaining fonts keep their relative order. Invalid or duplicated indices are ignored.
+    pub fn reorder(&mut self, order: &[usize]) {
+        let mut fonts: Vec<Option<ImageFont>> = self.fonts.drain(..).map(Some).collect();
+        let mut result = Vec::with_capacity(fonts.len());
+        for &i in order {
+            if let Some(font) = fonts.get_mut(i).and_then(Option::take) {
+                result.push(font);
+            }
+        }
+        result.extend(fonts.into_iter().flatten());
+        self.fonts = result;
+        // the cache is indexed by the position of font
+        self.glyph_cache.borrow_mut().clear();
+    }
+
+    /// Check that the first font, which draws most of the code, is monospaced.
+    /// The other fonts are fallbacks for other scripts, where a fixed width is rarely kept.
+    pub fn check_monospace(&self) -> Result<(), FontError> {
+        match self.fonts.first() {
+            Some(font) if !font.is_monospace() => {
+                Err(FontError::NotMonospace(font.get_regular().full_name()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Set how the glyph outlines are fitted to the pixel grid. Default: `Hinting::None`
+    pub fn set_hinting(&mut self, hinting: Hinting) {
+        self.hinting = hinting;
+        self.glyph_cache.borrow_mut().clear();
+    }
+
+    /// Set how the edges of the glyphs are smoothed. Default: `Antialiasing::Grayscale`
+    pub fn set_antialiasing(&mut self, antialiasing: Antialiasing) {
+        self.antialiasing = antialiasing;
+        self.glyph_cache.borrow_mut().clear();
+    }
+
+    /// Whether to fake the missing italic faces by slanting the regular glyphs,
+    /// and the missing bold faces by thickening them. Default: false
+    pub fn set_synthesize_styles(&mut self, synthesize: bool) {
+        self.synthesize_styles = synthesize;
+        self.glyph_cache.borrow_mut().clear();
+    }
+
+    /// Set the font features, eg. `calt=0` to disable the contextual ligatures or `ss01` to
+    /// enable a stylistic set. They are applied after the default `kern`, `clig` and `liga`,
+    /// so they can turn them off as well.
+    ///
+    /// It only takes effect with the `harfbuzz` feature, the invalid features are reported to
+    /// stderr and skipped.
+    pub fn set_font_features(&mut self, features: Vec<String>) {
+        #[cfg(feature = "harfbuzz")]
+        {
+            self.features = features
+                .into_iter()
+                .filter(|tag| match feature_from_tag(tag) {
+                    Ok(_) => true,
+                    Err(_) => {
+                        eprintln!("[warning] Invalid font feature `{}`", tag);
+                        false
+                    }
+                })
+                .collect();
+        }
+        #[cfg(not(feature = "harfbuzz"))]
+        let _ = features;
+    }
+
+    /// Find the glyph of the character, return the glyph id and the index of font
+    fn glyph_for_char(&self, c: char, style: FontStyle) -> Option<(u32, usize)> {
+        for (index, font) in self.fonts.iter().enumerate() {
+            if let Some(id) = font.get_by_style(style).glyph_for_char(c) {
+                return Some((id, index));
             }
         }
         eprintln!("[warning] No font found for character `{}`", c);
         None
     }
 
+    /// Get the rasterized glyph, from the cache if possible
+    fn get_glyph(&self, index: usize, style: FontStyle, id: u32) -> Rc<CachedGlyph> {
+        self.glyph_cache
+            .borrow_mut()
+            .entry((index, style, id))
+            .or_insert_with(|| {
+                let font = &self.fonts[index];
+                // `get_by_style` falls back to the regular face
+                let synthetic = if self.synthesize_styles && !font.fonts.contains_key(&style) {
+                    style
+                } else {
+                    REGULAR
+                };
+                Rc::new(CachedGlyph::new(
+                    font.get_by_style(style),
+                    id,
+                    font.size,
+                    self.hinting.options(font.size),
+                    self.antialiasing.options(),
+                    synthetic,
+                ))
+            })
+            .clone()
+    }
+
     /// get max height of all the fonts
     pub fn get_font_height(&self) -> u32 {
         self.fonts
@@ -258,36 +692,49 @@ impl FontCollection {
             .unwrap()
     }
 
+    /// Shape the text into glyphs, whose advances and offsets are in the scale of `font`
     #[cfg(feature = "harfbuzz")]
-    fn shape_text(&self, font: &mut HBFont, text: &str) -> Result<Vec<u32>> {
-        // feature tags
-        let features = vec![
-            feature_from_tag("kern")?,
-            feature_from_tag("clig")?,
-            feature_from_tag("liga")?,
-        ];
+    fn shape_text(&self, font: &mut HBFont, text: &str) -> Result<Vec<ShapedGlyph>> {
+        // feature tags, the later ones override the earlier ones
+        let features = ["kern", "clig", "liga"]
+            .iter()
+            .copied()
+            .chain(self.features.iter().map(String::as_str))
+            .map(feature_from_tag)
+            .collect::<Result<Vec<_>>>()?;
         let mut buf = HBBuffer::new()?;
         buf.add_str(text);
         buf.guess_segments_properties();
         font.shape(&buf, features.as_slice());
-        let hb_infos = buf.get_glyph_infos();
-        let mut glyph_ids = Vec::new();
-        for info in hb_infos.iter() {
-            glyph_ids.push(info.codepoint);
-        }
-        Ok(glyph_ids)
+        let hb_infos = buf
+            .get_glyph_infos()
+            .iter()
+            .map(|info| (info.codepoint, info.cluster))
+            .collect::<Vec<_>>();
+        let hb_positions = buf.get_glyph_positions();
+        let glyphs = hb_infos
+            .into_iter()
+            .zip(hb_positions.iter())
+            .map(|((id, cluster), position)| ShapedGlyph {
+                id,
+                cluster,
+                x_advance: position.x_advance,
+                x_offset: position.x_offset,
+                y_offset: position.y_offset,
+            })
+            .collect();
+        Ok(glyphs)
     }
 
+    /// Split the text into runs of the same font, return the index of font and the text
     #[cfg(feature = "harfbuzz")]
-    fn split_by_font(&self, text: &str, style: FontStyle) -> Vec<(&ImageFont, &Font, String)> {
-        let mut result: Vec<(&ImageFont, &Font, String)> = vec![];
+    fn split_by_font(&self, text: &str, style: FontStyle) -> Vec<(usize, String)> {
+        let mut result: Vec<(usize, String)> = vec![];
         for c in text.chars() {
-            if let Some((_, imfont, font)) = self.glyph_for_char(c, style) {
-                if result.is_empty() || !std::ptr::eq(result.last().unwrap().0, imfont) {
-                    result.push((imfont, font, String::new()));
-                }
-                if std::ptr::eq(result.last().unwrap().0, imfont) {
-                    result.last_mut().unwrap().2.push(c);
+            if let Some((_, index)) = self.glyph_for_char(c, style) {
+                match result.last_mut() {
+                    Some((last, text)) if *last == index => text.push(c),
+                    _ => result.push((index, c.to_string())),
                 }
             }
         }
@@ -295,83 +742,125 @@ impl FontCollection {
         result
     }
 
+    /// Shape the text with the font at `index`, return the index of font and the glyph,
+    /// whose advances and offsets are in `1 / POSITION_SCALE` font units.
+    ///
+    /// The clusters shaped into `.notdef` (glyph 0) are shaped again with the next fonts.
+    #[cfg(feature = "harfbuzz")]
+    fn shape_with_fallback(
+        &self,
+        index: usize,
+        style: FontStyle,
+        text: &str,
+    ) -> Vec<(usize, ShapedGlyph)> {
+        let mut hb_font = HBFont::new(self.fonts[index].get_by_style(style));
+        // apply font features especially ligature with a shape engine
+        let shaped = self.shape_text(&mut hb_font, text).unwrap();
+
+        if index + 1 >= self.fonts.len() || shaped.iter().all(|glyph| glyph.id != 0) {
+            return shaped.into_iter().map(|glyph| (index, glyph)).collect();
+        }
+
+        let mut result = vec![];
+        let mut i = 0;
+        while i < shaped.len() {
+            let cluster = shaped[i].cluster;
+            let end = shaped[i..]
+                .iter()
+                .position(|glyph| glyph.cluster != cluster)
+                .map_or(shaped.len(), |n| i + n);
+            if shaped[i..end].iter().any(|glyph| glyph.id == 0) {
+                // the cluster spans until the start of the next cluster
+                let next = shaped
+                    .iter()
+                    .map(|glyph| glyph.cluster)
+                    .filter(|&c| c > cluster)
+                    .min()
+                    .unwrap_or(text.len() as u32);
+                let cluster_text = &text[cluster as usize..next as usize];
+                result.extend(self.shape_with_fallback(index + 1, style, cluster_text));
+            } else {
+                result.extend(shaped[i..end].iter().map(|&glyph| (index, glyph)));
+            }
+            i = end;
+        }
+        result
+    }
+
     #[cfg(feature = "harfbuzz")]
     fn layout(&self, text: &str, style: FontStyle) -> (Vec<PositionedGlyph>, u32) {
-        let mut delta_x = 0;
+        // use the advances from harfbuzz, which include the kerning
+        let mut delta_x = 0.0;
         let height = self.get_font_height();
 
         let mut glyphs = Vec::with_capacity(text.len());
-        for (imfont, font, text) in self.split_by_font(text, style) {
-            let mut hb_font = HBFont::new(font);
-            // apply font features especially ligature with a shape engine
-            let shaped_glyphs = self.shape_text(&mut hb_font, &text).unwrap();
-            glyphs.extend(shaped_glyphs.iter().map(|id| {
-                let raster_rect = font
-                    .raster_bounds(
-                        *id,
-                        imfont.size,
-                        Transform2F::default(),
-                        HintingOptions::None,
-                        RasterizationOptions::GrayscaleAa,
-                    )
-                    .unwrap();
-                let position = Vector2I::new(delta_x as i32, height as i32) + raster_rect.origin();
-                delta_x += Self::get_glyph_width(font, *id, imfont.size);
-
-                PositionedGlyph {
-                    id: *id,
-                    font: font.clone(),
-                    size: imfont.size,
-                    raster_rect,
-                    position,
-                }
+        let pieces = bidi_runs(text).into_iter().flat_map(|(rtl, run)| {
+            let mut pieces = self.split_by_font(run, style);
+            // harfbuzz outputs the glyphs of a right-to-left piece in visual order already,
+            // only the order of pieces needs to be reversed
+            if rtl {
+                pieces.reverse();
+            }
+            pieces
+        });
+        for (index, text) in pieces {
+            let shaped_glyphs = self.shape_with_fallback(index, style, &text);
+            glyphs.extend(shaped_glyphs.into_iter().map(|(index, shaped)| {
+                let font = &self.fonts[index];
+                // from `1 / POSITION_SCALE` font units to pixels
+                let units_per_em = font.get_by_style(style).metrics().units_per_em as f32;
+                let scale = font.size / (units_per_em * POSITION_SCALE as f32);
+                let glyph = self.get_glyph(index, style, shaped.id);
+                // align to the baseline of the font which the glyph comes from
+                let baseline = height as i32 + glyph.descent;
+                // the y offset of harfbuzz points up
+                let offset = Vector2F::new(
+                    shaped.x_offset as f32 * scale,
+                    -shaped.y_offset as f32 * scale,
+                );
+                let position = Vector2F::new(delta_x, baseline as f32)
+                    + offset
+                    + glyph.raster_rect.origin().to_f32();
+                delta_x += shaped.x_advance as f32 * scale;
+
+                PositionedGlyph { glyph, position }
             }))
         }
 
-        (glyphs, delta_x)
+        (glyphs, delta_x.round() as u32)
     }
 
     #[cfg(not(feature = "harfbuzz"))]
     fn layout(&self, text: &str, style: FontStyle) -> (Vec<PositionedGlyph>, u32) {
-        let mut delta_x = 0;
+        // keep the fractional part of the advances, or the rounding errors add up along the line
+        let mut delta_x = 0.0;
         let height = self.get_font_height();
 
-        let glyphs = text
-            .chars()
+        // draw the characters in visual order
+        let chars = bidi_runs(text).into_iter().flat_map(|(rtl, run)| {
+            let mut chars = run.chars().collect::<Vec<_>>();
+            if rtl {
+                chars.reverse();
+            }
+            chars
+        });
+
+        let glyphs = chars
             .filter_map(|c| {
-                self.glyph_for_char(c, style).map(|(id, imfont, font)| {
-                    let raster_rect = font
-                        .raster_bounds(
-                            id,
-                            imfont.size,
-                            Transform2F::default(),
-                            HintingOptions::None,
-                            RasterizationOptions::GrayscaleAa,
-                        )
-                        .unwrap();
-                    let position =
-                        Vector2I::new(delta_x as i32, height as i32) + raster_rect.origin();
-                    delta_x += Self::get_glyph_width(font, id, imfont.size);
-
-                    PositionedGlyph {
-                        id,
-                        font: font.clone(),
-                        size: imfont.size,
-                        raster_rect,
-                        position,
-                    }
+                self.glyph_for_char(c, style).map(|(id, index)| {
+                    let glyph = self.get_glyph(index, style, id);
+                    // align to the baseline of the font which the glyph comes from
+                    let baseline = height as i32 + glyph.descent;
+                    let position = Vector2F::new(delta_x, baseline as f32)
+                        + glyph.raster_rect.origin().to_f32();
+                    delta_x += glyph.advance;
+
+                    PositionedGlyph { glyph, position }
                 })
             })
             .collect();
 
-        (glyphs, delta_x)
-    }
-
-    /// Get the width of the given glyph
-    fn get_glyph_width(font: &Font, id: u32, size: f32) -> u32 {
-        let metrics = font.metrics();
-        let advance = font.advance(id).unwrap();
-        (advance / metrics.units_per_em as f32 * size).x().ceil() as u32
+        (glyphs, delta_x.round() as u32)
     }
 
     /// Get the width of the given text
@@ -394,21 +883,30 @@ impl FontCollection {
         I: GenericImage,
         <I::Pixel as Pixel>::Subpixel: ValueInto<f32> + Clamp<f32>,
     {
-        let metrics = self.fonts[0].get_regular().metrics();
-        let offset =
-            (metrics.descent / metrics.units_per_em as f32 * self.fonts[0].size).round() as i32;
+        self.draw_glyphs(x, y, style, text, |x, y, v| {
+            let pixel = image.get_pixel(x, y);
+            let weighted_color = weighted_sum(pixel, color, 1.0 - v, v);
+            image.put_pixel(x, y, weighted_color);
+        })
+    }
 
+    /// Call `draw(x, y, coverage)` for every pixel covered by the text, return the width
+    fn draw_glyphs<F: FnMut(u32, u32, f32)>(
+        &self,
+        x: u32,
+        y: u32,
+        style: FontStyle,
+        text: &str,
+        mut draw: F,
+    ) -> u32 {
         let (glyphs, width) = self.layout(text, style);
 
         for glyph in glyphs {
-            glyph.draw(offset, |px, py, v| {
-                if v <= std::f32::EPSILON {
+            glyph.draw(|px, py, v| {
+                if v <= f32::EPSILON {
                     return;
                 }
-                let (x, y) = ((px + x as i32) as u32, (py + y as i32) as u32);
-                let pixel = image.get_pixel(x, y);
-                let weighted_color = weighted_sum(pixel, color, 1.0 - v, v);
-                image.put_pixel(x, y, weighted_color);
+                draw((px + x as i32) as u32, (py + y as i32) as u32, v);
             })
         }
 
@@ -416,45 +914,444 @@ impl FontCollection {
     }
 }
 
+/// A glyph shaped by harfbuzz
+#[cfg(feature = "harfbuzz")]
+#[derive(Debug, Copy, Clone)]
+struct ShapedGlyph {
+    id: u32,
+    /// byte offset of the cluster in the text
+    cluster: u32,
+    x_advance: i32,
+    x_offset: i32,
+    y_offset: i32,
+}
+
 #[derive(Debug)]
 struct PositionedGlyph {
-    id: u32,
-    font: Font,
-    size: f32,
-    position: Vector2I,
-    raster_rect: RectI,
+    glyph: Rc<CachedGlyph>,
+    /// Fractional position, which is only rounded when drawing
+    position: Vector2F,
 }
 
 impl PositionedGlyph {
-    fn draw<O: FnMut(i32, i32, f32)>(&self, offset: i32, mut o: O) {
-        let mut canvas = Canvas::new(self.raster_rect.size(), Format::A8);
+    fn draw<O: FnMut(i32, i32, f32)>(&self, mut o: O) {
+        let glyph = &self.glyph;
+        let position = self.position.round().to_i32();
+        for y in 0..glyph.raster_rect.height() {
+            let row_start = y as usize * glyph.stride;
+            let row = &glyph.pixels[row_start..row_start + glyph.raster_rect.width() as usize];
+
+            for (x, &val) in row.iter().enumerate() {
+                let px = position.x() + x as i32;
+                let py = position.y() + y;
+
+                o(px, py, f32::from(val) / 255.0);
+            }
+        }
+    }
+}
+
+/// A rasterized glyph with its metrics, in pixels
+#[derive(Debug)]
+struct CachedGlyph {
+    raster_rect: RectI,
+    /// A8 bitmap of the glyph
+    pixels: Vec<u8>,
+    stride: usize,
+    /// Advance width, with the fractional part
+    advance: f32,
+    /// Descent of the font (negative)
+    descent: i32,
+}
+
+impl CachedGlyph {
+    fn new(
+        font: &Font,
+        id: u32,
+        size: f32,
+        hinting: HintingOptions,
+        rasterization: RasterizationOptions,
+        synthetic: FontStyle,
+    ) -> Self {
+        let metrics = font.metrics();
+        // slant the glyph to the right, pivoting on the baseline
+        let transform = match synthetic {
+            ITALIC | BOLDITALIC => Transform2F::row_major(1.0, -0.2, 0.0, 1.0, 0.0, 0.0),
+            _ => Transform2F::default(),
+        };
+        let mut raster_rect = font
+            .raster_bounds(id, size, transform, hinting, rasterization)
+            .unwrap();
+        let mut canvas = Canvas::new(raster_rect.size(), Format::A8);
 
         // don't rasterize whitespace(https://github.com/pcwalton/font-kit/issues/7)
         if canvas.size != Vector2I::new(0, 0) {
-            self.font
-                .rasterize_glyph(
-                    &mut canvas,
-                    self.id,
-                    self.size,
-                    Transform2F::from_translation(-self.raster_rect.origin().to_f32()),
-                    HintingOptions::None,
-                    RasterizationOptions::GrayscaleAa,
-                )
-                .unwrap();
+            font.rasterize_glyph(
+                &mut canvas,
+                id,
+                size,
+                Transform2F::from_translation(-raster_rect.origin().to_f32()) * transform,
+                hinting,
+                rasterization,
+            )
+            .unwrap();
         }
 
-        for y in (0..self.raster_rect.height()).rev() {
-            let (row_start, row_end) =
-                (y as usize * canvas.stride, (y + 1) as usize * canvas.stride);
-            let row = &canvas.pixels[row_start..row_end];
+        let (mut pixels, mut stride) = (canvas.pixels, canvas.stride);
+        if matches!(synthetic, BOLD | BOLDITALIC) && !pixels.is_empty() {
+            // thicken the strokes by drawing the glyph several times, shifted to the right
+            let extra = (size / 24.0).ceil().max(1.0) as usize;
+            let (width, height) = (raster_rect.width() as usize, raster_rect.height() as usize);
+            let mut bold = vec![0; (width + extra) * height];
+            for y in 0..height {
+                let row = &pixels[y * stride..y * stride + width];
+                let bold_row = &mut bold[y * (width + extra)..(y + 1) * (width + extra)];
+                for shift in 0..=extra {
+                    for (x, &val) in row.iter().enumerate() {
+                        bold_row[x + shift] = bold_row[x + shift].max(val);
+                    }
+                }
+            }
+            raster_rect = RectI::new(
+                raster_rect.origin(),
+                raster_rect.size() + Vector2I::new(extra as i32, 0),
+            );
+            pixels = bold;
+            stride = width + extra;
+        }
 
-            for x in 0..self.raster_rect.width() {
-                let val = f32::from(row[x as usize]) / 255.0;
-                let px = self.position.x() + x;
-                let py = self.position.y() + y + offset;
+        let advance = font.advance(id).unwrap();
+        Self {
+            raster_rect,
+            pixels,
+            stride,
+            advance: (advance / metrics.units_per_em as f32 * size).x(),
+            descent: (metrics.descent / metrics.units_per_em as f32 * size).round() as i32,
+        }
+    }
+}
 
-                o(px, py, val);
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "harfbuzz")]
+    #[test]
+    fn harfbuzz_advances() {
+        // Hack has neither ligatures nor kerning, so the advances of harfbuzz are the ones
+        // in the font
+        let font = FontCollection::default();
+        let regular = ImageFont::default().get_regular().clone();
+        let text = "fn main() { x }";
+
+        let shaped = font.shape_with_fallback(0, REGULAR, text);
+        assert_eq!(shaped.len(), text.len());
+        let mut advances = 0.0;
+        for (c, (_, glyph)) in text.chars().zip(shaped) {
+            let advance = regular
+                .advance(regular.glyph_for_char(c).unwrap())
+                .unwrap()
+                .x();
+            assert_eq!(glyph.x_advance, advance as i32 * POSITION_SCALE);
+            assert_eq!((glyph.x_offset, glyph.y_offset), (0, 0));
+            advances += advance;
         }
+
+        let units_per_em = regular.metrics().units_per_em as f32;
+        let expected = (advances * 26.0 / units_per_em).round() as i32;
+        assert!((font.get_text_len(text) as i32 - expected).abs() <= 1);
+    }
+
+    #[test]
+    fn synthesize_styles() {
+        let regular = ImageFont::default().get_regular().clone();
+        let font = ImageFont {
+            fonts: vec![(REGULAR, regular)].into_iter().collect(),
+            size: 26.0,
+        };
+        let mut font = FontCollection {
+            fonts: vec![font],
+            ..Default::default()
+        };
+        let (id, _) = font.glyph_for_char('l', REGULAR).unwrap();
+        let ink = |glyph: &CachedGlyph| glyph.pixels.iter().map(|&v| u32::from(v)).sum::<u32>();
+
+        let upright = font.get_glyph(0, REGULAR, id);
+        assert_eq!(font.get_glyph(0, BOLDITALIC, id).pixels, upright.pixels);
+
+        font.set_synthesize_styles(true);
+        let italic = font.get_glyph(0, ITALIC, id);
+        assert_ne!(italic.pixels, upright.pixels);
+        assert!(italic.raster_rect.width() > upright.raster_rect.width());
+        assert_eq!(italic.advance, upright.advance);
+
+        let bold = font.get_glyph(0, BOLD, id);
+        assert!(bold.raster_rect.width() > upright.raster_rect.width());
+        assert!(ink(&bold) > ink(&upright));
+        assert_eq!(bold.advance, upright.advance);
+        assert_eq!(font.get_glyph(0, REGULAR, id).pixels, upright.pixels);
+    }
+
+    #[test]
+    fn fractional_advances() {
+        let font = FontCollection {
+            fonts: vec![ImageFont::default()],
+            ..Default::default()
+        };
+        let text = "fn main() { println!(\"Hello, world!\"); }".repeat(10);
+        let advances: f32 = text
+            .chars()
+            .map(|c| {
+                let (id, index) = font.glyph_for_char(c, REGULAR).unwrap();
+                font.get_glyph(index, REGULAR, id).advance
+            })
+            .sum();
+        assert!(
+            (font.get_text_len(&text) as f32 - advances).abs() <= 1.0,
+            "{} {}",
+            font.get_text_len(&text),
+            advances
+        );
+    }
+
+    #[test]
+    fn monospace() {
+        assert!(ImageFont::default().is_monospace());
+        assert!(FontCollection::default().check_monospace().is_ok());
+
+        let font = test_font();
+        assert!(!font.is_monospace());
+        let font = FontCollection {
+            fonts: vec![font],
+            ..Default::default()
+        };
+        assert!(matches!(
+            font.check_monospace(),
+            Err(FontError::NotMonospace(_))
+        ));
+    }
+
+    #[test]
+    fn bidi() {
+        assert_eq!(bidi_runs("let x = 1;"), vec![(false, "let x = 1;")]);
+        assert_eq!(
+            bidi_runs("// שלום עולם!"),
+            vec![(false, "// "), (true, "שלום עולם"), (false, "!")]
+        );
+        assert_eq!(
+            bidi_runs("a שלום 1"),
+            vec![(false, "a "), (true, "שלום"), (false, " 1")]
+        );
+    }
+
+    #[test]
+    fn font_weight() {
+        assert_eq!(
+            split_weight("JetBrains Mono:300"),
+            ("JetBrains Mono", Some(300.0))
+        );
+        assert_eq!(split_weight("Hack"), ("Hack", None));
+        assert_eq!(split_weight("Foo:Bar"), ("Foo:Bar", None));
+    }
+
+    #[test]
+    fn reorder() {
+        let mut font =
+            FontCollection::new(&[("Hack", 10.0), ("Hack", 20.0), ("Hack", 30.0)]).unwrap();
+        font.reorder(&[2, 5, 2]);
+        let sizes = font.fonts.iter().map(|f| f.size).collect::<Vec<_>>();
+        assert_eq!(sizes, vec![30.0, 10.0, 20.0]);
+    }
+
+    /// The lowest row covered by the text, relative to the bottom of the line
+    fn ink_bottom(font: &FontCollection, text: &str) -> i32 {
+        let mut image = RgbaImage::new(200, 200);
+        font.draw_text_mut(&mut image, Rgba([255; 4]), 0, 0, REGULAR, text);
+        let bottom = image
+            .enumerate_pixels()
+            .filter(|(_, _, p)| p.0[3] > 0)
+            .map(|(_, y, _)| y as i32)
+            .max()
+            .unwrap();
+        bottom - font.get_font_height() as i32
+    }
+
+    /// A proportional font whose metrics differ from Hack, covering `中` which Hack lacks,
+    /// `M` and `i`. See `assets/fonts/make_test_font.py`.
+    fn test_font() -> ImageFont {
+        let bytes = include_bytes!("../assets/fonts/SiliconTest-Regular.ttf").to_vec();
+        let font = Font::from_bytes(Arc::new(bytes), 0).unwrap();
+        ImageFont {
+            fonts: vec![(REGULAR, font)].into_iter().collect(),
+            size: 26.0,
+        }
+    }
+
+    #[test]
+    fn fallback_mixed_script() {
+        let text = "中";
+        let alone = FontCollection {
+            fonts: vec![test_font()],
+            ..Default::default()
+        };
+        let fallback_width = alone.get_text_len(text);
+        let hack = FontCollection::default();
+        let ab_width = hack.get_text_len("ab");
+
+        let mixed = FontCollection {
+            fonts: vec![
+                ImageFont::default(),
+                alone.fonts.into_iter().next().unwrap(),
+            ],
+            ..Default::default()
+        };
+        let mixed_text = format!("a{}b", text);
+        let (glyphs, width) = mixed.layout(&mixed_text, REGULAR);
+        assert_eq!(glyphs.len(), 3);
+        assert_eq!(width, ab_width + fallback_width);
+    }
+
+    #[cfg(feature = "harfbuzz")]
+    #[test]
+    fn fallback_notdef() {
+        let font = FontCollection {
+            fonts: vec![ImageFont::default(), test_font()],
+            ..Default::default()
+        };
+        // shape the whole text with Hack, which has no glyph for `中`
+        let shaped = font.shape_with_fallback(0, REGULAR, "a中b");
+        let glyphs = shaped
+            .iter()
+            .map(|&(index, glyph)| (index, glyph.id))
+            .collect::<Vec<_>>();
+        let hack = font.fonts[0].get_regular();
+        let id = |c| hack.glyph_for_char(c).unwrap();
+        assert_eq!(glyphs, vec![(0, id('a')), (1, 1), (0, id('b'))]);
+        assert_eq!(shaped[1].1.x_advance, 1000 * POSITION_SCALE);
+
+        // `.notdef` is kept if no font has the glyph
+        let shaped = font.shape_with_fallback(0, REGULAR, "a文");
+        assert_eq!((shaped[1].0, shaped[1].1.id), (1, 0));
+    }
+
+    #[test]
+    fn fallback_font_baseline() {
+        let text = "中";
+        let alone = FontCollection {
+            fonts: vec![test_font()],
+            ..Default::default()
+        };
+        let expected = ink_bottom(&alone, text);
+
+        // the fallback font is not the first one, but the glyph should stay at the same place
+        let mixed = FontCollection {
+            fonts: vec![
+                ImageFont::default(),
+                alone.fonts.into_iter().next().unwrap(),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(ink_bottom(&mixed, text), expected);
+    }
+
+    #[test]
+    fn fallback_font_size() {
+        let (fallback, text) = (test_font(), "中");
+
+        // each font is rendered at its own size, not the size of the first font
+        let width = |size| {
+            let font = ImageFont {
+                fonts: fallback.fonts.clone(),
+                size,
+            };
+            let mixed = FontCollection {
+                fonts: vec![ImageFont::default(), font],
+                ..Default::default()
+            };
+            mixed.get_text_len(text)
+        };
+        let (small, large) = (width(26.0), width(52.0));
+        assert!(
+            (large as i32 - 2 * small as i32).abs() <= 2,
+            "{} {}",
+            small,
+            large
+        );
+    }
+
+    #[test]
+    fn antialiasing() {
+        let coverage = |font: &FontCollection| {
+            let mut values = vec![];
+            for glyph in font.layout("a", REGULAR).0 {
+                glyph.draw(|_, _, v| values.push(v));
+            }
+            values
+        };
+
+        let mut font = FontCollection::default();
+        assert!(coverage(&font).iter().any(|&v| v > 0.0 && v < 1.0));
+
+        font.set_antialiasing(Antialiasing::None);
+        let values = coverage(&font);
+        assert!(values.contains(&1.0));
+        assert!(values.iter().all(|&v| v == 0.0 || v == 1.0));
+    }
+
+    #[test]
+    fn styles() {
+        assert_eq!(
+            ImageFont::default().styles(),
+            [REGULAR, BOLD, ITALIC, BOLDITALIC]
+        );
+        let mut font = ImageFont::default();
+        font.fonts.remove(&ITALIC);
+        assert_eq!(font.styles(), [REGULAR, BOLD, BOLDITALIC]);
+    }
+
+    #[test]
+    fn prefer_system_fonts() {
+        type System = fn(&str) -> Result<FamilyHandle, SelectionError>;
+        let installed: System = |_| {
+            let handle = Handle::from_memory(Arc::new(vec![]), 0);
+            Ok(FamilyHandle::from_font_handles(std::iter::once(handle)))
+        };
+        let missing: System = |_| Err(SelectionError::NotFound);
+        let bundled = |registry: &FontRegistry, system: System| {
+            let family = registry.locate_family_with("Hack", system);
+            family.unwrap().is_none()
+        };
+
+        let mut registry = FontRegistry::default();
+        assert!(bundled(&registry, installed));
+        assert!(bundled(&registry, missing));
+
+        registry.set_prefer_system_fonts(true);
+        assert!(!bundled(&registry, installed));
+        // the bundled Hack is used if it's not installed
+        assert!(bundled(&registry, missing));
+        let font = ImageFont::with_registry("Hack", 26.0, &registry).unwrap();
+        assert_eq!(font.get_regular().family_name(), "Hack");
+
+        // other fonts are never bundled
+        let other = registry.locate_family_with("Fira Code", missing);
+        assert!(matches!(other, Err(FontError::SelectionError(_))));
+    }
+
+    #[test]
+    fn font_load_errors() {
+        let fonts = [("Hack", 26.0), ("No Such Font", 26.0)];
+        let (font, errors) = FontCollection::load_with_registry(&fonts, &FontRegistry::default());
+        assert_eq!(font.fonts.len(), 1);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, "No Such Font");
+
+        assert!(FontCollection::try_new(&fonts).is_ok());
+        assert!(FontCollection::try_new(&[("No Such Font", 26.0)]).is_err());
+        let empty: [(&str, f32); 0] = [];
+        assert!(matches!(
+            FontCollection::try_new(&empty),
+            Err(FontError::NoFontLoaded)
+        ));
     }
 }
@@ -1,9 +1,115 @@
 //! Format the output of syntect into an image
-use crate::error::FontError;
-use crate::font::{FontCollection, FontStyle, TextLineDrawer};
+use crate::error::{FontError, FormatError};
+use crate::font::{
+    is_rtl, Antialiasing, FontCollection, FontRegistry, FontStyle, Hinting, TextLineDrawer,
+    DEFAULT_FONT_SIZE,
+};
 use crate::utils::*;
-use image::{Rgba, RgbaImage};
-use syntect::highlighting::{Color, Style, Theme};
+use image::{DynamicImage, Pixel, Rgba, RgbaImage};
+use imageproc::drawing::draw_line_segment_mut;
+use syntect::highlighting::{self, Color, Style, Theme};
+
+mod html;
+pub use self::html::HtmlFormatter;
+
+/// Lines of code highlighted by syntect
+pub type HighlightedLines<'a> = Vec<Vec<(Style, &'a str)>>;
+
+/// The foreground used when the theme doesn't specify one
+pub const DEFAULT_FOREGROUND: Color = Color::WHITE;
+
+/// The background used when the theme doesn't specify one
+pub const DEFAULT_BACKGROUND: Color = Color {
+    r: 0x27,
+    g: 0x28,
+    b: 0x22,
+    a: 0xff,
+};
+
+/// Inclusive ranges of lines and the color to highlight them with
+pub type HighlightGroup = (Vec<(u32, u32)>, Rgba<u8>);
+
+/// A block of code with an optional title
+type Block<'a> = (Option<&'a str>, &'a [Vec<(Style, &'a str)>]);
+
+/// A backend which formats the highlighted lines into some kind of output
+pub trait Formatter {
+    type Output;
+
+    /// Format the highlighted lines with the colors of the theme
+    fn format(&mut self, v: &[Vec<(Style, &str)>], theme: &Theme) -> Self::Output;
+}
+
+/// How the line numbers are displayed
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum LineNumberMode {
+    /// Show the absolute line number
+    #[default]
+    Absolute,
+    /// Show the distance from the anchor line (the first highlighted line, or the middle line).
+    /// The anchor line itself shows its absolute number.
+    Relative,
+}
+
+/// How the line numbers are aligned in the gutter
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum LineNumberAlign {
+    Left,
+    #[default]
+    Right,
+}
+
+/// Which side of the code the gutter is placed on
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum LineNumberSide {
+    #[default]
+    Left,
+    Right,
+}
+
+/// How the code is revealed in the frames of an animation
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum AnimationMode {
+    /// Reveal the code character by character
+    #[default]
+    Typing,
+    /// Reveal the code line by line
+    Lines,
+}
+
+/// How the tabs are rendered
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum TabStyle {
+    /// Only whitespace
+    #[default]
+    Spaces,
+    /// A dimmed `→` followed by whitespace
+    Arrow,
+    /// A dimmed `·` followed by whitespace
+    Dot,
+}
+
+impl TabStyle {
+    fn indicator(self) -> Option<&'static str> {
+        match self {
+            TabStyle::Spaces => None,
+            TabStyle::Arrow => Some("→"),
+            TabStyle::Dot => Some("·"),
+        }
+    }
+}
+
+/// The base direction of the lines
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum TextDirection {
+    /// Left-to-right
+    #[default]
+    Ltr,
+    /// Right-to-left: the tokens are placed from the right edge of the code area leftward
+    Rtl,
+    /// Detected for each line by its first letter
+    Auto,
+}
 
 pub struct ImageFormatter<T> {
     /// pad between lines
@@ -26,17 +132,58 @@ pub struct ImageFormatter<T> {
     /// Width for window controls
     /// Default: 120
     window_controls_width: u32,
-    /// Height for window controls
+    /// Gap between window controls and window title
+    /// Default: 15
+    title_controls_gap: u32,
+    /// Height of the content of title bar, the larger of window controls (40) and window title
     /// Default: 40
-    window_controls_height: u32,
+    title_bar_height: u32,
     /// Window title
     window_title: Option<String>,
+    /// Caption drawn in a bar below the code
+    caption: Option<String>,
+    /// Color of window title
+    /// Default: the foreground of theme
+    title_color: Option<Rgba<u8>>,
+    /// Font of window title
+    /// Default: the font of code
+    title_font: Option<T>,
     /// show line number
     /// Default: true
     line_number: bool,
+    /// how to display the line number
+    /// Default: Absolute
+    line_number_mode: LineNumberMode,
+    /// alignment of line number
+    /// Default: Right
+    line_number_align: LineNumberAlign,
+    /// which side of the code the line number is on
+    /// Default: Left
+    line_number_side: LineNumberSide,
+    /// color of line number
+    /// Default: derived from the foreground of theme
+    line_number_color: Option<Rgba<u8>>,
+    /// draw a vertical line between the line numbers and the code
+    /// Default: false
+    line_number_separator: bool,
+    /// draw a vertical line at each indentation level
+    /// Default: false
+    indent_guides: bool,
+    /// Fit the width to the visible content, ignoring the trailing whitespace
+    /// Default: false
+    crop_to_content: bool,
+    /// Maximum size of the image in bytes, checked by `format_to_image` before allocating it
+    /// Default: None
+    max_image_bytes: Option<u64>,
     /// round corner
     /// Default: true
     round_corner: bool,
+    /// radius of the round corner
+    /// Default: 12
+    corner_radius: u32,
+    /// which corners to round
+    /// Default: all
+    round_corners: Corners,
     /// pad between code and line number
     /// Default: 6
     line_number_pad: u32,
@@ -46,43 +193,178 @@ pub struct ImageFormatter<T> {
     /// font of english character, should be mono space font
     /// Default: Hack (builtin)
     font: T,
-    /// Highlight lines
-    highlight_lines: Vec<u32>,
+    /// Background of the code area
+    /// Default: the background of theme
+    code_background: Option<Rgba<u8>>,
+    /// Highlight lines, as inclusive ranges of the positions of the rendered lines (1-based)
+    highlight_lines: Vec<(u32, u32)>,
+    /// Color of the highlighted lines, blended over the background
+    /// Default: derived from the background
+    highlight_color: Option<Rgba<u8>>,
+    /// Groups of highlighted lines with their own colors, drawn above the highlighted lines
+    highlight_groups: Vec<HighlightGroup>,
+    /// The line of the cursor, which is highlighted above the highlighted lines
+    cursor_line: Option<u32>,
+    /// Color of the cursor line
+    /// Default: the foreground of theme
+    cursor_line_color: Option<Rgba<u8>>,
+    /// Lines to focus on as inclusive ranges, the other lines are faded
+    focus_lines: Vec<(u32, u32)>,
+    /// Highlight column ranges: (line, start column, end column)
+    highlight_ranges: Vec<(u32, u32, u32)>,
+    /// Bars drawn in the gutter: (line, value in 0..=1, color)
+    gutter_bars: Vec<(u32, f32, Rgba<u8>)>,
+    /// Colors overriding the theme for the tokens of the text
+    accents: Vec<(String, Rgba<u8>)>,
+    /// Texts whose occurrences are marked with a box behind them
+    marks: Vec<String>,
+    /// Color of the boxes of marks
+    /// Default: the find highlight color of theme, or the translucent foreground
+    mark_color: Option<Rgba<u8>>,
+    /// Text selection: (start line, start column, end line, end column)
+    selection: Option<(u32, u32, u32, u32)>,
+    /// Color of the selection
+    /// Default: the selection color of theme, or the translucent foreground
+    selection_color: Option<Rgba<u8>>,
     /// Shadow adder
     shadow_adder: Option<ShadowAdder>,
     /// Tab width
     tab_width: u8,
-    /// Line Offset
+    /// How to render the tabs
+    /// Default: Spaces
+    tab_style: TabStyle,
+    /// Base direction of the lines
+    /// Default: Ltr
+    text_direction: TextDirection,
+    /// Show control characters as control pictures
+    /// Default: false
+    control_pictures: bool,
+    /// Number of the first line
+    /// Default: 0
     line_offset: u32,
+    /// Only number every Nth line, besides the first and the last one
+    /// Default: 1
+    line_number_step: u32,
+    /// Minimum number of digits of the gutter, which is kept even if the line numbers are hidden
+    /// Default: None
+    min_line_number_chars: Option<u32>,
+    /// Maximum number of frames of an animation
+    /// Default: 200
+    max_frames: usize,
+    /// Y offset of the block being drawn, used by `format_many`
+    block_top: u32,
 }
 
 #[derive(Default)]
 pub struct ImageFormatterBuilder<S> {
     /// Pad between lines
     line_pad: u32,
+    /// Height of lines relative to the font height, overrides `line_pad`
+    line_height: Option<f32>,
     /// Padding to the right of the code
     code_pad_right: u32,
+    /// Gap between window controls and window title
+    title_controls_gap: u32,
     /// Show line number
     line_number: bool,
+    /// How to display the line number
+    line_number_mode: LineNumberMode,
+    /// Alignment of line number
+    line_number_align: LineNumberAlign,
+    /// Which side of the code the line number is on
+    line_number_side: LineNumberSide,
+    /// Color of line number
+    line_number_color: Option<Rgba<u8>>,
+    /// Draw a vertical line between the line numbers and the code
+    line_number_separator: bool,
+    /// Draw a vertical line at each indentation level
+    indent_guides: bool,
+    /// Fit the width to the visible content, ignoring the trailing whitespace
+    crop_to_content: bool,
+    /// Maximum size of the image in bytes
+    max_image_bytes: Option<u64>,
     /// Font of english character, should be mono space font
     font: Vec<(S, f32)>,
+    /// Fail instead of warning if the font isn't monospaced
+    strict_monospace: bool,
+    /// Fonts loaded from user directories
+    font_registry: FontRegistry,
+    /// Hinting of the glyphs
+    hinting: Hinting,
+    /// Anti-aliasing of the glyphs
+    antialiasing: Antialiasing,
+    /// Font features of harfbuzz, eg. `calt=0`
+    font_features: Vec<String>,
+    /// Fake the italic and bold faces missing from the fonts
+    synthesize_styles: bool,
+    /// Background of the code area
+    code_background: Option<Rgba<u8>>,
     /// Highlight lines
-    highlight_lines: Vec<u32>,
+    highlight_lines: Vec<(u32, u32)>,
+    /// Match the highlight lines against the line numbers instead of the positions
+    highlight_lines_absolute: bool,
+    /// Color of the highlighted lines
+    highlight_color: Option<Rgba<u8>>,
+    /// Groups of highlighted lines with their own colors
+    highlight_groups: Vec<HighlightGroup>,
+    /// The line of the cursor
+    cursor_line: Option<u32>,
+    /// Color of the cursor line
+    cursor_line_color: Option<Rgba<u8>>,
+    /// Lines to focus on
+    focus_lines: Vec<(u32, u32)>,
+    /// Highlight column ranges
+    highlight_ranges: Vec<(u32, u32, u32)>,
+    /// Bars drawn in the gutter
+    gutter_bars: Vec<(u32, f32, Rgba<u8>)>,
+    /// Accent colors of tokens
+    accents: Vec<(String, Rgba<u8>)>,
+    /// Texts to mark
+    marks: Vec<String>,
+    /// Color of the boxes of marks
+    mark_color: Option<Rgba<u8>>,
+    /// Text selection
+    selection: Option<(u32, u32, u32, u32)>,
+    /// Color of the selection
+    selection_color: Option<Rgba<u8>>,
     /// Whether show the window controls
     window_controls: bool,
     /// Window title
     window_title: Option<String>,
+    /// Caption below the code
+    caption: Option<String>,
+    /// Color of window title
+    title_color: Option<Rgba<u8>>,
+    /// Font size of window title
+    title_font_size: Option<f32>,
     /// Whether round the corner of the image
     round_corner: bool,
+    /// Which corners to round
+    round_corners: Corners,
     /// Shadow adder,
     shadow_adder: Option<ShadowAdder>,
     /// Tab width
     tab_width: u8,
+    /// How to render the tabs
+    tab_style: TabStyle,
+    /// Base direction of the lines
+    text_direction: TextDirection,
+    /// Show control characters as control pictures
+    control_pictures: bool,
     /// Line Offset
     line_offset: u32,
+    /// Only number every Nth line
+    line_number_step: u32,
+    /// Minimum width of the gutter in digits
+    min_line_number_chars: Option<u32>,
+    /// Scale factor of the font sizes, paddings and radii
+    scale: f32,
+    /// Maximum number of frames of an animation
+    max_frames: usize,
 }
 
-// FIXME: cannot use `ImageFormatterBuilder::new().build()` bacuse cannot infer type for `S`
+// NOTE: `ImageFormatterBuilder::new().build()` cannot infer the type for `S`,
+// use `ImageFormatter::builder()` if `font` isn't called
 impl<S: AsRef<str> + Default> ImageFormatterBuilder<S> {
     pub fn new() -> Self {
         Self {
@@ -90,8 +372,12 @@ impl<S: AsRef<str> + Default> ImageFormatterBuilder<S> {
             line_number: true,
             window_controls: true,
             window_title: None,
+            title_controls_gap: 15,
             round_corner: true,
             tab_width: 4,
+            line_number_step: 1,
+            scale: 1.0,
+            max_frames: 200,
             ..Default::default()
         }
     }
@@ -102,30 +388,147 @@ impl<S: AsRef<str> + Default> ImageFormatterBuilder<S> {
         self
     }
 
-    /// Set Line offset
+    /// Set how to display the line number
+    pub fn line_number_mode(mut self, mode: LineNumberMode) -> Self {
+        self.line_number_mode = mode;
+        self
+    }
+
+    /// Set the alignment of line number
+    pub fn line_number_align(mut self, align: LineNumberAlign) -> Self {
+        self.line_number_align = align;
+        self
+    }
+
+    /// Set which side of the code the line number is on
+    pub fn line_number_side(mut self, side: LineNumberSide) -> Self {
+        self.line_number_side = side;
+        self
+    }
+
+    /// Set the color of line number. If not set, it will be derived from the theme foreground.
+    pub fn line_number_color(mut self, color: Option<Rgba<u8>>) -> Self {
+        self.line_number_color = color;
+        self
+    }
+
+    /// Whether draw a vertical line between the line numbers and the code
+    pub fn line_number_separator(mut self, show: bool) -> Self {
+        self.line_number_separator = show;
+        self
+    }
+
+    /// Whether to draw a vertical line at each indentation level
+    pub fn indent_guides(mut self, show: bool) -> Self {
+        self.indent_guides = show;
+        self
+    }
+
+    /// Fit the width of the code area to the visible content, so the trailing whitespace
+    /// doesn't widen the image, and drop the minimum width which is only kept for the
+    /// window controls.
+    pub fn crop_to_content(mut self, crop: bool) -> Self {
+        self.crop_to_content = crop;
+        self
+    }
+
+    /// Set the maximum size of the image in bytes (4 bytes per pixel). `format_to_image`
+    /// returns an error instead of allocating a larger image. Default: no limit
+    pub fn max_image_bytes(mut self, max: Option<u64>) -> Self {
+        self.max_image_bytes = max;
+        self
+    }
+
+    /// Set the number of the first line
     pub fn line_offset(mut self, offset: u32) -> Self {
         self.line_offset = offset;
         self
     }
 
+    /// Only show the numbers which are multiples of `step`, and the ones of the first and the
+    /// last line. The gutter of the other lines is left blank.
+    pub fn line_number_step(mut self, step: u32) -> Self {
+        self.line_number_step = step;
+        self
+    }
+
+    /// Reserve the gutter for at least `chars` digits, even if the line numbers are hidden, so
+    /// that the code lines up across images with and without line numbers. Default: None
+    pub fn min_line_number_chars(mut self, chars: Option<u32>) -> Self {
+        self.min_line_number_chars = chars;
+        self
+    }
+
     /// Set the pad between lines
     pub fn line_pad(mut self, pad: u32) -> Self {
         self.line_pad = pad;
         self
     }
 
+    /// Set the height of lines as a multiple of the font height, eg. 1.4. It overrides
+    /// `line_pad`, so that the spacing keeps the same look with any size of font.
+    /// The values less than 1.0 are treated as 1.0.
+    pub fn line_height(mut self, height: Option<f32>) -> Self {
+        self.line_height = height;
+        self
+    }
+
     /// Set the pad on the right of the screen
     pub fn code_pad_right(mut self, pad: u32) -> Self {
         self.code_pad_right = pad;
         self
     }
 
+    /// Set the gap between the window controls and the window title
+    pub fn title_controls_gap(mut self, gap: u32) -> Self {
+        self.title_controls_gap = gap;
+        self
+    }
+
     /// Set the font
     pub fn font(mut self, fonts: Vec<(S, f32)>) -> Self {
         self.font = fonts;
         self
     }
 
+    /// Fail to build if the first font isn't monospaced, instead of printing a warning.
+    pub fn strict_monospace(mut self, strict: bool) -> Self {
+        self.strict_monospace = strict;
+        self
+    }
+
+    /// Set the fonts loaded from user directories, they are preferred over the system fonts
+    pub fn font_registry(mut self, registry: FontRegistry) -> Self {
+        self.font_registry = registry;
+        self
+    }
+
+    /// Set how the glyph outlines are fitted to the pixel grid, which sharpens small text
+    pub fn hinting(mut self, hinting: Hinting) -> Self {
+        self.hinting = hinting;
+        self
+    }
+
+    /// Set how the edges of the glyphs are smoothed
+    pub fn antialiasing(mut self, antialiasing: Antialiasing) -> Self {
+        self.antialiasing = antialiasing;
+        self
+    }
+
+    /// Set the font features applied by harfbuzz, eg. `calt=0` to disable the ligatures of
+    /// Fira Code, or `ss01` to enable a stylistic set. Ignored without the `harfbuzz` feature.
+    pub fn font_features(mut self, features: Vec<String>) -> Self {
+        self.font_features = features;
+        self
+    }
+
+    /// Fake the italic and bold faces missing from the fonts, by slanting and thickening the
+    /// regular glyphs. Otherwise the regular face is used for them.
+    pub fn synthesize_styles(mut self, synthesize: bool) -> Self {
+        self.synthesize_styles = synthesize;
+        self
+    }
+
     /// Whether show the windows controls
     pub fn window_controls(mut self, show: bool) -> Self {
         self.window_controls = show;
@@ -138,12 +541,36 @@ impl<S: AsRef<str> + Default> ImageFormatterBuilder<S> {
         self
     }
 
+    /// Caption drawn in a bar below the code, with the font and color of window title
+    pub fn caption(mut self, caption: Option<String>) -> Self {
+        self.caption = caption;
+        self
+    }
+
+    /// Set the color of window title. If not set, the foreground of theme will be used.
+    pub fn title_color(mut self, color: Option<Rgba<u8>>) -> Self {
+        self.title_color = color;
+        self
+    }
+
+    /// Set the font size of window title. If not set, the size of the code font will be used.
+    pub fn title_font_size(mut self, size: Option<f32>) -> Self {
+        self.title_font_size = size;
+        self
+    }
+
     /// Whether round the corner
     pub fn round_corner(mut self, b: bool) -> Self {
         self.round_corner = b;
         self
     }
 
+    /// Set which corners to round
+    pub fn round_corners(mut self, corners: Corners) -> Self {
+        self.round_corners = corners;
+        self
+    }
+
     /// Add the shadow
     pub fn shadow_adder(mut self, adder: ShadowAdder) -> Self {
         self.shadow_adder = Some(adder);
@@ -152,7 +579,121 @@ impl<S: AsRef<str> + Default> ImageFormatterBuilder<S> {
 
     /// Set the lines to highlight.
     pub fn highlight_lines(mut self, lines: Vec<u32>) -> Self {
-        self.highlight_lines = lines;
+        self.highlight_lines = lines.into_iter().map(|n| (n, n)).collect();
+        self
+    }
+
+    /// Set the ranges of lines to highlight, both ends are inclusive.
+    /// Use `u32::MAX` as the end to highlight until the last line.
+    pub fn highlight_line_ranges(mut self, ranges: Vec<(u32, u32)>) -> Self {
+        self.highlight_lines = ranges;
+        self
+    }
+
+    /// Match the highlight lines against the line numbers shown in the gutter, which start
+    /// from `line_offset`, instead of the positions of the rendered lines starting from 1.
+    pub fn highlight_lines_absolute(mut self, absolute: bool) -> Self {
+        self.highlight_lines_absolute = absolute;
+        self
+    }
+
+    /// Set the background of the code area, overriding the background of theme.
+    /// A transparent color lets the background behind the image show through.
+    pub fn code_background(mut self, color: Option<Rgba<u8>>) -> Self {
+        self.code_background = color;
+        self
+    }
+
+    /// Set the color of the highlighted lines, which is alpha blended over the background.
+    /// If not set, it will be derived from the background.
+    pub fn highlight_color(mut self, color: Option<Rgba<u8>>) -> Self {
+        self.highlight_color = color;
+        self
+    }
+
+    /// Set groups of line ranges to highlight, each with its own color. The groups are drawn
+    /// in order above the highlighted lines, so the colors of overlapping groups are blended.
+    pub fn highlight_groups(mut self, groups: Vec<HighlightGroup>) -> Self {
+        self.highlight_groups = groups;
+        self
+    }
+
+    /// Set the line of the cursor (1-based). It's drawn with a stronger highlight and a bar
+    /// on the left, above the highlighted lines.
+    pub fn cursor_line(mut self, line: Option<u32>) -> Self {
+        self.cursor_line = line;
+        self
+    }
+
+    /// Set the color of the cursor line. The bar uses it as is, and the line is filled with it
+    /// at a quarter of its opacity. If not set, the foreground of theme will be used.
+    pub fn cursor_line_color(mut self, color: Option<Rgba<u8>>) -> Self {
+        self.cursor_line_color = color;
+        self
+    }
+
+    /// Set the lines to focus on, the other lines will be faded into the background.
+    pub fn focus_lines(mut self, lines: Vec<u32>) -> Self {
+        self.focus_lines = lines.into_iter().map(|n| (n, n)).collect();
+        self
+    }
+
+    /// Set the ranges of lines to focus on, both ends are inclusive.
+    /// Use `u32::MAX` as the end to focus until the last line.
+    pub fn focus_line_ranges(mut self, ranges: Vec<(u32, u32)>) -> Self {
+        self.focus_lines = ranges;
+        self
+    }
+
+    /// Set the column ranges to highlight.
+    ///
+    /// Each item is `(line, start, end)`, columns are 1-based and inclusive.
+    /// A tab counts as one column.
+    pub fn highlight_ranges(mut self, ranges: Vec<(u32, u32, u32)>) -> Self {
+        self.highlight_ranges = ranges;
+        self
+    }
+
+    /// Draw a bar in the gutter for each line, whose width is proportional to its value.
+    ///
+    /// Each item is `(line, value, color)`, where `value` is in `0.0..=1.0`.
+    pub fn gutter_bars(mut self, bars: Vec<(u32, f32, Rgba<u8>)>) -> Self {
+        self.gutter_bars = bars;
+        self
+    }
+
+    /// Recolor the tokens whose text (without the surrounding whitespace) is exactly the
+    /// given text, eg. all occurrences of a function name. The first matching item wins.
+    pub fn accents(mut self, accents: Vec<(String, Rgba<u8>)>) -> Self {
+        self.accents = accents;
+        self
+    }
+
+    /// Draw a box behind every occurrence of the texts, like the matches of a search.
+    pub fn marks(mut self, marks: Vec<String>) -> Self {
+        self.marks = marks;
+        self
+    }
+
+    /// Set the color of the boxes of marks. If not set, the find highlight color of theme
+    /// will be used, or the foreground of theme at a quarter of its opacity.
+    pub fn mark_color(mut self, color: Option<Rgba<u8>>) -> Self {
+        self.mark_color = color;
+        self
+    }
+
+    /// Draw a text selection from (start line, start column) to (end line, end column), like
+    /// the one of an editor. The columns are 1-based and inclusive, the lines between the
+    /// first and the last one are selected entirely.
+    pub fn selection(mut self, selection: Option<(u32, u32, u32, u32)>) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    /// Set the color of the selection. If not set, the selection color of theme will be
+    /// used, or the foreground of theme at a quarter of its opacity.
+    pub fn selection_color(mut self, color: Option<Rgba<u8>>) -> Self {
+        self.selection_color = color;
         self
     }
 
@@ -162,36 +703,353 @@ impl<S: AsRef<str> + Default> ImageFormatterBuilder<S> {
         self
     }
 
+    /// Set how to render the tabs
+    pub fn tab_style(mut self, style: TabStyle) -> Self {
+        self.tab_style = style;
+        self
+    }
+
+    /// Set the base direction of the lines.
+    ///
+    /// The right-to-left lines are right aligned, and their tokens are placed from right to left.
+    /// Note that the column ranges are not supported on those lines.
+    pub fn text_direction(mut self, direction: TextDirection) -> Self {
+        self.text_direction = direction;
+        self
+    }
+
+    /// Whether replace the non-printable control characters (eg. NUL, ESC) with their
+    /// Unicode control pictures (eg. `␀`, `␛`) in a dimmed color
+    pub fn control_pictures(mut self, show: bool) -> Self {
+        self.control_pictures = show;
+        self
+    }
+
+    /// Scale the output by a factor, eg. 2.0 for high DPI screens.
+    ///
+    /// It multiplies the font sizes (including the window title), the line pad, the code pads,
+    /// the title bar and window controls, the pad of line number, the radius of round corner,
+    /// and the paddings, offsets and blur radius of the shadow adder. Default: 1.0
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Set the maximum number of frames of `format_frames`, including the empty first one
+    /// and the full last one. Above it, each frame reveals several characters or lines,
+    /// so that the frames stay evenly spaced. It's at least 2. Default: 200
+    pub fn max_frames(mut self, max_frames: usize) -> Self {
+        self.max_frames = max_frames;
+        self
+    }
+
     pub fn build(self) -> Result<ImageFormatter<FontCollection>, FontError> {
-        let font = if self.font.is_empty() {
-            FontCollection::default()
+        let scale = self.scale;
+        let px = |value: u32| (value as f32 * scale).round() as u32;
+
+        // the line numbered `line_offset` is rendered at position 1, and
+        // the lines before it become 0 which matches nothing
+        let (absolute, line_offset) = (self.highlight_lines_absolute, self.line_offset);
+        let to_positions = |ranges: Vec<(u32, u32)>| -> Vec<(u32, u32)> {
+            if !absolute {
+                return ranges;
+            }
+            let to_position = |n: u32| n.saturating_add(1).saturating_sub(line_offset);
+            ranges
+                .iter()
+                .map(|&(start, end)| (to_position(start), to_position(end)))
+                .collect()
+        };
+        let highlight_lines = to_positions(self.highlight_lines);
+        let highlight_groups = self
+            .highlight_groups
+            .into_iter()
+            .map(|(ranges, color)| (to_positions(ranges), color))
+            .collect();
+
+        let mut font = if self.font.is_empty() {
+            let size = DEFAULT_FONT_SIZE * scale;
+            FontCollection::with_registry(&[("Hack", size)], &self.font_registry)?
         } else {
-            FontCollection::new(&self.font)?
+            let fonts = self
+                .font
+                .iter()
+                .map(|(name, size)| (name.as_ref(), size * scale))
+                .collect::<Vec<_>>();
+            FontCollection::with_registry(&fonts, &self.font_registry)?
+        };
+
+        match font.check_monospace() {
+            Err(err) if self.strict_monospace => return Err(err),
+            Err(FontError::NotMonospace(name)) => eprintln!(
+                "[warning] `{}` is not a monospace font, the code may be misaligned",
+                name
+            ),
+            _ => (),
+        }
+
+        // the same fonts as the code, with a different size
+        let mut title_font = match self.title_font_size.map(|size| size * scale) {
+            Some(size) if self.font.is_empty() => Some(FontCollection::with_registry(
+                &[("Hack", size)],
+                &self.font_registry,
+            )?),
+            Some(size) => {
+                let fonts = self
+                    .font
+                    .iter()
+                    .map(|(name, _)| (name.as_ref(), size))
+                    .collect::<Vec<_>>();
+                Some(FontCollection::with_registry(&fonts, &self.font_registry)?)
+            }
+            None => None,
         };
 
+        let fonts = std::iter::once(&mut font).chain(title_font.iter_mut());
+        for font in fonts {
+            font.set_hinting(self.hinting);
+            font.set_antialiasing(self.antialiasing);
+            font.set_font_features(self.font_features.clone());
+            font.set_synthesize_styles(self.synthesize_styles);
+        }
+
         let title_bar = self.window_controls || self.window_title.is_some();
+        let line_pad = match self.line_height {
+            Some(height) => {
+                (font.get_font_height() as f32 * (height.max(1.0) - 1.0)).round() as u32
+            }
+            None => px(self.line_pad),
+        };
 
-        Ok(ImageFormatter {
-            line_pad: self.line_pad,
-            code_pad: 25,
-            code_pad_top: if title_bar { 50 } else { 0 },
-            code_pad_right: self.code_pad_right,
-            title_bar_pad: 15,
+        let mut formatter = ImageFormatter {
+            line_pad,
+            code_pad: px(25),
+            code_pad_top: if title_bar { px(50) } else { 0 },
+            code_pad_right: px(self.code_pad_right),
+            title_bar_pad: px(15),
             window_controls: self.window_controls,
-            window_controls_width: 120,
-            window_controls_height: 40,
+            window_controls_width: px(120),
+            title_controls_gap: px(self.title_controls_gap),
+            title_bar_height: px(40),
             window_title: self.window_title,
+            caption: self.caption,
+            title_color: self.title_color,
+            title_font,
             line_number: self.line_number,
-            line_number_pad: 6,
+            line_number_mode: self.line_number_mode,
+            line_number_align: self.line_number_align,
+            line_number_side: self.line_number_side,
+            line_number_color: self.line_number_color,
+            line_number_separator: self.line_number_separator,
+            indent_guides: self.indent_guides,
+            crop_to_content: self.crop_to_content,
+            max_image_bytes: self.max_image_bytes,
+            line_number_pad: px(6),
             line_number_chars: 0,
-            highlight_lines: self.highlight_lines,
+            code_background: self.code_background,
+            highlight_lines,
+            highlight_color: self.highlight_color,
+            highlight_groups,
+            cursor_line: self.cursor_line,
+            cursor_line_color: self.cursor_line_color,
+            focus_lines: self.focus_lines,
+            highlight_ranges: self.highlight_ranges,
+            gutter_bars: self.gutter_bars,
+            accents: self.accents,
+            marks: self.marks,
+            mark_color: self.mark_color,
+            selection: self.selection,
+            selection_color: self.selection_color,
             round_corner: self.round_corner,
-            shadow_adder: self.shadow_adder,
+            corner_radius: px(12),
+            round_corners: self.round_corners,
+            shadow_adder: self.shadow_adder.map(|adder| adder.scale(scale)),
             tab_width: self.tab_width,
+            tab_style: self.tab_style,
+            text_direction: self.text_direction,
+            control_pictures: self.control_pictures,
             font,
             line_offset: self.line_offset,
+            line_number_step: self.line_number_step.max(1),
+            min_line_number_chars: self.min_line_number_chars,
+            max_frames: self.max_frames.max(2),
+            block_top: 0,
+        };
+
+        // the title bar grows with a title taller than the window controls
+        if let Some(title) = formatter.window_title.clone() {
+            let title_height = formatter.title_font().height(&title);
+            if title_height > formatter.title_bar_height {
+                formatter.code_pad_top += title_height - formatter.title_bar_height;
+                formatter.title_bar_height = title_height;
+            }
+        }
+
+        Ok(formatter)
+    }
+}
+
+/// Get the control picture of a non-printable control character
+fn control_picture(c: char) -> Option<char> {
+    match c {
+        '\t' | '\n' | '\r' => None,
+        '\x00'..='\x1f' => char::from_u32(0x2400 + c as u32),
+        '\x7f' => Some('\u{2421}'),
+        _ => None,
+    }
+}
+
+/// Whether the line is in any of the inclusive ranges
+fn in_ranges(ranges: &[(u32, u32)], line: u32) -> bool {
+    ranges
+        .iter()
+        .any(|&(start, end)| (start..=end).contains(&line))
+}
+
+/// Get the number of revealed characters of each frame, starting from an empty frame.
+///
+/// The whitespace is revealed together with the next visible character.
+fn reveal_steps(v: &[Vec<(Style, &str)>], mode: AnimationMode) -> Vec<usize> {
+    let mut steps = vec![0];
+    let mut count = 0;
+    for line in v {
+        let mut visible = false;
+        for c in line.iter().flat_map(|(_, text)| text.chars()) {
+            count += 1;
+            if !c.is_whitespace() {
+                visible = true;
+                if mode == AnimationMode::Typing {
+                    steps.push(count);
+                }
+            }
+        }
+        if mode == AnimationMode::Lines && visible {
+            steps.push(count);
+        }
+    }
+    steps
+}
+
+/// Keep at most `max_frames` (at least 2) of the steps, evenly spaced, including the first and
+/// the last one
+fn limit_steps(steps: Vec<usize>, max_frames: usize) -> Vec<usize> {
+    if steps.len() <= max_frames {
+        return steps;
+    }
+    let last = *steps.last().unwrap();
+    let stride = (steps.len() - 1).div_ceil(max_frames - 1);
+    let mut result = steps.into_iter().step_by(stride).collect::<Vec<_>>();
+    if result.last() != Some(&last) {
+        result.push(last);
+    }
+    result
+}
+
+/// Make the characters after the first `count` ones transparent,
+/// so that the layout is the same as the one of the full code
+fn hide_after<'a>(v: &[Vec<(Style, &'a str)>], count: usize) -> HighlightedLines<'a> {
+    let mut rest = count;
+    v.iter()
+        .map(|line| {
+            let mut result = vec![];
+            for &(style, text) in line {
+                let len = text.chars().count();
+                if rest >= len {
+                    rest -= len;
+                    result.push((style, text));
+                    continue;
+                }
+                let split = text.char_indices().nth(rest).map_or(text.len(), |(i, _)| i);
+                let (shown, hidden) = text.split_at(split);
+                if !shown.is_empty() {
+                    result.push((style, shown));
+                }
+                let mut style = style;
+                style.foreground.a = 0;
+                result.push((style, hidden));
+                rest = 0;
+            }
+            result
         })
+        .collect()
+}
+
+/// Get the indentation of the line in columns, a tab is as wide as `tab_width` spaces.
+///
+/// Return `None` for blank lines.
+fn indent_columns(tokens: &[(Style, &str)], tab_width: u32) -> Option<u32> {
+    let mut columns = 0;
+    for c in tokens.iter().flat_map(|(_, text)| text.chars()) {
+        match c {
+            ' ' => columns += 1,
+            '\t' => columns += tab_width,
+            '\n' | '\r' => return None,
+            _ => return Some(columns),
+        }
+    }
+    None
+}
+
+/// Get the indentation guides as `(column, first line, last line)`, the lines are 0-based.
+///
+/// A line indented by `n` levels gets the guides of the levels `0..n`, and the guides of
+/// consecutive lines are joined. A blank line takes the smaller indentation of the lines around it.
+fn indent_guides(indents: &[Option<u32>], tab_width: u32) -> Vec<(u32, u32, u32)> {
+    if tab_width == 0 {
+        return vec![];
+    }
+    let levels = (0..indents.len()).map(|i| {
+        let columns = indents[i].unwrap_or_else(|| {
+            let prev = indents[..i].iter().rev().find_map(|&n| n).unwrap_or(0);
+            let next = indents[i + 1..].iter().find_map(|&n| n).unwrap_or(0);
+            prev.min(next)
+        });
+        columns / tab_width
+    });
+
+    let mut guides = vec![];
+    // the first line of the guide of each open level
+    let mut open: Vec<u32> = vec![];
+    for (i, level) in levels.chain(std::iter::once(0)).enumerate() {
+        while open.len() as u32 > level {
+            let start = open.pop().unwrap();
+            guides.push((open.len() as u32 * tab_width, start, i as u32 - 1));
+        }
+        while (open.len() as u32) < level {
+            open.push(i as u32);
+        }
+    }
+    guides
+}
+
+/// Dim the color by mixing it with gray
+fn dim_color(color: Color) -> Color {
+    let dim = |c: u8| ((u16::from(c) + 0x80) / 2) as u8;
+    Color {
+        r: dim(color.r),
+        g: dim(color.g),
+        b: dim(color.b),
+        a: color.a,
+    }
+}
+
+/// Split the text into runs of the same color,
+/// the control characters are replaced with control pictures in a dimmed color
+fn split_control_chars(text: &str, color: Color) -> Vec<(Color, String)> {
+    let dimmed = dim_color(color);
+
+    let mut result: Vec<(Color, String)> = vec![];
+    for c in text.chars() {
+        let (color, c) = match control_picture(c) {
+            Some(picture) => (dimmed, picture),
+            None => (color, c),
+        };
+        match result.last_mut() {
+            Some((last, s)) if *last == color => s.push(c),
+            _ => result.push((color, c.to_string())),
+        }
     }
+    result
 }
 
 struct Drawable {
@@ -199,8 +1057,63 @@ struct Drawable {
     max_width: u32,
     /// max number of line of the picture
     max_lineno: u32,
+    /// arguments for draw_text_mut, the style keeps the underline which isn't a font style
+    drawables: Vec<(u32, u32, Option<Color>, highlighting::FontStyle, String)>,
+}
+
+/// The layout metrics of an image, see `ImageFormatter::metrics`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metrics {
+    /// Width of the image, including the padding of the shadow adder
+    pub width: u32,
+    /// Height of the image, including the padding of the shadow adder
+    pub height: u32,
+    /// Number of lines of code in all blocks
+    pub lines: usize,
+    /// Number of digits reserved for the line numbers
+    pub line_number_chars: u32,
+    /// Distance between the left edge of the panel and the code
+    pub left_pad: u32,
+    /// Height of the font, without the line padding
+    pub font_height: u32,
+}
+
+/// The measured layout of the blocks, before anything is drawn
+struct Layout {
+    /// size of the panel
+    size: (u32, u32),
+    /// `(top, max_lineno)` of each block
+    blocks: Vec<(u32, u32)>,
+    /// y coordinates of the separators between blocks
+    separators: Vec<u32>,
     /// arguments for draw_text_mut
-    drawables: Vec<(u32, u32, Option<Color>, FontStyle, String)>,
+    drawables: Vec<(u32, u32, Option<Color>, highlighting::FontStyle, String)>,
+    title: Option<Drawable>,
+    caption: Option<Drawable>,
+}
+
+impl ImageFormatter<FontCollection> {
+    /// Create a builder whose font names are `String`s.
+    ///
+    /// Unlike `ImageFormatterBuilder::new()`, the type of the builder doesn't need to be
+    /// annotated when `font` isn't called.
+    ///
+    /// ```
+    /// use silicon::formatter::ImageFormatter;
+    ///
+    /// let formatter = ImageFormatter::builder()
+    ///     .line_number(false)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let with_fonts = ImageFormatter::builder()
+    ///     .font(vec![("Hack".to_owned(), 26.0)])
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn builder() -> ImageFormatterBuilder<String> {
+        ImageFormatterBuilder::new()
+    }
 }
 
 impl<T: TextLineDrawer> ImageFormatter<T> {
@@ -211,25 +1124,51 @@ impl<T: TextLineDrawer> ImageFormatter<T> {
 
     /// calculate the Y coordinate of a line
     fn get_line_y(&mut self, lineno: u32) -> u32 {
-        lineno * self.get_line_height() + self.code_pad + self.code_pad_top
+        lineno * self.get_line_height() + self.code_pad + self.code_pad_top + self.block_top
     }
 
     /// calculate the size of code area
     fn get_image_size(&mut self, max_width: u32, lineno: u32) -> (u32, u32) {
+        let right_gutter = match self.line_number_side {
+            LineNumberSide::Left => 0,
+            LineNumberSide::Right => self.get_gutter_width(),
+        };
+        let min_width = match (self.crop_to_content, self.window_controls) {
+            (false, _) => 150,
+            (true, true) => self.window_controls_width + self.title_bar_pad * 2,
+            (true, false) => 0,
+        };
         (
-            (max_width + self.code_pad_right).max(150),
-            self.get_line_y(lineno + 1) + self.code_pad,
+            (max_width + right_gutter + self.code_pad_right).max(min_width),
+            self.get_line_y(lineno + 1) + self.code_pad + self.get_caption_height(),
         )
     }
 
+    /// Calculate the height of the caption bar
+    fn get_caption_height(&mut self) -> u32 {
+        match self.caption.clone() {
+            Some(caption) => self.title_font().height(&caption) + self.title_bar_pad * 2,
+            None => 0,
+        }
+    }
+
+    /// Calculate the width of line number area, including the pad between it and the code
+    fn get_gutter_width(&mut self) -> u32 {
+        if self.line_number || self.min_line_number_chars.is_some() {
+            // measure the digits instead of the padding spaces, which may be narrower
+            let tmp = "0".repeat(self.line_number_chars as usize);
+            2 * self.line_number_pad + self.font.width(&tmp)
+        } else {
+            0
+        }
+    }
+
     /// Calculate where code start
     fn get_left_pad(&mut self) -> u32 {
         self.code_pad
-            + if self.line_number {
-                let tmp = format!("{:>width$}", 0, width = self.line_number_chars as usize);
-                2 * self.line_number_pad + self.font.width(&tmp)
-            } else {
-                0
+            + match self.line_number_side {
+                LineNumberSide::Left => self.get_gutter_width(),
+                LineNumberSide::Right => 0,
             }
     }
 
@@ -239,53 +1178,103 @@ impl<T: TextLineDrawer> ImageFormatter<T> {
         let tab = " ".repeat(self.tab_width as usize);
         let mut drawables = vec![];
         let (mut max_width, mut max_lineno) = (0, 0);
+        // the right edge of the visible text, which excludes the trailing whitespace,
+        // the gutter is always kept even if there is no text
+        let mut content_width = self.get_left_pad();
+        // (start, end, width) of the drawables of right-to-left lines
+        let mut rtl_lines = vec![];
 
         for (i, tokens) in v.iter().enumerate() {
             let height = self.get_line_y(i as u32);
             let mut width = self.get_left_pad();
+            let line_start = drawables.len();
+            // the width of each drawable of this line
+            let mut widths = vec![];
+            let rtl = self.is_rtl_line(tokens);
 
             for (style, text) in tokens {
-                let text = text.trim_end_matches('\n').replace('\t', &tab);
+                // the `\r` of `\r\n` may be a separate token
+                let text = text.trim_end_matches(['\r', '\n']);
                 if text.is_empty() {
                     continue;
                 }
 
-                drawables.push((
-                    width,
-                    height,
-                    Some(style.foreground),
-                    style.font_style.into(),
-                    text.to_owned(),
-                ));
+                // split the text by tabs if the tabs need to be drawn
+                let pieces = match self.tab_style.indicator() {
+                    None => vec![text.replace('\t', &tab)],
+                    Some(_) => text.split('\t').map(str::to_owned).collect(),
+                };
+
+                for (i, piece) in pieces.into_iter().enumerate() {
+                    if i > 0 {
+                        // the indicator is followed by whitespace, so the width is always `tab`
+                        let indicator = self.tab_style.indicator().unwrap().to_owned();
+                        let color = dim_color(style.foreground);
+                        let style = highlighting::FontStyle::empty();
+                        drawables.push((width, height, Some(color), style, indicator));
+                        widths.push(self.font.width(&tab));
+                        width += self.font.width(&tab);
+                        max_width = max_width.max(width);
+                        content_width = content_width.max(width);
+                    }
+                    if piece.is_empty() {
+                        continue;
+                    }
 
-                width += self.font.width(&text);
+                    let segments = if self.control_pictures {
+                        split_control_chars(&piece, style.foreground)
+                    } else {
+                        vec![(style.foreground, piece)]
+                    };
 
-                max_width = max_width.max(width);
+                    for (color, text) in segments {
+                        let color = self.accent_color(&text).unwrap_or(color);
+                        let text_width = self.font.width(&text);
+                        if rtl {
+                            // the whitespace around the text is mirrored with it
+                            let trimmed = text.trim_start();
+                            let x = width + self.font.width(&text[..text.len() - trimmed.len()]);
+                            let trimmed = trimmed.trim_end().to_owned();
+                            widths.push(self.font.width(&trimmed));
+                            drawables.push((x, height, Some(color), style.font_style, trimmed));
+                        } else {
+                            let visible = text.trim_end();
+                            if !visible.is_empty() {
+                                let right = width + self.font.width(visible);
+                                content_width = content_width.max(right);
+                            }
+                            drawables.push((width, height, Some(color), style.font_style, text));
+                            widths.push(text_width);
+                        }
+
+                        width += text_width;
+
+                        max_width = max_width.max(width);
+                    }
+                }
+            }
+            if rtl {
+                // mirror the tokens inside the line, the widths are rounded one by one, so
+                // the indent and the trimmed text may be 1px wider than the whole token
+                let left_pad = self.get_left_pad();
+                for (drawable, w) in drawables[line_start..].iter_mut().zip(widths) {
+                    drawable.0 = left_pad + (width - drawable.0).saturating_sub(w);
+                }
+                rtl_lines.push((line_start, drawables.len(), width));
+                content_width = content_width.max(width);
             }
             max_lineno = i as u32;
         }
 
-        if self.window_title.is_some() {
-            let title = self.window_title.as_ref().unwrap();
-            let title_width = self.font.width(title);
-
-            let ctrls_offset = if self.window_controls {
-                self.window_controls_width + self.title_bar_pad
-            } else {
-                0
-            };
-            let ctrls_center = self.window_controls_height / 2;
-
-            drawables.push((
-                ctrls_offset + self.title_bar_pad,
-                self.title_bar_pad + ctrls_center - self.font.height(" ") / 2,
-                None,
-                FontStyle::BOLD,
-                title.to_string(),
-            ));
+        if self.crop_to_content {
+            max_width = content_width;
+        }
 
-            let title_bar_width = ctrls_offset + title_width + self.title_bar_pad * 2;
-            max_width = max_width.max(title_bar_width);
+        // align the right-to-left lines to the right
+        for (start, end, width) in rtl_lines {
+            for drawable in &mut drawables[start..end] {
+                drawable.0 += max_width - width;
+            }
         }
 
         Drawable {
@@ -295,100 +1284,1970 @@ impl<T: TextLineDrawer> ImageFormatter<T> {
         }
     }
 
-    fn draw_line_number(&mut self, image: &mut RgbaImage, lineno: u32, mut color: Rgba<u8>) {
-        for i in color.0.iter_mut() {
-            *i = (*i).saturating_sub(20);
-        }
-        for i in 0..=lineno {
-            let line_number = format!(
-                "{:>width$}",
-                i + self.line_offset,
-                width = self.line_number_chars as usize
-            );
-            let y = self.get_line_y(i);
-            self.font.draw_text(
-                image,
-                color,
-                self.code_pad,
-                y,
-                FontStyle::REGULAR,
-                &line_number,
-            );
+    /// Get the accent color of a token, if any
+    fn accent_color(&self, text: &str) -> Option<Color> {
+        let text = text.trim();
+        self.accents
+            .iter()
+            .find(|(accent, _)| accent == text)
+            .map(|(_, color)| Color {
+                r: color.0[0],
+                g: color.0[1],
+                b: color.0[2],
+                a: color.0[3],
+            })
+    }
+
+    /// get the font of window title
+    fn title_font(&mut self) -> &mut T {
+        match &mut self.title_font {
+            Some(font) => font,
+            None => &mut self.font,
         }
     }
 
-    fn highlight_lines<I: IntoIterator<Item = u32>>(&mut self, image: &mut RgbaImage, lines: I) {
-        let width = image.width();
-        let height = self.get_line_height();
-        let color = image.get_pixel_mut(20, 20);
+    /// create the drawable of window title, it should be drawn with `title_font`
+    fn create_title_drawable(&mut self) -> Option<Drawable> {
+        let title = self.window_title.clone()?;
+        let title_width = self.title_font().width(&title);
+        let title_height = self.title_font().height(&title);
+
+        let ctrls_offset = if self.window_controls {
+            self.window_controls_width + self.title_controls_gap
+        } else {
+            0
+        };
+        let ctrls_center = self.title_bar_height / 2;
 
-        for i in color.0.iter_mut() {
-            *i = (*i).saturating_add(40);
+        let drawable = (
+            self.title_bar_pad + ctrls_offset,
+            (self.title_bar_pad + ctrls_center).saturating_sub(title_height / 2),
+            None,
+            highlighting::FontStyle::BOLD,
+            title,
+        );
+
+        Some(Drawable {
+            max_width: ctrls_offset + title_width + self.title_bar_pad * 2,
+            max_lineno: 0,
+            drawables: vec![drawable],
+        })
+    }
+
+    /// create the drawable of caption at the bottom of an image of the given height,
+    /// it should be drawn with `title_font`
+    fn create_caption_drawable(&mut self, height: u32) -> Option<Drawable> {
+        let caption = self.caption.clone()?;
+        let caption_width = self.title_font().width(&caption);
+        let y = height - self.get_caption_height() + self.title_bar_pad;
+
+        Some(Drawable {
+            max_width: self.code_pad + caption_width,
+            max_lineno: 0,
+            drawables: vec![(
+                self.code_pad,
+                y,
+                None,
+                highlighting::FontStyle::empty(),
+                caption,
+            )],
+        })
+    }
+
+    fn draw_line_number(&mut self, image: &mut RgbaImage, lineno: u32, mut color: Rgba<u8>) {
+        if let Some(line_number_color) = self.line_number_color {
+            color = line_number_color;
+        } else {
+            for i in color.0.iter_mut() {
+                *i = (*i).saturating_sub(20);
+            }
+        }
+        for (i, number) in self.line_numbers(lineno) {
+            let width = self.line_number_chars as usize;
+            let line_number = match self.line_number_align {
+                LineNumberAlign::Left => format!("{:<width$}", number, width = width),
+                LineNumberAlign::Right => format!("{:>width$}", number, width = width),
+            };
+            let x = match self.line_number_side {
+                LineNumberSide::Left => self.code_pad,
+                LineNumberSide::Right => {
+                    image.width() - self.code_pad_right - self.font.width(&line_number)
+                }
+            };
+            let y = self.get_line_y(i);
+            self.font
+                .draw_text(image, color, x, y, FontStyle::REGULAR, &line_number);
         }
+    }
 
-        let shadow = RgbaImage::from_pixel(width, height, *color);
+    /// Get the numbered lines of the block, as `(index of line, number)`
+    fn line_numbers(&self, lineno: u32) -> Vec<(u32, u32)> {
+        let anchor = match self.highlight_lines.first() {
+            Some(&(n, _)) if n >= 1 && n <= lineno + 1 => n - 1,
+            _ => lineno / 2,
+        };
+        let relative = self.line_number_mode == LineNumberMode::Relative;
+        (0..=lineno)
+            .filter_map(|i| {
+                let number = if relative && i != anchor {
+                    i.abs_diff(anchor)
+                } else {
+                    i.saturating_add(self.line_offset)
+                };
+                // the first, the last and the current (in relative mode) lines are always numbered
+                let numbered = number % self.line_number_step == 0
+                    || i == 0
+                    || i == lineno
+                    || (relative && i == anchor);
+                numbered.then_some((i, number))
+            })
+            .collect()
+    }
+
+    fn highlight_lines<I: IntoIterator<Item = u32>>(
+        &mut self,
+        image: &mut RgbaImage,
+        lines: I,
+        color: Rgba<u8>,
+    ) {
+        let width = image.width();
+        let height = self.get_line_height();
+        let shadow = RgbaImage::from_pixel(width, height, color);
 
         for i in lines {
             let y = self.get_line_y(i - 1);
-            copy_alpha(&shadow, image, 0, y);
+            copy_alpha(&shadow, image, 0, y as i32);
         }
     }
 
-    // TODO: use &T instead of &mut T ?
-    pub fn format(&mut self, v: &[Vec<(Style, &str)>], theme: &Theme) -> RgbaImage {
-        if self.line_number {
-            self.line_number_chars =
-                (((v.len() + self.line_offset as usize) as f32).log10() + 1.0).floor() as u32;
-        } else {
-            self.line_number_chars = 0;
-            self.line_number_pad = 0;
+    /// Draw a vertical rule from the top of line `first` to the bottom of line `last`, with a
+    /// quarter of `color` over the background. It's 1px wide, or thicker if the image is scaled.
+    fn draw_dimmed_rule(
+        &mut self,
+        image: &mut RgbaImage,
+        x: u32,
+        (first, last): (u32, u32),
+        color: Rgba<u8>,
+        background: Rgba<u8>,
+    ) {
+        let mut dimmed = color;
+        dimmed.0[3] = 0x40;
+        let mut color = background;
+        color.blend(&dimmed);
+
+        let top = self.get_line_y(first) as f32;
+        let bottom = self.get_line_y(last + 1) as f32 - 1.0;
+        for dx in 0..(self.code_pad / 25).max(1) {
+            let x = (x + dx) as f32;
+            draw_line_segment_mut(image, (x, top), (x, bottom), color);
+        }
+    }
+
+    /// Draw a vertical line between the line numbers and the code
+    fn draw_line_number_separator(
+        &mut self,
+        image: &mut RgbaImage,
+        lineno: u32,
+        color: Rgba<u8>,
+        background: Rgba<u8>,
+    ) {
+        let x = match self.line_number_side {
+            LineNumberSide::Left => self.get_left_pad() - self.line_number_pad,
+            LineNumberSide::Right => {
+                image.width() - self.code_pad_right - self.get_gutter_width() + self.line_number_pad
+            }
+        };
+        self.draw_dimmed_rule(image, x, (0, lineno), color, background);
+    }
+
+    /// Draw the indentation guides of the lines
+    fn draw_indent_guides(
+        &mut self,
+        image: &mut RgbaImage,
+        v: &[Vec<(Style, &str)>],
+        color: Rgba<u8>,
+        background: Rgba<u8>,
+    ) {
+        let tab_width = u32::from(self.tab_width);
+        // the right-to-left lines are mirrored, don't draw guides on them
+        let indents = v
+            .iter()
+            .map(|line| {
+                if self.is_rtl_line(line) {
+                    None
+                } else {
+                    indent_columns(line, tab_width)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for (column, first, last) in indent_guides(&indents, tab_width) {
+            let x = self.get_left_pad() + self.font.width(&" ".repeat(column as usize));
+            self.draw_dimmed_rule(image, x, (first, last), color, background);
         }
+    }
 
-        let drawables = self.create_drawables(v);
+    /// Fill the cursor line and draw a bar on its left
+    fn draw_cursor_line(&mut self, image: &mut RgbaImage, lineno: u32, foreground: Rgba<u8>) {
+        let color = self.cursor_line_color.unwrap_or(foreground);
+        let height = self.get_line_height();
+        let y = self.get_line_y(lineno - 1) as i32;
 
-        let size = self.get_image_size(drawables.max_width, drawables.max_lineno);
+        let mut fill = color;
+        fill.0[3] /= 4;
+        copy_alpha(
+            &RgbaImage::from_pixel(image.width(), height, fill),
+            image,
+            0,
+            y,
+        );
 
-        let foreground = theme.settings.foreground.unwrap();
-        let background = theme.settings.background.unwrap();
+        let bar_width = (self.code_pad / 6).max(1);
+        copy_alpha(
+            &RgbaImage::from_pixel(bar_width, height, color),
+            image,
+            0,
+            y,
+        );
+    }
 
-        let mut image = RgbaImage::from_pixel(size.0, size.1, background.to_rgba());
+    /// Move the lines which are not focused 60% of the way to the background of the panel.
+    ///
+    /// The colors are weighted by their alpha, so over a transparent background the text
+    /// fades out instead of keeping its opacity.
+    fn fade_unfocused_lines(&mut self, image: &mut RgbaImage, lineno: u32, background: Rgba<u8>) {
+        let height = self.get_line_height();
+        let fade = |pixel: &mut Rgba<u8>| {
+            let (alpha, bg_alpha) = (f32::from(pixel.0[3]), f32::from(background.0[3]));
+            let faded_alpha = alpha * 0.4 + bg_alpha * 0.6;
+            if faded_alpha > 0.0 {
+                for (c, &b) in pixel.0[..3].iter_mut().zip(&background.0[..3]) {
+                    let faded =
+                        (f32::from(*c) * alpha * 0.4 + f32::from(b) * bg_alpha * 0.6) / faded_alpha;
+                    *c = faded.round() as u8;
+                }
+            }
+            pixel.0[3] = faded_alpha.round() as u8;
+        };
 
-        if !self.highlight_lines.is_empty() {
-            let highlight_lines = self
-                .highlight_lines
+        for i in 1..=lineno + 1 {
+            if !in_ranges(&self.focus_lines, i) {
+                let top = self.get_line_y(i - 1);
+                for y in top..(top + height).min(image.height()) {
+                    for x in 0..image.width() {
+                        fade(image.get_pixel_mut(x, y));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether the line should be drawn from right to left
+    fn is_rtl_line(&self, tokens: &[(Style, &str)]) -> bool {
+        match self.text_direction {
+            TextDirection::Ltr => false,
+            TextDirection::Rtl => true,
+            TextDirection::Auto => tokens
                 .iter()
-                .cloned()
-                .filter(|&n| n >= 1 && n <= drawables.max_lineno + 1)
-                .collect::<Vec<_>>();
-            self.highlight_lines(&mut image, highlight_lines);
+                .flat_map(|(_, text)| text.chars())
+                .find(|c| c.is_alphabetic())
+                .is_some_and(is_rtl),
         }
-        if self.line_number {
-            self.draw_line_number(&mut image, drawables.max_lineno, foreground.to_rgba());
+    }
+
+    /// calculate the X coordinate of a column (0-based) of a line
+    fn get_column_x(&mut self, line: &str, col: u32) -> u32 {
+        let tab = " ".repeat(self.tab_width as usize);
+        let prefix = line
+            .chars()
+            .take(col as usize)
+            .collect::<String>()
+            .replace('\t', &tab);
+        self.get_left_pad() + self.font.width(&prefix)
+    }
+
+    fn highlight_ranges(
+        &mut self,
+        image: &mut RgbaImage,
+        v: &[Vec<(Style, &str)>],
+        mut color: Rgba<u8>,
+    ) {
+        color.0[3] = 0x40;
+        let height = self.get_line_height();
+
+        for (lineno, start, end) in self.highlight_ranges.clone() {
+            if lineno < 1 || lineno as usize > v.len() || start < 1 || end < start {
+                continue;
+            }
+            let line = v[lineno as usize - 1]
+                .iter()
+                .map(|(_, text)| *text)
+                .collect::<String>();
+            let line = line.trim_end_matches(['\r', '\n']);
+
+            let x0 = self.get_column_x(line, start - 1);
+            let x1 = self.get_column_x(line, end);
+            if x1 <= x0 {
+                continue;
+            }
+            let rect = RgbaImage::from_pixel(x1 - x0, height, color);
+            let y = self.get_line_y(lineno - 1);
+            copy_alpha(&rect, image, x0 as i32, y as i32);
+        }
+    }
+
+    /// Draw the boxes behind the occurrences of marks
+    fn draw_marks(&mut self, image: &mut RgbaImage, v: &[Vec<(Style, &str)>], color: Rgba<u8>) {
+        let height = self.get_line_height();
+        let marks = self.marks.clone();
+
+        for (i, tokens) in v.iter().enumerate() {
+            let line = tokens.iter().map(|(_, text)| *text).collect::<String>();
+            let line = line.trim_end_matches(['\r', '\n']);
+
+            for mark in marks.iter().filter(|mark| !mark.is_empty()) {
+                for (start, _) in line.match_indices(mark.as_str()) {
+                    let col = line[..start].chars().count() as u32;
+                    let x0 = self.get_column_x(line, col);
+                    let x1 = self.get_column_x(line, col + mark.chars().count() as u32);
+                    if x1 <= x0 {
+                        continue;
+                    }
+                    let rect = RgbaImage::from_pixel(x1 - x0, height, color);
+                    let y = self.get_line_y(i as u32);
+                    copy_alpha(&rect, image, x0 as i32, y as i32);
+                }
+            }
         }
+    }
 
-        for (x, y, color, style, text) in drawables.drawables {
-            let color = color.unwrap_or(foreground).to_rgba();
-            self.font.draw_text(&mut image, color, x, y, style, &text);
+    /// Draw the selection behind the glyphs
+    fn draw_selection(&mut self, image: &mut RgbaImage, v: &[Vec<(Style, &str)>], color: Rgba<u8>) {
+        let (start_line, start_col, end_line, end_col) = match self.selection {
+            Some(selection) => selection,
+            None => return,
+        };
+        if start_line < 1 || start_col < 1 || (end_line, end_col) < (start_line, start_col) {
+            return;
         }
+        let height = self.get_line_height();
+        // the selection of the lines except the last one reaches the right edge of the code
+        let right = image.width()
+            - self.code_pad_right
+            - match self.line_number_side {
+                LineNumberSide::Right => self.get_gutter_width(),
+                _ => 0,
+            };
 
+        for lineno in start_line..=end_line.min(v.len() as u32) {
+            let line = v[lineno as usize - 1]
+                .iter()
+                .map(|(_, text)| *text)
+                .collect::<String>();
+            let line = line.trim_end_matches(['\r', '\n']);
+
+            let x0 = if lineno == start_line {
+                self.get_column_x(line, start_col - 1)
+            } else {
+                self.get_left_pad()
+            };
+            let x1 = if lineno == end_line {
+                self.get_column_x(line, end_col)
+            } else {
+                right
+            };
+            if x1 <= x0 {
+                continue;
+            }
+            let rect = RgbaImage::from_pixel(x1 - x0, height, color);
+            let y = self.get_line_y(lineno - 1);
+            copy_alpha(&rect, image, x0 as i32, y as i32);
+        }
+    }
+
+    /// Draw a line under the text which starts at (x, y), below the baseline
+    fn draw_underline(
+        &mut self,
+        image: &mut RgbaImage,
+        color: Rgba<u8>,
+        x: u32,
+        y: u32,
+        text: &str,
+    ) {
+        let width = self.font.width(text.trim_end());
+        let font_height = self.font.height(" ");
+        let thickness = (font_height / 16).max(1);
+        if width == 0 {
+            return;
+        }
+        let line = RgbaImage::from_pixel(width, thickness, color);
+        let y = y + font_height - (font_height / 10).max(thickness);
+        copy_alpha(&line, image, x as i32, y as i32);
+    }
+
+    fn draw_gutter_bars(&mut self, image: &mut RgbaImage, max_lineno: u32) {
+        // the bars grow from the edge of the image toward the code
+        let gutter_width = match self.line_number_side {
+            LineNumberSide::Left => self.code_pad + self.get_gutter_width(),
+            LineNumberSide::Right => self.code_pad_right + self.get_gutter_width(),
+        } - self.line_number_pad;
+        let height = self.get_line_height();
+
+        for (lineno, value, color) in self.gutter_bars.clone() {
+            if lineno < 1 || lineno > max_lineno + 1 {
+                continue;
+            }
+            let width = (gutter_width as f32 * value.clamp(0.0, 1.0)).round() as u32;
+            if width == 0 {
+                continue;
+            }
+            let bar = RgbaImage::from_pixel(width, height, color);
+            let x = match self.line_number_side {
+                LineNumberSide::Left => 0,
+                LineNumberSide::Right => image.width() - width,
+            };
+            let y = self.get_line_y(lineno - 1);
+            copy_alpha(&bar, image, x as i32, y as i32);
+        }
+    }
+
+    /// Like `format`, but return an error instead of falling back to the default colors
+    /// if the theme doesn't specify the foreground or background color
+    pub fn format_to_image(
+        &mut self,
+        v: &[Vec<(Style, &str)>],
+        theme: &Theme,
+    ) -> Result<RgbaImage, FormatError> {
+        if theme.settings.foreground.is_none() || theme.settings.background.is_none() {
+            return Err(FormatError::MissingThemeColors);
+        }
+        let blocks = [(None, v)];
+        let layout = self.layout_blocks(&blocks);
+        let (width, height) = self.with_shadow_size(layout.size);
+        self.check_size(width, height)?;
+
+        let mut image = self.draw_blocks(layout, &blocks, theme);
+        self.apply_window_controls(&mut image);
+        self.apply_round_corner(&mut image);
+        Ok(self.apply_shadow(&image))
+    }
+
+    /// Check that an image of the size doesn't exceed the maximum size set by `max_image_bytes`
+    pub fn check_size(&self, width: u32, height: u32) -> Result<(), FormatError> {
+        match self.max_image_bytes {
+            Some(max_bytes) if u64::from(width) * u64::from(height) * 4 > max_bytes => {
+                Err(FormatError::ImageTooLarge {
+                    width,
+                    height,
+                    max_bytes,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // TODO: use &T instead of &mut T ?
+    /// Format the code into an image.
+    ///
+    /// If the theme doesn't specify the foreground or background color,
+    /// white (`#FFFFFF`) and `#272822` will be used.
+    ///
+    /// The size set by `max_image_bytes` isn't checked, use `format_to_image` to check it
+    /// before the image is allocated.
+    ///
+    /// This is a shortcut of the stages below, which can be called one by one to
+    /// post-process the image between them:
+    ///
+    /// ```
+    /// # use silicon::formatter::ImageFormatterBuilder;
+    /// # use silicon::assets::HighlightingAssets;
+    /// # use syntect::easy::HighlightLines;
+    /// # use syntect::util::LinesWithEndings;
+    /// # let ha = HighlightingAssets::new();
+    /// # let (ps, ts) = (&ha.syntax_set, &ha.theme_set);
+    /// # let syntax = ps.find_syntax_by_token("rs").unwrap();
+    /// # let theme = &ts.themes["Dracula"];
+    /// # let mut h = HighlightLines::new(syntax, theme);
+    /// # let v = LinesWithEndings::from("fn main() {}")
+    /// #     .map(|line| h.highlight_line(line, ps))
+    /// #     .collect::<Result<Vec<_>, _>>()
+    /// #     .unwrap();
+    /// let mut formatter = ImageFormatterBuilder::<String>::new().build().unwrap();
+    ///
+    /// let mut image = formatter.format_code(&v, theme);
+    /// // stamp a logo, apply a filter...
+    /// formatter.apply_window_controls(&mut image);
+    /// formatter.apply_round_corner(&mut image);
+    /// let image = formatter.apply_shadow(&image);
+    /// ```
+    pub fn format(&mut self, v: &[Vec<(Style, &str)>], theme: &Theme) -> RgbaImage {
+        let image = self.format_panel(v, theme);
+        self.apply_shadow(&image)
+    }
+
+    /// Format the code into an image, without the window controls, the round corners
+    /// and the shadow. The space of the title bar is kept.
+    pub fn format_code(&mut self, v: &[Vec<(Style, &str)>], theme: &Theme) -> RgbaImage {
+        self.format_blocks(&[(None, v)], theme)
+    }
+
+    /// Draw the window controls on the image if they are enabled
+    pub fn apply_window_controls(&self, image: &mut RgbaImage) {
         if self.window_controls {
             let params = WindowControlsParams {
                 width: self.window_controls_width,
-                height: self.window_controls_height,
+                // centered in the title bar
+                height: self.title_bar_height,
                 padding: self.title_bar_pad,
                 radius: self.window_controls_width / 3 / 4,
             };
-            add_window_controls(&mut image, &params);
+            add_window_controls(image, &params);
         }
+    }
 
+    /// Round the corners of the image if it's enabled
+    pub fn apply_round_corner(&self, image: &mut RgbaImage) {
         if self.round_corner {
-            round_corner(&mut image, 12);
+            round_corner(image, self.corner_radius, self.round_corners);
+        }
+    }
+
+    /// Add the shadow to the image if there is a shadow adder, otherwise return a copy of it
+    pub fn apply_shadow(&self, image: &RgbaImage) -> RgbaImage {
+        match &self.shadow_adder {
+            Some(adder) => adder.apply_to(image),
+            None => image.clone(),
         }
+    }
+
+    /// Format the code into the frames of an animation, which reveal the code progressively.
+    ///
+    /// All the frames have the size of the image of the full code, and the last one is the same as it.
+    /// There are at most `max_frames` frames, see `ImageFormatterBuilder::max_frames`.
+    pub fn format_frames(
+        &mut self,
+        v: &[Vec<(Style, &str)>],
+        theme: &Theme,
+        mode: AnimationMode,
+    ) -> Vec<DynamicImage> {
+        // the shadow only depends on the shape of the panel, so draw it once
+        let full = self.format_panel(v, theme);
+        let backdrop = self
+            .shadow_adder
+            .as_ref()
+            .map(|adder| adder.backdrop(&full));
+
+        limit_steps(reveal_steps(v, mode), self.max_frames)
+            .into_iter()
+            .map(|count| {
+                let panel = self.format_panel(&hide_after(v, count), theme);
+                let image = match (&self.shadow_adder, &backdrop) {
+                    (Some(adder), Some(backdrop)) => {
+                        let mut image = backdrop.clone();
+                        adder.paste_onto(&panel, &mut image);
+                        image
+                    }
+                    _ => panel,
+                };
+                DynamicImage::ImageRgba8(image)
+            })
+            .collect()
+    }
+
+    /// Get the size of the image that `format` would produce, without drawing it.
+    ///
+    /// The size includes the title bar and the padding of the shadow adder.
+    pub fn dimensions(&mut self, v: &[Vec<(Style, &str)>]) -> (u32, u32) {
+        let size = self.layout_blocks(&[(None, v)]).size;
+        self.with_shadow_size(size)
+    }
+
+    /// Get the size of the image with the padding of the shadow adder, from the size of the panel
+    fn with_shadow_size(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        match &self.shadow_adder {
+            Some(adder) => adder.output_size(width, height),
+            None => (width, height),
+        }
+    }
 
-        if let Some(adder) = &self.shadow_adder {
-            adder.apply_to(&image)
+    /// Get the layout metrics of the image that `format` would produce, without drawing it.
+    pub fn metrics(&mut self, v: &[Vec<(Style, &str)>]) -> Metrics {
+        self.metrics_blocks(&[(None, v)])
+    }
+
+    /// Like `metrics`, but for the image that `format_many` would produce
+    pub fn metrics_many(&mut self, blocks: &[(String, HighlightedLines)]) -> Metrics {
+        let blocks = blocks
+            .iter()
+            .map(|(title, v)| (Some(title.as_str()), v.as_slice()))
+            .collect::<Vec<_>>();
+        self.metrics_blocks(&blocks)
+    }
+
+    fn metrics_blocks(&mut self, blocks: &[Block]) -> Metrics {
+        let (width, height) = self.layout_blocks(blocks).size;
+        let (width, height) = match &self.shadow_adder {
+            Some(adder) => adder.output_size(width, height),
+            None => (width, height),
+        };
+
+        Metrics {
+            width,
+            height,
+            lines: blocks.iter().map(|(_, v)| v.len()).sum(),
+            line_number_chars: self.line_number_chars,
+            left_pad: self.get_left_pad(),
+            font_height: self.font.height(" "),
+        }
+    }
+
+    /// Format the code into the panel (the window), without applying the shadow adder
+    pub fn format_panel(&mut self, v: &[Vec<(Style, &str)>], theme: &Theme) -> RgbaImage {
+        let mut image = self.format_code(v, theme);
+        self.apply_window_controls(&mut image);
+        self.apply_round_corner(&mut image);
+        image
+    }
+
+    /// Format several pieces of code into one image, from top to bottom.
+    ///
+    /// Each block is `(title, lines)`. The title is drawn above the code,
+    /// and the line numbers restart for each block.
+    pub fn format_many(
+        &mut self,
+        blocks: &[(String, HighlightedLines)],
+        theme: &Theme,
+    ) -> RgbaImage {
+        let image = self.format_many_panel(blocks, theme);
+        self.apply_shadow(&image)
+    }
+
+    /// Like `format_many`, but without applying the shadow adder
+    pub fn format_many_panel(
+        &mut self,
+        blocks: &[(String, HighlightedLines)],
+        theme: &Theme,
+    ) -> RgbaImage {
+        let mut image = self.format_many_code(blocks, theme);
+        self.apply_window_controls(&mut image);
+        self.apply_round_corner(&mut image);
+        image
+    }
+
+    /// Like `format_code`, but for several pieces of code as `format_many`
+    pub fn format_many_code(
+        &mut self,
+        blocks: &[(String, HighlightedLines)],
+        theme: &Theme,
+    ) -> RgbaImage {
+        let blocks = blocks
+            .iter()
+            .map(|(title, v)| (Some(title.as_str()), v.as_slice()))
+            .collect::<Vec<_>>();
+        self.format_blocks(&blocks, theme)
+    }
+
+    /// Measure the blocks and create the drawables, without drawing anything
+    fn layout_blocks(&mut self, blocks: &[Block]) -> Layout {
+        if self.line_number {
+            let max_len = blocks.iter().map(|(_, v)| v.len()).max().unwrap_or(0);
+            // the largest printed number, empty input still has one (blank) line
+            let max_number = u64::from(self.line_offset) + max_len.max(1) as u64 - 1;
+            self.line_number_chars =
+                (max_number.to_string().len() as u32).max(self.min_line_number_chars.unwrap_or(0));
+        } else if let Some(chars) = self.min_line_number_chars {
+            self.line_number_chars = chars;
         } else {
-            image
+            self.line_number_chars = 0;
+            self.line_number_pad = 0;
+        }
+
+        let line_height = self.get_line_height();
+        let mut drawables = vec![];
+        let mut max_width = 0;
+        // (top, max_lineno) of each block
+        let mut layout = vec![];
+        let mut separators = vec![];
+        let mut top = 0;
+
+        for (i, (title, v)) in blocks.iter().enumerate() {
+            if i > 0 {
+                separators.push(top - self.code_pad / 2);
+            }
+            self.block_top = top;
+            if let Some(title) = title {
+                let y = self.get_line_y(0);
+                max_width = max_width.max(self.code_pad + self.font.width(title));
+                let style = highlighting::FontStyle::BOLD;
+                drawables.push((self.code_pad, y, None, style, title.to_string()));
+                self.block_top += line_height;
+            }
+
+            let drawable = self.create_drawables(v);
+            max_width = max_width.max(drawable.max_width);
+            drawables.extend(drawable.drawables);
+            layout.push((self.block_top, drawable.max_lineno));
+
+            top = self.block_top + (drawable.max_lineno + 1) * line_height + self.code_pad;
+        }
+
+        let title = self.create_title_drawable();
+        if let Some(drawable) = &title {
+            max_width = max_width.max(drawable.max_width);
+        }
+
+        let last_lineno = layout.last().map(|&(_, lineno)| lineno).unwrap_or(0);
+        let size = self.get_image_size(max_width, last_lineno);
+        self.block_top = 0;
+
+        // the caption only widens the image, its position depends on the height
+        let caption = self.create_caption_drawable(size.1);
+        let size = match &caption {
+            Some(drawable) => {
+                let width = drawable.max_width + self.code_pad_right;
+                (size.0.max(width), size.1)
+            }
+            None => size,
+        };
+
+        Layout {
+            size,
+            blocks: layout,
+            separators,
+            drawables,
+            title,
+            caption,
+        }
+    }
+
+    fn format_blocks(&mut self, blocks: &[Block], theme: &Theme) -> RgbaImage {
+        let layout = self.layout_blocks(blocks);
+        self.draw_blocks(layout, blocks, theme)
+    }
+
+    /// Draw the blocks measured by `layout_blocks`
+    fn draw_blocks(&mut self, layout: Layout, blocks: &[Block], theme: &Theme) -> RgbaImage {
+        let Layout {
+            size,
+            blocks: layout,
+            separators,
+            drawables,
+            title,
+            caption,
+        } = layout;
+
+        let foreground = theme.settings.foreground.unwrap_or(DEFAULT_FOREGROUND);
+        let background = theme.settings.background.unwrap_or(DEFAULT_BACKGROUND);
+
+        let background = self.code_background.unwrap_or_else(|| background.to_rgba());
+
+        let mut image = RgbaImage::from_pixel(size.0, size.1, background);
+
+        for (&(block_top, max_lineno), (_, v)) in layout.iter().zip(blocks) {
+            self.block_top = block_top;
+
+            if !self.highlight_lines.is_empty() {
+                let color = self.highlight_color.unwrap_or_else(|| {
+                    let mut color = *image.get_pixel(20, 20);
+                    for i in color.0.iter_mut() {
+                        *i = (*i).saturating_add(40);
+                    }
+                    color
+                });
+                // open-ended ranges are clamped to the last line here
+                let highlight_lines = (1..=max_lineno + 1)
+                    .filter(|&n| in_ranges(&self.highlight_lines, n))
+                    .collect::<Vec<_>>();
+                self.highlight_lines(&mut image, highlight_lines, color);
+            }
+            for (ranges, color) in self.highlight_groups.clone() {
+                let highlight_lines = (1..=max_lineno + 1)
+                    .filter(|&n| in_ranges(&ranges, n))
+                    .collect::<Vec<_>>();
+                self.highlight_lines(&mut image, highlight_lines, color);
+            }
+            match self.cursor_line {
+                Some(line) if line >= 1 && line <= max_lineno + 1 => {
+                    self.draw_cursor_line(&mut image, line, foreground.to_rgba());
+                }
+                _ => (),
+            }
+            if !self.highlight_ranges.is_empty() {
+                self.highlight_ranges(&mut image, v, foreground.to_rgba());
+            }
+            if !self.marks.is_empty() {
+                // like the matches of a search in the editor
+                let color = match (self.mark_color, theme.settings.find_highlight) {
+                    (Some(color), _) => color,
+                    (None, Some(color)) => color.to_rgba(),
+                    (None, None) => {
+                        let mut color = foreground.to_rgba();
+                        color.0[3] = 0x40;
+                        color
+                    }
+                };
+                self.draw_marks(&mut image, v, color);
+            }
+            if self.selection.is_some() {
+                let color = match (self.selection_color, theme.settings.selection) {
+                    (Some(color), _) => color,
+                    (None, Some(color)) => color.to_rgba(),
+                    (None, None) => {
+                        let mut color = foreground.to_rgba();
+                        color.0[3] = 0x40;
+                        color
+                    }
+                };
+                self.draw_selection(&mut image, v, color);
+            }
+            if !self.gutter_bars.is_empty() {
+                self.draw_gutter_bars(&mut image, max_lineno);
+            }
+            if self.line_number {
+                self.draw_line_number(&mut image, max_lineno, foreground.to_rgba());
+            }
+            if self.line_number && self.line_number_separator {
+                let color = self.line_number_color.unwrap_or(foreground.to_rgba());
+                self.draw_line_number_separator(&mut image, max_lineno, color, background);
+            }
+            if self.indent_guides {
+                self.draw_indent_guides(&mut image, v, foreground.to_rgba(), background);
+            }
+        }
+        self.block_top = 0;
+
+        if !separators.is_empty() {
+            let mut color = foreground.to_rgba();
+            color.0[3] = 0x40;
+            let line = RgbaImage::from_pixel(size.0 - self.code_pad * 2, 1, color);
+            for y in separators {
+                let y = y + self.get_line_y(0);
+                copy_alpha(&line, &mut image, self.code_pad as i32, y as i32);
+            }
+        }
+
+        for (x, y, color, style, text) in drawables {
+            let color = color.unwrap_or(foreground).to_rgba();
+            self.font
+                .draw_text(&mut image, color, x, y, style.into(), &text);
+            if style.contains(highlighting::FontStyle::UNDERLINE) {
+                self.draw_underline(&mut image, color, x, y, &text);
+            }
+        }
+
+        if !self.focus_lines.is_empty() {
+            for &(block_top, max_lineno) in &layout {
+                self.block_top = block_top;
+                self.fade_unfocused_lines(&mut image, max_lineno, background);
+            }
+            self.block_top = 0;
+        }
+
+        if let Some(drawable) = title {
+            let color = self.title_color.unwrap_or_else(|| foreground.to_rgba());
+            for (x, y, _, style, text) in drawable.drawables {
+                self.title_font()
+                    .draw_text(&mut image, color, x, y, style.into(), &text);
+            }
+        }
+
+        if let Some(drawable) = caption {
+            // a faint tint of the foreground distinguishes the bar from the code
+            let mut tint = foreground.to_rgba();
+            tint.0[3] = 0x10;
+            let bar_height = self.get_caption_height();
+            let bar = RgbaImage::from_pixel(size.0, bar_height, tint);
+            copy_alpha(&bar, &mut image, 0, (size.1 - bar_height) as i32);
+
+            let color = self.title_color.unwrap_or_else(|| foreground.to_rgba());
+            for (x, y, _, style, text) in drawable.drawables {
+                self.title_font()
+                    .draw_text(&mut image, color, x, y, style.into(), &text);
+            }
+        }
+
+        image
+    }
+}
+
+impl<T: TextLineDrawer> Formatter for ImageFormatter<T> {
+    type Output = RgbaImage;
+
+    fn format(&mut self, v: &[Vec<(Style, &str)>], theme: &Theme) -> Self::Output {
+        ImageFormatter::format(self, v, theme)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::HighlightingAssets;
+    use image::Pixel;
+    use syntect::easy::HighlightLines;
+    use syntect::util::LinesWithEndings;
+
+    #[test]
+    fn line_number_color() {
+        let ha = HighlightingAssets::new();
+        let (ps, ts) = (ha.syntax_set, ha.theme_set);
+        let syntax = ps.find_syntax_by_token("rs").unwrap();
+        let theme = &ts.themes["Dracula"];
+
+        let mut h = HighlightLines::new(syntax, theme);
+        let highlight = LinesWithEndings::from("fn main() {}\n")
+            .map(|line| h.highlight_line(line, &ps))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let color = Rgba([0x12, 0xab, 0x34, 0xff]);
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .window_controls(false)
+            .round_corner(false)
+            .line_number_color(Some(color))
+            .build()
+            .unwrap();
+        let image = formatter.format(&highlight, theme);
+
+        let left_pad = formatter.get_left_pad();
+        let (top, bottom) = (formatter.get_line_y(0), formatter.get_line_y(1));
+        let found = (top..bottom)
+            .flat_map(|y| (formatter.code_pad..left_pad).map(move |x| (x, y)))
+            .any(|(x, y)| *image.get_pixel(x, y) == color);
+        assert!(found);
+    }
+
+    #[test]
+    fn highlight_color() {
+        let ha = HighlightingAssets::new();
+        let (ps, ts) = (ha.syntax_set, ha.theme_set);
+        let syntax = ps.find_syntax_by_token("rs").unwrap();
+        let theme = &ts.themes["Dracula"];
+
+        let mut h = HighlightLines::new(syntax, theme);
+        let highlight = LinesWithEndings::from("fn main() {\n}\n")
+            .map(|line| h.highlight_line(line, &ps))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let color = Rgba([0xff, 0x00, 0x00, 0x80]);
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .window_controls(false)
+            .round_corner(false)
+            .highlight_lines(vec![2])
+            .highlight_color(Some(color))
+            .build()
+            .unwrap();
+        let image = formatter.format(&highlight, theme);
+
+        let mut expected = theme.settings.background.unwrap().to_rgba();
+        expected.blend(&color);
+        let y = formatter.get_line_y(1);
+        assert_eq!(*image.get_pixel(1, y), expected);
+        assert_ne!(*image.get_pixel(1, formatter.get_line_y(0)), expected);
+    }
+
+    #[test]
+    fn highlight_groups() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let lines = vec![vec![(Style::default(), "x\n")]; 4];
+
+        let (red, green) = (Rgba([0xff, 0, 0, 0x80]), Rgba([0, 0xff, 0, 0x80]));
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .window_controls(false)
+            .round_corner(false)
+            .highlight_groups(vec![(vec![(1, 2)], red), (vec![(2, 3)], green)])
+            .build()
+            .unwrap();
+        let image = formatter.format(&lines, theme);
+
+        let background = theme.settings.background.unwrap().to_rgba();
+        let blended = |colors: &[Rgba<u8>]| {
+            let mut pixel = background;
+            colors.iter().for_each(|color| pixel.blend(color));
+            pixel
+        };
+        let mut pixel = |i| *image.get_pixel(1, formatter.get_line_y(i));
+        assert_eq!(pixel(0), blended(&[red]));
+        assert_eq!(pixel(1), blended(&[red, green]));
+        assert_eq!(pixel(2), blended(&[green]));
+        assert_eq!(pixel(3), background);
+    }
+
+    #[test]
+    fn highlight_lines_absolute() {
+        let ha = HighlightingAssets::new();
+        let (ps, ts) = (ha.syntax_set, ha.theme_set);
+        let syntax = ps.find_syntax_by_token("rs").unwrap();
+        let theme = &ts.themes["Dracula"];
+
+        let mut h = HighlightLines::new(syntax, theme);
+        let highlight = LinesWithEndings::from("fn main() {\n}\n")
+            .map(|line| h.highlight_line(line, &ps))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let color = Rgba([0xff, 0x00, 0x00, 0x80]);
+        let mut expected = theme.settings.background.unwrap().to_rgba();
+        expected.blend(&color);
+
+        for (absolute, highlighted) in [(false, None), (true, Some(1))] {
+            let mut formatter = ImageFormatterBuilder::<String>::new()
+                .window_controls(false)
+                .round_corner(false)
+                .line_offset(10)
+                .highlight_lines(vec![11])
+                .highlight_lines_absolute(absolute)
+                .highlight_color(Some(color))
+                .build()
+                .unwrap();
+            let image = formatter.format(&highlight, theme);
+            for i in 0..2 {
+                let pixel = *image.get_pixel(1, formatter.get_line_y(i));
+                assert_eq!(pixel == expected, highlighted == Some(i));
+            }
+        }
+    }
+
+    #[test]
+    fn dimensions() {
+        let ha = HighlightingAssets::new();
+        let (ps, ts) = (ha.syntax_set, ha.theme_set);
+        let syntax = ps.find_syntax_by_token("rs").unwrap();
+        let theme = &ts.themes["Dracula"];
+
+        let mut h = HighlightLines::new(syntax, theme);
+        let highlight = LinesWithEndings::from("fn main() {\n    println!(\"hi\");\n}\n")
+            .map(|line| h.highlight_line(line, &ps))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .window_title(Some("main.rs".to_owned()))
+            .shadow_adder(ShadowAdder::new().pad_horiz(30).pad_vert(40))
+            .build()
+            .unwrap();
+        let size = formatter.dimensions(&highlight);
+        let image = formatter.format(&highlight, theme);
+        assert_eq!(size, image.dimensions());
+    }
+
+    #[test]
+    fn stages() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let v = vec![vec![(Style::default(), "let x = 1;\n")]];
+
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .shadow_adder(ShadowAdder::new())
+            .build()
+            .unwrap();
+        let mut image = formatter.format_code(&v, theme);
+        // the corners are not rounded yet
+        assert_eq!(image.get_pixel(0, 0).0[3], 0xff);
+
+        formatter.apply_window_controls(&mut image);
+        formatter.apply_round_corner(&mut image);
+        assert_eq!(image.get_pixel(0, 0).0[3], 0);
+        assert_eq!(formatter.apply_shadow(&image), formatter.format(&v, theme));
+    }
+
+    #[test]
+    fn title_controls_gap() {
+        let title_x = |gap| {
+            let mut formatter = ImageFormatterBuilder::<String>::new()
+                .window_title(Some("main.rs".to_owned()))
+                .title_controls_gap(gap)
+                .build()
+                .unwrap();
+            formatter.create_title_drawable().unwrap().drawables[0].0
+        };
+        assert_eq!(title_x(15), 15 + 120 + 15);
+        assert_eq!(title_x(0), 15 + 120);
+    }
+
+    #[test]
+    fn tall_window_title() {
+        let v = vec![vec![(Style::default(), "x")]];
+        let build = |size| {
+            ImageFormatterBuilder::<String>::new()
+                .window_title(Some("main.rs".to_owned()))
+                .title_font_size(size)
+                .build()
+                .unwrap()
+        };
+
+        let mut formatter = build(None);
+        let (code_pad_top, line_y) = (formatter.code_pad_top, formatter.get_line_y(0));
+        assert_eq!(formatter.title_bar_height, 40);
+
+        let mut formatter = build(Some(80.0));
+        let title_height = formatter.title_font().height("main.rs");
+        assert_eq!(formatter.title_bar_height, title_height);
+        assert_eq!(formatter.code_pad_top, code_pad_top + title_height - 40);
+        assert!(formatter.get_line_y(0) > line_y);
+
+        // the title is inside the title bar
+        let title = formatter.create_title_drawable().unwrap().drawables[0].1;
+        assert_eq!(title, formatter.title_bar_pad);
+        assert_eq!(
+            formatter.dimensions(&v).1,
+            formatter.format(&v, &Theme::default()).height()
+        );
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let style = Style::default();
+        let v = LinesWithEndings::from("a\r\nb\r\n")
+            .map(|line| vec![(style, line)])
+            .collect::<Vec<_>>();
+        let lf = vec![vec![(style, "a\n")], vec![(style, "b\n")]];
+
+        let mut formatter = ImageFormatterBuilder::<String>::new().build().unwrap();
+        let drawable = formatter.create_drawables(&v);
+        let texts = drawable
+            .drawables
+            .iter()
+            .map(|(_, _, _, _, text)| text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(texts, vec!["a", "b"]);
+        assert_eq!(drawable.max_lineno, 1);
+        assert_eq!(formatter.dimensions(&v), formatter.dimensions(&lf));
+
+        // syntect may split the `\r` into its own token
+        let split = vec![vec![(style, "a"), (style, "\r"), (style, "\n")]];
+        assert_eq!(formatter.create_drawables(&split).drawables.len(), 1);
+    }
+
+    #[test]
+    fn right_to_left_indent() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let style = Style::default();
+        // the advance of the default font isn't a whole number of pixels, so the indent and
+        // the text are rounded up separately, but the whole token is rounded down
+        let v = vec![
+            vec![(style, " x\n")],
+            vec![(style, "   xyz  \n")],
+            vec![(style, "x\n")],
+        ];
+
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .text_direction(TextDirection::Rtl)
+            .build()
+            .unwrap();
+        let drawable = formatter.create_drawables(&v);
+        let left_pad = formatter.get_left_pad();
+        for (x, _, _, _, text) in &drawable.drawables {
+            assert!(*x >= left_pad);
+            assert!(x + formatter.font.width(text) <= drawable.max_width + 1);
+        }
+        // the indent is mirrored to the right of the text
+        let x = |i: usize| drawable.drawables[i].0;
+        assert!(x(0) < x(2) && x(1) < x(2));
+        formatter.format(&v, theme);
+    }
+
+    #[test]
+    fn accents() {
+        let style = Style::default();
+        let v = vec![vec![
+            (style, "fn"),
+            (style, " main"),
+            (style, "("),
+            (style, "mainly"),
+        ]];
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .accents(vec![("main".to_owned(), Rgba([0xff, 0, 0, 0xff]))])
+            .build()
+            .unwrap();
+
+        let drawable = formatter.create_drawables(&v);
+        let red = Color {
+            r: 0xff,
+            g: 0,
+            b: 0,
+            a: 0xff,
+        };
+        let colors = drawable
+            .drawables
+            .iter()
+            .map(|(_, _, color, _, _)| *color == Some(red))
+            .collect::<Vec<_>>();
+        assert_eq!(colors, vec![false, true, false, false]);
+    }
+
+    #[test]
+    fn marks() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let line = "a TODO b TODO";
+        let v = vec![vec![
+            (Style::default(), "a TODO b "),
+            (Style::default(), "TODO\n"),
+        ]];
+        let red = Rgba([0xff, 0, 0, 0xff]);
+
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .marks(vec!["TODO".to_owned()])
+            .mark_color(Some(red))
+            .build()
+            .unwrap();
+        let image = formatter.format(&v, theme);
+
+        // the bottom of the line is below the glyphs
+        let y = formatter.get_line_y(1) - 1;
+        let mut pixel_at = |col| {
+            let x = (formatter.get_column_x(line, col) + formatter.get_column_x(line, col + 1)) / 2;
+            *image.get_pixel(x, y)
+        };
+        assert_ne!(pixel_at(0), red);
+        assert_eq!(pixel_at(2), red);
+        assert_eq!(pixel_at(5), red);
+        assert_ne!(pixel_at(7), red);
+        assert_eq!(pixel_at(12), red);
+    }
+
+    #[test]
+    fn selection() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let lines = ["let a = 1;", "let b = 2;", "let c = 3;"];
+        let v = lines
+            .iter()
+            .map(|line| vec![(Style::default(), *line)])
+            .collect::<Vec<_>>();
+        let red = Rgba([0xff, 0, 0, 0xff]);
+
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .selection(Some((1, 5, 3, 5)))
+            .selection_color(Some(red))
+            .build()
+            .unwrap();
+        let image = formatter.format(&v, theme);
+
+        let mut pixel_at = |lineno: u32, col| {
+            let line = lines[lineno as usize - 1];
+            // the bottom of the line is below the glyphs
+            let y = formatter.get_line_y(lineno) - 1;
+            let x = (formatter.get_column_x(line, col) + formatter.get_column_x(line, col + 1)) / 2;
+            *image.get_pixel(x, y)
+        };
+        // the first line is selected from the column to the end
+        assert_ne!(pixel_at(1, 3), red);
+        assert_eq!(pixel_at(1, 4), red);
+        assert_eq!(pixel_at(1, 9), red);
+        // the lines in between are selected entirely
+        assert_eq!(pixel_at(2, 0), red);
+        assert_eq!(pixel_at(2, 9), red);
+        // the last line is selected from the start to the column
+        assert_eq!(pixel_at(3, 0), red);
+        assert_eq!(pixel_at(3, 4), red);
+        assert_ne!(pixel_at(3, 5), red);
+    }
+
+    #[test]
+    fn crop_to_content() {
+        let style = Style::default();
+        let code = vec![vec![(style, "let x = 1;\n")]];
+        let trailing = vec![vec![(style, "let x = 1;"), (style, "          \n")]];
+
+        let mut formatter = ImageFormatterBuilder::<String>::new().build().unwrap();
+        let (width, _) = formatter.dimensions(&code);
+        assert!(formatter.dimensions(&trailing).0 > width);
+
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .crop_to_content(true)
+            .build()
+            .unwrap();
+        assert_eq!(formatter.dimensions(&trailing).0, width);
+
+        // narrower than the default minimum width of 150
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .window_controls(false)
+            .line_number(false)
+            .crop_to_content(true)
+            .build()
+            .unwrap();
+        assert!(formatter.dimensions(&[vec![(style, "x")]]).0 < 150);
+
+        // there is no text to crop to, but the gutter and the line numbers are kept
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .window_controls(false)
+            .crop_to_content(true)
+            .build()
+            .unwrap();
+        let blank = vec![vec![(style, "   \n")], vec![(style, "\t\n")]];
+        for input in [&[][..], &[vec![]], &blank] {
+            let left_pad = formatter.metrics(input).left_pad;
+            let image = formatter.format(input, theme);
+            assert_eq!(image.width(), left_pad + formatter.code_pad_right);
         }
     }
+
+    #[test]
+    fn strict_monospace() {
+        let mut registry = FontRegistry::default();
+        registry
+            .add_from_folder(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/fonts"))
+            .unwrap();
+        let builder = |strict| {
+            ImageFormatterBuilder::new()
+                .font(vec![("Silicon Test", 26.0)])
+                .font_registry(registry.clone())
+                .strict_monospace(strict)
+                .build()
+        };
+
+        assert!(builder(false).is_ok());
+        assert!(matches!(builder(true), Err(FontError::NotMonospace(_))));
+        assert!(ImageFormatterBuilder::<String>::new()
+            .strict_monospace(true)
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn empty_input() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let mut formatter = ImageFormatterBuilder::<String>::new().build().unwrap();
+
+        let blank_line = vec![vec![(Style::default(), "\n")]];
+        let expected = formatter.format(&blank_line, theme).dimensions();
+        assert_eq!(formatter.line_number_chars, 1);
+
+        let image = formatter.format(&[], theme);
+        assert_eq!(formatter.line_number_chars, 1);
+        assert_eq!(image.dimensions(), expected);
+        assert_eq!(formatter.format(&[vec![]], theme).dimensions(), expected);
+    }
+
+    #[test]
+    fn line_number_chars() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let lines = vec![vec![(Style::default(), "x\n")]; 10];
+
+        for &(offset, chars) in &[(0, 1), (1, 2), (90, 2), (91, 3), (u32::MAX - 9, 10)] {
+            let mut formatter = ImageFormatterBuilder::<String>::new()
+                .line_offset(offset)
+                .build()
+                .unwrap();
+            formatter.format(&lines, theme);
+            assert_eq!(formatter.line_number_chars, chars, "offset: {}", offset);
+        }
+    }
+
+    #[test]
+    fn min_line_number_chars() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        // wide enough not to be widened to the minimum width
+        let lines = vec![vec![(Style::default(), "let x = 1;\n")]; 12];
+        let format = |line_number, chars| {
+            let mut formatter = ImageFormatterBuilder::<String>::new()
+                .line_number(line_number)
+                .min_line_number_chars(chars)
+                .build()
+                .unwrap();
+            let size = formatter.format(&lines, theme).dimensions();
+            (size, formatter.line_number_chars)
+        };
+
+        let (numbered, chars) = format(true, None);
+        assert_eq!(chars, 2);
+        // the blank gutter takes the same space as the numbers
+        assert_eq!(format(false, Some(2)), (numbered, 2));
+        assert!(format(false, None).0 .0 < numbered.0);
+        // the numbers aren't truncated
+        assert_eq!(format(true, Some(1)), (numbered, 2));
+        let (wider, chars) = format(true, Some(4));
+        assert_eq!(chars, 4);
+        assert!(wider.0 > numbered.0);
+    }
+
+    #[test]
+    fn line_number_step() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let lines = vec![vec![(Style::default(), "x\n")]; 12];
+        let color = Rgba([0x12, 0xab, 0x34, 0xff]);
+
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .line_offset(1)
+            .line_number_step(5)
+            .line_number_color(Some(color))
+            .build()
+            .unwrap();
+        let image = formatter.format(&lines, theme);
+
+        let gutter = formatter.get_left_pad();
+        let mut numbered = vec![];
+        for i in 0..12 {
+            let (top, bottom) = (formatter.get_line_y(i), formatter.get_line_y(i + 1));
+            if (top..bottom).any(|y| (0..gutter).any(|x| *image.get_pixel(x, y) == color)) {
+                numbered.push(i + 1);
+            }
+        }
+        assert_eq!(numbered, [1, 5, 10, 12]);
+        assert_eq!(formatter.line_number_chars, 2);
+    }
+
+    #[test]
+    fn relative_line_numbers() {
+        let builder = || {
+            ImageFormatterBuilder::<String>::new()
+                .line_offset(10)
+                .line_number_mode(LineNumberMode::Relative)
+        };
+        let numbers = |formatter: &ImageFormatter<FontCollection>| {
+            formatter
+                .line_numbers(6)
+                .into_iter()
+                .map(|(_, number)| number)
+                .collect::<Vec<_>>()
+        };
+
+        // the middle line is the anchor, which keeps its absolute number
+        let formatter = builder().build().unwrap();
+        assert_eq!(numbers(&formatter), [3, 2, 1, 13, 1, 2, 3]);
+
+        // the first highlighted line is the anchor
+        let formatter = builder().highlight_lines(vec![2, 5]).build().unwrap();
+        assert_eq!(numbers(&formatter), [1, 11, 1, 2, 3, 4, 5]);
+
+        // the anchor is numbered whatever the step
+        let formatter = builder()
+            .highlight_lines(vec![2])
+            .line_number_step(3)
+            .build()
+            .unwrap();
+        assert_eq!(formatter.line_numbers(6), [(0, 1), (1, 11), (4, 3), (6, 5)]);
+
+        // a highlighted line out of the code falls back to the middle line
+        let formatter = builder().highlight_lines(vec![20]).build().unwrap();
+        assert_eq!(numbers(&formatter)[3], 13);
+    }
+
+    #[test]
+    fn underline() {
+        use std::str::FromStr;
+        use syntect::highlighting::{ScopeSelectors, StyleModifier, ThemeItem};
+
+        let ha = HighlightingAssets::new();
+        let syntax = ha.syntax_set.find_syntax_by_token("rs").unwrap();
+        let mut theme = ha.theme_set.themes["Dracula"].clone();
+        theme.scopes.push(ThemeItem {
+            scope: ScopeSelectors::from_str("storage.type.function").unwrap(),
+            style: StyleModifier {
+                foreground: Some(Color::WHITE),
+                background: None,
+                font_style: Some(highlighting::FontStyle::UNDERLINE),
+            },
+        });
+        let mut h = HighlightLines::new(syntax, &theme);
+        let v = vec![h.highlight_line("fn main() {}\n", &ha.syntax_set).unwrap()];
+
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .window_controls(false)
+            .build()
+            .unwrap();
+        let image = formatter.format(&v, &theme);
+
+        // the row of the underline, below the baseline
+        let white = Rgba([0xff; 4]);
+        let mut count = |col| {
+            let line = "fn main() {}";
+            let x0 = formatter.get_column_x(line, col);
+            let x1 = formatter.get_column_x(line, col + 2);
+            (formatter.get_line_y(0)..formatter.get_line_y(1))
+                .filter(|&y| (x0..x1).all(|x| *image.get_pixel(x, y) == white))
+                .count()
+        };
+        // `fn` is underlined, `main` isn't
+        assert!(count(0) > 0);
+        assert_eq!(count(3), 0);
+    }
+
+    #[test]
+    fn max_image_bytes() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let line = "x".repeat(1000);
+        let v = vec![vec![(Style::default(), line.as_str())]];
+
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .max_image_bytes(Some(1 << 20))
+            .build()
+            .unwrap();
+        let (width, height) = formatter.dimensions(&v);
+        assert_eq!(
+            formatter.format_to_image(&v, theme),
+            Err(FormatError::ImageTooLarge {
+                width,
+                height,
+                max_bytes: 1 << 20
+            })
+        );
+        assert!(formatter.format_to_image(&v[..0], theme).is_ok());
+
+        let v = vec![vec![(Style::default(), "fn main() {}\n")]; 2];
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .shadow_adder(ShadowAdder::new())
+            .max_image_bytes(Some(1 << 24))
+            .build()
+            .unwrap();
+        let image = formatter.format_to_image(&v, theme).unwrap();
+        assert_eq!(image.dimensions(), formatter.dimensions(&v));
+        assert_eq!(image, formatter.format(&v, theme));
+    }
+
+    #[test]
+    fn line_height() {
+        for scale in [1.0, 2.0] {
+            let mut formatter = ImageFormatterBuilder::<String>::new()
+                .line_pad(100)
+                .line_height(Some(1.5))
+                .scale(scale)
+                .build()
+                .unwrap();
+            let font_height = formatter.font.height(" ") as f32;
+            let line_height = formatter.get_line_height() as f32;
+            assert!((font_height * 1.5 - line_height).abs() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn scale() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let lines = vec![vec![(Style::default(), "fn main() {}\n")]; 3];
+        let format = |scale| {
+            let mut formatter = ImageFormatterBuilder::<String>::new()
+                .shadow_adder(ShadowAdder::new().pad_horiz(30).pad_vert(20))
+                .scale(scale)
+                .build()
+                .unwrap();
+            let image = formatter.format(&lines, theme);
+            let pads = [
+                formatter.code_pad,
+                formatter.code_pad_top,
+                formatter.line_number_pad,
+            ];
+            let shadow_pads = formatter.shadow_adder.as_ref().unwrap().output_size(0, 0);
+            (image.dimensions(), pads, shadow_pads)
+        };
+
+        let ((width, height), pads, (shadow_x, shadow_y)) = format(1.0);
+        let ((width2, height2), pads2, shadow_pads2) = format(2.0);
+        assert_eq!(pads2, pads.map(|pad| 2 * pad));
+        assert_eq!(shadow_pads2, (2 * shadow_x, 2 * shadow_y));
+        // the text is rounded at each size, its width once and its height on every line
+        assert!(
+            (width2 as i32 - 2 * width as i32).abs() <= 2,
+            "{} {}",
+            width,
+            width2
+        );
+        assert!(
+            (height2 as i32 - 2 * height as i32).abs() <= 3,
+            "{} {}",
+            height,
+            height2
+        );
+    }
+
+    #[test]
+    fn line_number_fits_gutter() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let lines = vec![vec![(Style::default(), "x\n")]; 3];
+        let color = Rgba([0x12, 0xab, 0x34, 0xff]);
+
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .window_controls(false)
+            .line_offset(998)
+            .line_number_color(Some(color))
+            .build()
+            .unwrap();
+        let image = formatter.format(&lines, theme);
+        assert_eq!(formatter.line_number_chars, 4);
+
+        // `1000` ends before the pad between the gutter and the code
+        let code_start = formatter.get_left_pad() - formatter.line_number_pad;
+        let (top, bottom) = (formatter.get_line_y(2), formatter.get_line_y(3));
+        let columns = (top..bottom)
+            .flat_map(|y| (0..image.width()).map(move |x| (x, y)))
+            .filter(|&(x, y)| *image.get_pixel(x, y) == color)
+            .map(|(x, _)| x)
+            .collect::<Vec<_>>();
+        assert!(!columns.is_empty());
+        assert!(columns.iter().all(|&x| x < code_start));
+    }
+
+    #[test]
+    fn metrics() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let lines = vec![vec![(Style::default(), "x\n")]; 12];
+
+        let mut formatter = ImageFormatterBuilder::<String>::new().build().unwrap();
+        let metrics = formatter.metrics(&lines);
+        let image = formatter.format(&lines, theme);
+        assert_eq!((metrics.width, metrics.height), image.dimensions());
+        assert_eq!(metrics.lines, 12);
+        assert_eq!(metrics.line_number_chars, 2);
+        assert_eq!(metrics.left_pad, formatter.get_left_pad());
+    }
+
+    #[test]
+    fn animation_frames() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let style = Style::default();
+        let v = vec![
+            vec![(style, "a "), (style, "b\n")],
+            vec![(style, "\n")],
+            vec![(style, "cd\n")],
+        ];
+
+        assert_eq!(reveal_steps(&v, AnimationMode::Typing), vec![0, 1, 3, 6, 7]);
+        assert_eq!(reveal_steps(&v, AnimationMode::Lines), vec![0, 4, 8]);
+
+        let steps = (0..=100).collect::<Vec<_>>();
+        assert_eq!(limit_steps(steps.clone(), 200), steps);
+        assert_eq!(limit_steps(steps.clone(), 2), vec![0, 100]);
+        assert_eq!(
+            limit_steps(steps.clone(), 11),
+            (0..=100).step_by(10).collect::<Vec<_>>()
+        );
+        let limited = limit_steps(steps, 8);
+        assert_eq!(limited, vec![0, 15, 30, 45, 60, 75, 90, 100]);
+
+        let hidden = hide_after(&v, 2);
+        let text = |line: &[(Style, &str)], visible: bool| {
+            line.iter()
+                .filter(|(style, _)| (style.foreground.a != 0) == visible)
+                .map(|(_, text)| *text)
+                .collect::<String>()
+        };
+        assert_eq!(text(&hidden[0], true), "a ");
+        assert_eq!(text(&hidden[0], false), "b\n");
+        assert_eq!(text(&hidden[2], false), "cd\n");
+
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .shadow_adder(ShadowAdder::new())
+            .build()
+            .unwrap();
+        let size = formatter.dimensions(&v);
+        let frames = formatter.format_frames(&v, theme, AnimationMode::Lines);
+        assert_eq!(frames.len(), 3);
+        assert!(frames
+            .iter()
+            .all(|frame| (frame.width(), frame.height()) == size));
+        assert_eq!(
+            frames[2].to_rgba8(),
+            formatter.format(&v, theme),
+            "the last frame should be the full image"
+        );
+
+        // a frame per character would be too many
+        let long = vec![vec![(style, "abcdefghij\n")]; 3];
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .max_frames(4)
+            .build()
+            .unwrap();
+        let frames = formatter.format_frames(&long, theme, AnimationMode::Typing);
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[3].to_rgba8(), formatter.format(&long, theme));
+    }
+
+    #[test]
+    fn caption() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let lines = vec![vec![(Style::default(), "x\n")]; 2];
+        let caption = "a caption longer than the code".to_owned();
+
+        let builder = || {
+            ImageFormatterBuilder::<String>::new()
+                .window_controls(false)
+                .round_corner(false)
+        };
+        let plain = builder().build().unwrap().format(&lines, theme);
+        let mut formatter = builder().caption(Some(caption.clone())).build().unwrap();
+        let image = formatter.format(&lines, theme);
+
+        let bar_height = formatter.get_caption_height();
+        assert!(bar_height > 0);
+        assert_eq!(image.height(), plain.height() + bar_height);
+        assert!(image.width() >= formatter.code_pad + formatter.font.width(&caption));
+        // the bar is tinted
+        let background = theme.settings.background.unwrap().to_rgba();
+        assert_eq!(*image.get_pixel(1, 1), background);
+        assert_ne!(*image.get_pixel(1, image.height() - 2), background);
+    }
+
+    #[test]
+    fn indent_guide_segments() {
+        let style = Style::default();
+        let code = "fn a() {\n    if b {\n\tc();\n\n        d();\n    }\n}\n";
+        let indents = code
+            .split_inclusive('\n')
+            .map(|line| indent_columns(&[(style, line)], 4))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            indents,
+            vec![Some(0), Some(4), Some(4), None, Some(8), Some(4), Some(0)]
+        );
+
+        let mut guides = indent_guides(&indents, 4);
+        guides.sort();
+        // the blank line joins the guides around it
+        assert_eq!(guides, vec![(0, 1, 5), (4, 4, 4)]);
+        assert!(indent_guides(&indents, 0).is_empty());
+    }
+
+    #[test]
+    fn gutter_bars() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let lines = vec![vec![(Style::default(), "x\n")]; 3];
+        let red = Rgba([0xff, 0, 0, 0xff]);
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .window_controls(false)
+            .round_corner(false)
+            .gutter_bars(vec![(2, 0.5, red), (3, 0.0, red), (9, 1.0, red)])
+            .build()
+            .unwrap();
+        let image = formatter.format(&lines, theme);
+
+        // the bar grows from the left edge to half of the gutter
+        let gutter_width = formatter.get_left_pad() - formatter.line_number_pad;
+        let width = (gutter_width as f32 * 0.5).round() as u32;
+        let y = formatter.get_line_y(1);
+        assert_eq!(*image.get_pixel(0, y), red);
+        assert_eq!(*image.get_pixel(width - 1, y), red);
+        assert_ne!(*image.get_pixel(width, y), red);
+        assert_ne!(*image.get_pixel(0, formatter.get_line_y(0)), red);
+        assert_ne!(*image.get_pixel(0, formatter.get_line_y(2)), red);
+    }
+
+    #[test]
+    fn highlight_ranges() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let lines = vec![vec![(Style::default(), "abcdef\n")]];
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .window_controls(false)
+            .round_corner(false)
+            .highlight_ranges(vec![(1, 2, 3), (2, 1, 1), (1, 5, 4)])
+            .build()
+            .unwrap();
+        let image = formatter.format(&lines, theme);
+
+        let background = theme.settings.background.unwrap().to_rgba();
+        let mut color = theme.settings.foreground.unwrap().to_rgba();
+        color.0[3] = 0x40;
+        let mut expected = background;
+        expected.blend(&color);
+
+        // the columns 2 to 3 are covered, at the top of the line above the glyphs
+        let (x0, x1) = (
+            formatter.get_column_x("abcdef", 1),
+            formatter.get_column_x("abcdef", 3),
+        );
+        let y = formatter.get_line_y(0);
+        assert_eq!(*image.get_pixel(x0, y), expected);
+        assert_eq!(*image.get_pixel(x1 - 1, y), expected);
+        assert_eq!(*image.get_pixel(x0 - 1, y), background);
+        assert_eq!(*image.get_pixel(x1, y), background);
+    }
+
+    #[test]
+    fn control_pictures() {
+        assert_eq!(control_picture('\x00'), Some('\u{2400}'));
+        assert_eq!(control_picture('\x1b'), Some('\u{241b}'));
+        assert_eq!(control_picture('\x7f'), Some('\u{2421}'));
+        assert_eq!(control_picture('\t'), None);
+        assert_eq!(control_picture('\n'), None);
+        assert_eq!(control_picture('a'), None);
+
+        let color = Color::WHITE;
+        let dimmed = dim_color(color);
+        assert_ne!(dimmed, color);
+        assert_eq!(
+            split_control_chars("ab\x07\x07c", color),
+            vec![
+                (color, "ab".to_owned()),
+                (dimmed, "\u{2407}\u{2407}".to_owned()),
+                (color, "c".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn line_number_align_and_side() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        // two digits, so that the first number is padded
+        let lines = vec![vec![(Style::default(), "let x = 1;\n")]; 12];
+        let color = Rgba([0x12, 0xab, 0x34, 0xff]);
+
+        // the columns of the pixels of the first line number
+        let number_columns = |align, side| {
+            let mut formatter = ImageFormatterBuilder::<String>::new()
+                .window_controls(false)
+                .round_corner(false)
+                .line_number_color(Some(color))
+                .line_number_align(align)
+                .line_number_side(side)
+                .build()
+                .unwrap();
+            let image = formatter.format(&lines, theme);
+            let (top, bottom) = (formatter.get_line_y(0), formatter.get_line_y(1));
+            let columns = (top..bottom)
+                .flat_map(|y| (0..image.width()).map(move |x| (x, y)))
+                .filter(|&(x, y)| *image.get_pixel(x, y) == color)
+                .map(|(x, _)| x)
+                .collect::<Vec<_>>();
+            assert!(!columns.is_empty());
+            (formatter, image.width(), columns)
+        };
+
+        let (mut formatter, _, right) =
+            number_columns(LineNumberAlign::Right, LineNumberSide::Left);
+        let (_, _, left) = number_columns(LineNumberAlign::Left, LineNumberSide::Left);
+        let digit = formatter.font.width("0");
+        assert!(right.iter().all(|&x| x >= formatter.code_pad + digit));
+        assert!(left.iter().all(|&x| x < formatter.code_pad + digit));
+        assert!(right.iter().all(|&x| x < formatter.get_left_pad()));
+
+        // the gutter is on the right of the code, which starts at the left padding
+        let (mut formatter, width, columns) =
+            number_columns(LineNumberAlign::Right, LineNumberSide::Right);
+        assert_eq!(formatter.get_left_pad(), formatter.code_pad);
+        let gutter_left = width - formatter.code_pad_right - formatter.get_gutter_width();
+        assert!(columns.iter().all(|&x| x >= gutter_left));
+    }
+
+    #[test]
+    fn title_color() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let lines = vec![vec![(Style::default(), "x\n")]];
+        let color = Rgba([0x12, 0xab, 0x34, 0xff]);
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .window_title(Some("main.rs".to_owned()))
+            .title_color(Some(color))
+            .build()
+            .unwrap();
+        let image = formatter.format(&lines, theme);
+
+        let in_title_bar = |y| y < formatter.code_pad_top;
+        let colored = image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| **pixel == color)
+            .collect::<Vec<_>>();
+        assert!(!colored.is_empty());
+        assert!(colored.iter().all(|&(_, y, _)| in_title_bar(y)));
+    }
+
+    #[test]
+    fn cursor_line() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let lines = vec![vec![(Style::default(), "x\n")]; 3];
+        let color = Rgba([0, 0xff, 0, 0xff]);
+        let mut formatter = ImageFormatterBuilder::<String>::new()
+            .window_controls(false)
+            .round_corner(false)
+            .cursor_line(Some(2))
+            .cursor_line_color(Some(color))
+            .build()
+            .unwrap();
+        let image = formatter.format(&lines, theme);
+
+        let background = theme.settings.background.unwrap().to_rgba();
+        let mut fill = color;
+        fill.0[3] /= 4;
+        let mut filled = background;
+        filled.blend(&fill);
+
+        // the bar on the left and the translucent fill of the line
+        let y = formatter.get_line_y(1);
+        let bar_width = (formatter.code_pad / 6).max(1);
+        assert_eq!(*image.get_pixel(0, y), color);
+        assert_eq!(*image.get_pixel(bar_width - 1, y), color);
+        assert_eq!(*image.get_pixel(bar_width, y), filled);
+        assert_eq!(*image.get_pixel(image.width() - 1, y), filled);
+        assert_eq!(*image.get_pixel(0, formatter.get_line_y(0)), background);
+        assert_eq!(*image.get_pixel(0, formatter.get_line_y(2)), background);
+    }
+
+    #[test]
+    fn line_number_separator() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let lines = vec![vec![(Style::default(), "x\n")]; 3];
+        let color = Rgba([0xff, 0, 0, 0xff]);
+        let format = |separator| {
+            let mut formatter = ImageFormatterBuilder::<String>::new()
+                .window_controls(false)
+                .round_corner(false)
+                .line_number_color(Some(color))
+                .line_number_separator(separator)
+                .build()
+                .unwrap();
+            let image = formatter.format(&lines, theme);
+            let x = formatter.get_left_pad() - formatter.line_number_pad;
+            let (top, bottom) = (formatter.get_line_y(0), formatter.get_line_y(3) - 1);
+            (image, x, top, bottom)
+        };
+
+        let background = theme.settings.background.unwrap().to_rgba();
+        let mut expected = background;
+        expected.blend(&Rgba([0xff, 0, 0, 0x40]));
+
+        // a rule from the first line to the last one, between the numbers and the code
+        let (image, x, top, bottom) = format(true);
+        assert_eq!(*image.get_pixel(x, top), expected);
+        assert_eq!(*image.get_pixel(x, bottom), expected);
+        assert_eq!(*image.get_pixel(x, top - 1), background);
+        assert_eq!(*image.get_pixel(x + 1, top), background);
+
+        let (image, x, top, _) = format(false);
+        assert_eq!(*image.get_pixel(x, top), background);
+    }
+
+    #[test]
+    fn fade_unfocused_lines() {
+        let theme = &HighlightingAssets::new().theme_set.themes["Dracula"];
+        let lines = vec![vec![(Style::default(), "xxxx\n")]; 2];
+        let format = |code_background| {
+            let mut formatter = ImageFormatterBuilder::<String>::new()
+                .window_controls(false)
+                .round_corner(false)
+                .code_background(code_background)
+                .focus_lines(vec![1])
+                .build()
+                .unwrap();
+            let image = formatter.format(&lines, theme);
+            let mut line = |i| {
+                let (top, bottom) = (formatter.get_line_y(i), formatter.get_line_y(i + 1));
+                let (left, right) = (formatter.get_left_pad(), image.width());
+                (top..bottom)
+                    .flat_map(|y| (left..right).map(move |x| (x, y)))
+                    .map(|(x, y)| *image.get_pixel(x, y))
+                    .collect::<Vec<_>>()
+            };
+            (line(0), line(1))
+        };
+
+        // the glyphs of the unfocused line are drawn at 40% of their opacity
+        let (focused, unfocused) = format(Some(Rgba([0, 0, 0, 0])));
+        let max_alpha = |pixels: &[Rgba<u8>]| pixels.iter().map(|p| p.0[3]).max().unwrap();
+        assert_eq!(max_alpha(&focused), 0xff);
+        assert_eq!(max_alpha(&unfocused), 102);
+
+        // the glyphs over the opaque background move toward it
+        let background = theme.settings.background.unwrap().to_rgba();
+        let (focused, unfocused) = format(None);
+        let distance = |pixels: &[Rgba<u8>]| {
+            pixels
+                .iter()
+                .map(|p| {
+                    (0..3)
+                        .map(|i| p.0[i].abs_diff(background.0[i]) as u32)
+                        .sum::<u32>()
+                })
+                .max()
+                .unwrap()
+        };
+        assert!(distance(&unfocused) > 0);
+        assert!(distance(&unfocused) * 2 < distance(&focused));
+        assert!(unfocused.iter().all(|p| p.0[3] == 0xff));
+    }
 }
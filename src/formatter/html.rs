@@ -0,0 +1,146 @@
+//! Format the output of syntect into HTML
+use crate::formatter::{Formatter, DEFAULT_BACKGROUND, DEFAULT_FOREGROUND};
+use crate::utils::ToHtml;
+use syntect::highlighting::{FontStyle, Style, Theme};
+
+/// Formats the highlighted lines into a `<pre>` element with inline styles,
+/// so the code can be pasted into emails and documents as selectable text.
+#[derive(Default)]
+pub struct HtmlFormatter {
+    /// The CSS font families, tried in order
+    font_family: Vec<String>,
+}
+
+impl HtmlFormatter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the fonts of the code, `monospace` is always appended as the last fallback
+    pub fn font_family(mut self, fonts: Vec<String>) -> Self {
+        self.font_family = fonts;
+        self
+    }
+
+    fn css_font_family(&self) -> String {
+        self.font_family
+            .iter()
+            .map(|name| format!("'{}'", name.replace(['\'', '"', '\\'], "")))
+            .chain(std::iter::once("monospace".to_owned()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Escape the characters which have special meanings in HTML
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn span_style(style: &Style) -> String {
+    let mut css = format!("color:{}", style.foreground.to_html());
+    if style.font_style.contains(FontStyle::BOLD) {
+        css.push_str(";font-weight:bold");
+    }
+    if style.font_style.contains(FontStyle::ITALIC) {
+        css.push_str(";font-style:italic");
+    }
+    if style.font_style.contains(FontStyle::UNDERLINE) {
+        css.push_str(";text-decoration:underline");
+    }
+    css
+}
+
+impl Formatter for HtmlFormatter {
+    type Output = String;
+
+    fn format(&mut self, v: &[Vec<(Style, &str)>], theme: &Theme) -> Self::Output {
+        let background = theme.settings.background.unwrap_or(DEFAULT_BACKGROUND);
+        let foreground = theme.settings.foreground.unwrap_or(DEFAULT_FOREGROUND);
+
+        let mut html = format!(
+            "<pre style=\"background-color:{};color:{};font-family:{};padding:1em\">",
+            background.to_html(),
+            foreground.to_html(),
+            self.css_font_family()
+        );
+        for line in v {
+            for (style, text) in line {
+                // the line endings are kept outside of the spans
+                let content = text.trim_end_matches(['\r', '\n']);
+                if !content.is_empty() {
+                    html.push_str(&format!(
+                        "<span style=\"{}\">{}</span>",
+                        span_style(style),
+                        escape(content)
+                    ));
+                }
+                if content.len() != text.len() {
+                    html.push('\n');
+                }
+            }
+        }
+        html.push_str("</pre>\n");
+        html
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntect::highlighting::{Color, ThemeSettings};
+
+    #[test]
+    fn format_html() {
+        let theme = Theme {
+            settings: ThemeSettings {
+                background: Some(Color {
+                    r: 0x10,
+                    g: 0x20,
+                    b: 0x30,
+                    a: 0xff,
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let keyword = Style {
+            foreground: Color {
+                r: 0xff,
+                g: 0,
+                b: 0,
+                a: 0xff,
+            },
+            font_style: FontStyle::BOLD,
+            ..Default::default()
+        };
+        let plain = Style {
+            foreground: Color::BLACK,
+            ..Default::default()
+        };
+        let v = vec![
+            vec![(keyword, "if"), (plain, " a < b && c > d\n")],
+            vec![(plain, "}")],
+        ];
+
+        let html = HtmlFormatter::new()
+            .font_family(vec!["Hack".to_owned()])
+            .format(&v, &theme);
+        assert_eq!(
+            html,
+            "<pre style=\"background-color:#102030;color:#ffffff;font-family:'Hack', monospace;padding:1em\">\
+             <span style=\"color:#ff0000;font-weight:bold\">if</span>\
+             <span style=\"color:#000000\"> a &lt; b &amp;&amp; c &gt; d</span>\n\
+             <span style=\"color:#000000\">}</span></pre>\n"
+        );
+    }
+}
@@ -7,8 +7,12 @@ use font_kit::loaders::freetype::NativeFont;
 pub use harfbuzz::*;
 use harfbuzz_sys as harfbuzz;
 use std::mem;
+use std::os::raw::{c_int, c_long, c_uint};
 
-/// font feature tag
+/// The positions of shaped glyphs are in `1 / POSITION_SCALE` font units
+pub const POSITION_SCALE: i32 = 64;
+
+/// Parse a font feature in the syntax of harfbuzz, eg. `liga`, `calt=0`, `-liga` or `ss01=1`
 pub fn feature_from_tag(tag: &str) -> Result<hb_feature_t> {
     unsafe {
         let mut feature = mem::zeroed();
@@ -33,6 +37,13 @@ pub struct HBFont {
 // harfbuzz freetype integration
 extern "C" {
     pub fn hb_ft_font_create_referenced(face: NativeFont) -> *mut hb_font_t; // the same as hb_face_t
+    fn FT_Set_Char_Size(
+        face: NativeFont,
+        char_width: c_long,
+        char_height: c_long,
+        horz_resolution: c_uint,
+        vert_resolution: c_uint,
+    ) -> c_int;
 }
 
 impl Drop for HBFont {
@@ -42,11 +53,22 @@ impl Drop for HBFont {
 }
 
 impl HBFont {
+    /// Create the font, the positions of shaped glyphs are in `1 / POSITION_SCALE` font units.
+    ///
+    /// hb-ft takes the advances from the size of the FreeType face in 26.6 format, while the
+    /// offsets follow the scale of harfbuzz, so set both of them to `POSITION_SCALE` per unit.
     pub fn new(face: &Font) -> HBFont {
-        HBFont {
-            font: unsafe { hb_ft_font_create_referenced(face.native_font() as _) },
+        let scale = face.metrics().units_per_em as i32 * POSITION_SCALE;
+        unsafe {
+            let native = face.native_font();
+            // a size of `units_per_em` pixels per em at 72 dpi
+            FT_Set_Char_Size(native as _, scale as c_long, 0, 0, 0);
+            let font = hb_ft_font_create_referenced(native as _);
+            hb_font_set_scale(font, scale, scale);
+            HBFont { font }
         }
     }
+
     pub fn shape(&mut self, buffer: &HBBuffer, features: &[hb_feature_t]) {
         unsafe {
             hb_shape(
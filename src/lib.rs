@@ -37,7 +37,10 @@
 #[macro_use]
 extern crate log;
 
+pub mod ansi;
 pub mod assets;
+#[cfg(feature = "base16")]
+pub mod base16;
 pub mod blur;
 pub mod directories;
 pub mod error;
@@ -46,3 +49,5 @@ pub mod formatter;
 #[cfg(feature = "harfbuzz")]
 pub mod hb_wrapper;
 pub mod utils;
+#[cfg(feature = "vscode")]
+pub mod vscode;
@@ -1,5 +1,28 @@
+//! Helpers to decorate the image: window controls, rounded corners, shadow and background
+//!
+//! They work on any `RgbaImage`, not only the ones produced by the formatter.
+//! A `DynamicImage` can be converted with `into_rgba8`.
+//!
+//! # Example
+//!
+//! ```
+//! use image::{Rgba, RgbaImage};
+//! use silicon::utils::{add_window_controls, round_corner, Corners, ShadowAdder, WindowControlsParams};
+//!
+//! let mut image = RgbaImage::from_pixel(400, 300, Rgba([0x28, 0x2a, 0x36, 0xff]));
+//! let params = WindowControlsParams {
+//!     width: 120,
+//!     height: 40,
+//!     padding: 10,
+//!     radius: 10,
+//! };
+//! add_window_controls(&mut image, &params);
+//! round_corner(&mut image, 12, Corners::ALL);
+//! let image = ShadowAdder::new().apply_to(&image);
+//! ```
+use crate::blur::BlurKind;
 use crate::error::ParseColorError;
-use image::imageops::{crop_imm, resize, FilterType};
+use image::imageops::{crop_imm, overlay, resize, FilterType};
 use image::Pixel;
 use image::{GenericImage, GenericImageView, Rgba, RgbaImage};
 use imageproc::drawing::{draw_filled_rect_mut, draw_line_segment_mut};
@@ -66,25 +89,53 @@ impl ToRgba for syntect::highlighting::Color {
     }
 }
 
+pub trait ToHtml {
+    fn to_html(&self) -> String;
+}
+
+/// Format the color as `#rrggbb`, or `rgba(r, g, b, a)` if it's not opaque
+impl ToHtml for syntect::highlighting::Color {
+    fn to_html(&self) -> String {
+        if self.a == 0xff {
+            format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+        } else {
+            format!(
+                "rgba({}, {}, {}, {:.3})",
+                self.r,
+                self.g,
+                self.b,
+                self.a as f32 / 255.0
+            )
+        }
+    }
+}
+
+/// The size and position of the window controls
 pub struct WindowControlsParams {
+    /// Width of the area of the three buttons
     pub width: u32,
+    /// Height of the area of the three buttons
     pub height: u32,
+    /// Distance from the top left corner of the image
     pub padding: u32,
+    /// Radius of each button
     pub radius: u32,
 }
 
-/// Add the window controls for image
-pub(crate) fn add_window_controls(image: &mut RgbaImage, params: &WindowControlsParams) {
+/// Add the window controls (the three colored buttons) to the top left of the image
+///
+/// The buttons are drawn over the color of the pixel at `(37, 37)`, so the image should be larger than it.
+pub fn add_window_controls(image: &mut RgbaImage, params: &WindowControlsParams) {
     let color = [
         ("#FF5F56", "#E0443E"),
         ("#FFBD2E", "#DEA123"),
         ("#27C93F", "#1AAB29"),
     ];
 
-    let background = image.get_pixel_mut(37, 37);
+    let mut background = *image.get_pixel(37, 37);
     background.0[3] = 0;
 
-    let mut title_bar = RgbaImage::from_pixel(params.width * 3, params.height * 3, *background);
+    let mut title_bar = RgbaImage::from_pixel(params.width * 3, params.height * 3, background);
     let step = (params.radius * 2) as i32;
     let spacer = step * 2;
     let center_y = (params.height / 2) as i32;
@@ -112,13 +163,32 @@ pub(crate) fn add_window_controls(image: &mut RgbaImage, params: &WindowControls
         FilterType::Triangle,
     );
 
-    copy_alpha(&title_bar, image, params.padding, params.padding);
+    copy_alpha(
+        &title_bar,
+        image,
+        params.padding as i32,
+        params.padding as i32,
+    );
+}
+
+/// How the background image fits the size of the image
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum BackgroundFit {
+    /// Resize to the size, ignoring the aspect ratio
+    #[default]
+    Stretch,
+    /// Repeat the image
+    Tile,
+    /// Put the image in the center without scaling, the remainder is filled with the default color
+    Center,
+    /// Scale the image preserving the aspect ratio to cover the size, and crop the overflow
+    Cover,
 }
 
 #[derive(Clone, Debug)]
 pub enum Background {
     Solid(Rgba<u8>),
-    Image(RgbaImage),
+    Image(RgbaImage, BackgroundFit),
 }
 
 impl Default for Background {
@@ -131,19 +201,111 @@ impl Background {
     fn to_image(&self, width: u32, height: u32) -> RgbaImage {
         match self {
             Background::Solid(color) => RgbaImage::from_pixel(width, height, color.to_owned()),
-            Background::Image(image) => resize(image, width, height, FilterType::Triangle),
+            // there's nothing to tile or scale in an empty image
+            Background::Image(image, _) if image.width() == 0 || image.height() == 0 => {
+                Background::default().to_image(width, height)
+            }
+            Background::Image(image, fit) => {
+                let (w, h) = image.dimensions();
+                match fit {
+                    BackgroundFit::Stretch => resize(image, width, height, FilterType::Triangle),
+                    BackgroundFit::Tile => {
+                        RgbaImage::from_fn(width, height, |x, y| *image.get_pixel(x % w, y % h))
+                    }
+                    BackgroundFit::Center => {
+                        let mut canvas = Background::default().to_image(width, height);
+                        let x = (i64::from(width) - i64::from(w)) / 2;
+                        let y = (i64::from(height) - i64::from(h)) / 2;
+                        overlay(&mut canvas, image, x, y);
+                        canvas
+                    }
+                    BackgroundFit::Cover => {
+                        let scale = (width as f32 / w as f32).max(height as f32 / h as f32);
+                        let (w, h) = (
+                            ((w as f32 * scale).ceil() as u32).max(width),
+                            ((h as f32 * scale).ceil() as u32).max(height),
+                        );
+                        let image = resize(image, w, h, FilterType::Triangle);
+                        crop_imm(&image, (w - width) / 2, (h - height) / 2, width, height)
+                            .to_image()
+                    }
+                }
+            }
+        }
+    }
+
+    /// Blend the background toward `tint`, so the code stands out of a busy background image.
+    ///
+    /// `opacity` is how much of the original background is kept, from 0 (the tint only)
+    /// to 1 (unchanged). The alpha channel isn't changed.
+    pub fn tint(self, tint: Rgba<u8>, opacity: f32) -> Self {
+        let opacity = opacity.clamp(0.0, 1.0);
+        let blend = |mut pixel: Rgba<u8>| {
+            for (c, &t) in pixel.0[..3].iter_mut().zip(&tint.0[..3]) {
+                *c = (f32::from(*c) * opacity + f32::from(t) * (1.0 - opacity)).round() as u8;
+            }
+            pixel
+        };
+        match self {
+            Background::Solid(color) => Background::Solid(blend(color)),
+            Background::Image(mut image, fit) => {
+                image.pixels_mut().for_each(|pixel| *pixel = blend(*pixel));
+                Background::Image(image, fit)
+            }
+        }
+    }
+
+    /// Get the average color of the background
+    fn average_color(&self) -> Rgba<u8> {
+        match self {
+            Background::Solid(color) => *color,
+            Background::Image(image, _) => {
+                let mut sum = [0u64; 4];
+                for pixel in image.pixels() {
+                    for (s, &c) in sum.iter_mut().zip(pixel.0.iter()) {
+                        *s += u64::from(c);
+                    }
+                }
+                let count = u64::from(image.width()) * u64::from(image.height());
+                let count = count.max(1);
+                Rgba(sum.map(|s| (s / count) as u8))
+            }
         }
     }
+
+    /// Derive a shadow color contrasting with the background:
+    /// light backgrounds get a darker shadow and dark backgrounds get a lighter one.
+    fn contrasting_shadow_color(&self) -> Rgba<u8> {
+        let Rgba([r, g, b, _]) = self.average_color();
+        let luminance = 0.2126 * f32::from(r) + 0.7152 * f32::from(g) + 0.0722 * f32::from(b);
+
+        let (target, ratio) = if luminance >= 128.0 {
+            (0.0, 0.6)
+        } else {
+            (255.0, 0.3)
+        };
+        let mix = |c: u8| (f32::from(c) * (1.0 - ratio) + target * ratio).round() as u8;
+
+        Rgba([mix(r), mix(g), mix(b), 0xff])
+    }
 }
 
 /// Add the shadow for image
 #[derive(Debug)]
 pub struct ShadowAdder {
     background: Background,
-    shadow_color: Rgba<u8>,
+    /// Whether to draw the shadow, the background and paddings are kept if not
+    enabled: bool,
+    /// Derived from the background if not set
+    shadow_color: Option<Rgba<u8>>,
     blur_radius: f32,
-    pad_horiz: u32,
-    pad_vert: u32,
+    blur_passes: usize,
+    blur_kind: BlurKind,
+    softness: f32,
+    pad_top: u32,
+    pad_bottom: u32,
+    pad_left: u32,
+    pad_right: u32,
     offset_x: i32,
     offset_y: i32,
 }
@@ -152,10 +314,16 @@ impl ShadowAdder {
     pub fn new() -> Self {
         Self {
             background: Background::default(),
-            shadow_color: "#707070".to_rgba().unwrap(),
+            enabled: true,
+            shadow_color: None,
             blur_radius: 50.0,
-            pad_horiz: 80,
-            pad_vert: 100,
+            blur_passes: 3,
+            blur_kind: BlurKind::default(),
+            softness: 0.0,
+            pad_top: 100,
+            pad_bottom: 100,
+            pad_left: 80,
+            pad_right: 80,
             offset_x: 0,
             offset_y: 0,
         }
@@ -167,25 +335,84 @@ impl ShadowAdder {
         self
     }
 
-    /// Set the shadow color
+    /// Whether to draw the shadow. If disabled, only the background and paddings are added.
+    /// Default: true
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// Set the shadow color.
+    /// If not set, a color contrasting with the background will be used.
     pub fn shadow_color(mut self, color: Rgba<u8>) -> Self {
-        self.shadow_color = color;
+        self.shadow_color = Some(color);
         self
     }
 
-    /// Set the shadow size
+    /// Get the shadow color, derived from the background if not set
+    fn get_shadow_color(&self) -> Rgba<u8> {
+        self.shadow_color
+            .unwrap_or_else(|| self.background.contrasting_shadow_color())
+    }
+
+    /// Set the shadow size, the shadow is sharp if it's 0
     pub fn blur_radius(mut self, sigma: f32) -> Self {
         self.blur_radius = sigma;
         self
     }
 
+    /// Set the number of box blur passes used to approximate the Gaussian blur.
+    /// Fewer passes are faster but less smooth. Default: 3
+    pub fn blur_passes(mut self, passes: usize) -> Self {
+        self.blur_passes = passes;
+        self
+    }
+
+    /// Set how to blur the shadow. `BlurKind::TrueGaussian` is smoother at small radii
+    /// but slower at big ones. Default: `BlurKind::BoxApprox`
+    pub fn blur_kind(mut self, kind: BlurKind) -> Self {
+        self.blur_kind = kind;
+        self
+    }
+
+    /// Set how much the opacity of the shadow falls off from its center before blurring,
+    /// from 0 (a uniform rect) to 1 (fading from the center). Default: 0
+    pub fn shadow_softness(mut self, softness: f32) -> Self {
+        self.softness = softness.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the paddings on the left and the right
     pub fn pad_horiz(mut self, pad: u32) -> Self {
-        self.pad_horiz = pad;
+        self.pad_left = pad;
+        self.pad_right = pad;
         self
     }
 
+    /// Set the paddings on the top and the bottom
     pub fn pad_vert(mut self, pad: u32) -> Self {
-        self.pad_vert = pad;
+        self.pad_top = pad;
+        self.pad_bottom = pad;
+        self
+    }
+
+    pub fn pad_top(mut self, pad: u32) -> Self {
+        self.pad_top = pad;
+        self
+    }
+
+    pub fn pad_bottom(mut self, pad: u32) -> Self {
+        self.pad_bottom = pad;
+        self
+    }
+
+    pub fn pad_left(mut self, pad: u32) -> Self {
+        self.pad_left = pad;
+        self
+    }
+
+    pub fn pad_right(mut self, pad: u32) -> Self {
+        self.pad_right = pad;
         self
     }
 
@@ -199,32 +426,142 @@ impl ShadowAdder {
         self
     }
 
-    pub fn apply_to(&self, image: &RgbaImage) -> RgbaImage {
-        // the size of the final image
-        let width = image.width() + self.pad_horiz * 2;
-        let height = image.height() + self.pad_vert * 2;
-
-        // create the shadow
-        let mut shadow = self.background.to_image(width, height);
-        if self.blur_radius > 0.0 {
-            let rect = Rect::at(
-                self.pad_horiz as i32 + self.offset_x,
-                self.pad_vert as i32 + self.offset_y,
-            )
-            .of_size(image.width(), image.height());
+    /// Scale the paddings, offsets and blur radius by a factor
+    pub(crate) fn scale(mut self, scale: f32) -> Self {
+        let px = |value: u32| (value as f32 * scale).round() as u32;
+        self.blur_radius *= scale;
+        self.pad_top = px(self.pad_top);
+        self.pad_bottom = px(self.pad_bottom);
+        self.pad_left = px(self.pad_left);
+        self.pad_right = px(self.pad_right);
+        self.offset_x = (self.offset_x as f32 * scale).round() as i32;
+        self.offset_y = (self.offset_y as f32 * scale).round() as i32;
+        self
+    }
 
-            draw_filled_rect_mut(&mut shadow, rect, self.shadow_color);
+    /// Get the size of the result of `apply_to` for an image of the given size.
+    ///
+    /// The canvas grows by the offsets of the shadow, so that the shadow isn't clipped.
+    pub fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        (
+            width + self.pad_left + self.pad_right + self.offset_x.unsigned_abs(),
+            height + self.pad_top + self.pad_bottom + self.offset_y.unsigned_abs(),
+        )
+    }
+
+    /// Get the position of the image in the result of `apply_to`.
+    ///
+    /// A negative offset moves the shadow to the left / top, so the image is moved the other way.
+    fn image_origin(&self) -> (i32, i32) {
+        (
+            (self.pad_left + (-self.offset_x).max(0) as u32) as i32,
+            (self.pad_top + (-self.offset_y).max(0) as u32) as i32,
+        )
+    }
+
+    /// Draw the shadow of an image on the canvas
+    fn draw_shadow(&self, mut canvas: RgbaImage, image: &RgbaImage) -> RgbaImage {
+        if self.enabled {
+            let (x, y) = self.image_origin();
+            let rect = Rect::at(x + self.offset_x, y + self.offset_y)
+                .of_size(image.width(), image.height());
+
+            if self.softness > 0.0 {
+                let mask = self.soft_shadow_mask(image.width(), image.height());
+                copy_alpha(&mask, &mut canvas, rect.left(), rect.top());
+            } else {
+                draw_filled_rect_mut(&mut canvas, rect, self.get_shadow_color());
+            }
 
-            shadow = crate::blur::gaussian_blur(shadow, self.blur_radius);
+            if self.blur_radius > 0.0 {
+                canvas = match self.blur_kind {
+                    BlurKind::BoxApprox => {
+                        crate::blur::gaussian_blur(canvas, self.blur_radius, self.blur_passes)
+                    }
+                    BlurKind::TrueGaussian => {
+                        crate::blur::true_gaussian_blur(canvas, self.blur_radius)
+                    }
+                };
+            }
         }
         // it's to slow!
         // shadow = blur(&shadow, self.blur_radius);
+        canvas
+    }
 
-        // copy the original image to the top of it
-        copy_alpha(image, &mut shadow, self.pad_horiz, self.pad_vert);
+    /// Create the shadow rect whose opacity falls off radially from its center.
+    ///
+    /// The distance is relative to the half size of the rect, from 0 at the center to 1 at the
+    /// corners, so the falloff is elliptic for a non-square rect. The opacity fades over the
+    /// last `softness` part of it.
+    fn soft_shadow_mask(&self, width: u32, height: u32) -> RgbaImage {
+        let color = self.get_shadow_color();
+        let (cx, cy) = (width as f32 / 2.0, height as f32 / 2.0);
+        RgbaImage::from_fn(width, height, |x, y| {
+            let dx = (x as f32 + 0.5 - cx) / cx;
+            let dy = (y as f32 + 0.5 - cy) / cy;
+            let distance = dx.hypot(dy) / std::f32::consts::SQRT_2;
+            let opacity = ((1.0 - distance) / self.softness).clamp(0.0, 1.0);
+            let mut pixel = color;
+            pixel.0[3] = (f32::from(color.0[3]) * opacity).round() as u8;
+            pixel
+        })
+    }
+
+    /// Draw the shadow alone on a transparent canvas of the given size
+    fn shadow_layer(&self, width: u32, height: u32, image: &RgbaImage) -> RgbaImage {
+        let mut transparent = self.get_shadow_color();
+        transparent.0[3] = 0;
+        self.draw_shadow(RgbaImage::from_pixel(width, height, transparent), image)
+    }
 
+    pub fn apply_to(&self, image: &RgbaImage) -> RgbaImage {
+        let mut shadow = self.backdrop(image);
+        self.paste_onto(image, &mut shadow);
         shadow
     }
+
+    /// Create the background with the shadow of the image, but without the image itself
+    pub(crate) fn backdrop(&self, image: &RgbaImage) -> RgbaImage {
+        // the size of the final image
+        let (width, height) = self.output_size(image.width(), image.height());
+
+        match self.background {
+            // blurring over a translucent background would mix the shadow with its color
+            // and make it opaque, so blur the shadow alone and put it over the background
+            Background::Solid(color) if color.0[3] != 0xff => {
+                let mut canvas = self.background.to_image(width, height);
+                copy_alpha(&self.shadow_layer(width, height, image), &mut canvas, 0, 0);
+                canvas
+            }
+            _ => self.draw_shadow(self.background.to_image(width, height), image),
+        }
+    }
+
+    /// Copy the image to the top of the backdrop created by `backdrop`
+    pub(crate) fn paste_onto(&self, image: &RgbaImage, backdrop: &mut RgbaImage) {
+        let (x, y) = self.image_origin();
+        copy_alpha(image, backdrop, x, y);
+    }
+
+    /// Get the layers of the result of `apply_to`, without the background.
+    ///
+    /// Return `(panel, shadow)`, both of them have the same size as the final image
+    /// and are transparent outside of the panel / shadow.
+    pub fn layers(&self, image: &RgbaImage) -> (RgbaImage, RgbaImage) {
+        let (width, height) = self.output_size(image.width(), image.height());
+
+        let mut transparent = self.get_shadow_color();
+        transparent.0[3] = 0;
+
+        let mut panel = RgbaImage::from_pixel(width, height, transparent);
+        let (x, y) = self.image_origin();
+        copy_alpha(image, &mut panel, x, y);
+
+        let shadow = self.shadow_layer(width, height, image);
+
+        (panel, shadow)
+    }
 }
 
 impl Default for ShadowAdder {
@@ -233,32 +570,103 @@ impl Default for ShadowAdder {
     }
 }
 
-/// copy from src to dst, taking into account alpha channels
-pub(crate) fn copy_alpha(src: &RgbaImage, dst: &mut RgbaImage, x: u32, y: u32) {
-    assert!(src.width() + x <= dst.width());
-    assert!(src.height() + y <= dst.height());
-    for j in 0..src.height() {
-        for i in 0..src.width() {
+/// Put the image at the center of a canvas of the given size filled with the background.
+///
+/// The image is scaled down first if it's larger than the canvas, keeping its aspect ratio.
+/// It should already be filled with the background (eg. by `ShadowAdder`), its pixels replace
+/// the ones of the canvas so that a translucent background isn't blended twice.
+pub fn fit_to_canvas(
+    image: &RgbaImage,
+    width: u32,
+    height: u32,
+    background: &Background,
+) -> RgbaImage {
+    let scale = (width as f32 / image.width() as f32)
+        .min(height as f32 / image.height() as f32)
+        .min(1.0);
+    let scaled;
+    let image = if scale < 1.0 {
+        let w = ((image.width() as f32 * scale).round() as u32).clamp(1, width);
+        let h = ((image.height() as f32 * scale).round() as u32).clamp(1, height);
+        scaled = resize(image, w, h, FilterType::Triangle);
+        &scaled
+    } else {
+        image
+    };
+
+    let mut canvas = background.to_image(width, height);
+    let x = (width - image.width()) / 2;
+    let y = (height - image.height()) / 2;
+    canvas.copy_from(image, x, y).unwrap();
+    canvas
+}
+
+/// Copy `src` to `dst` at `(x, y)`, blending the translucent pixels of `src` over `dst`
+///
+/// The part of src which is out of the bounds of dst is skipped.
+pub fn copy_alpha(src: &RgbaImage, dst: &mut RgbaImage, x: i32, y: i32) {
+    // the range of src which lies inside dst
+    let clip = |offset: i32, src_len: u32, dst_len: u32| {
+        let start = (-i64::from(offset)).clamp(0, i64::from(src_len)) as u32;
+        let end = (i64::from(dst_len) - i64::from(offset)).clamp(0, i64::from(src_len)) as u32;
+        start..end
+    };
+    let cols = clip(x, src.width(), dst.width());
+    for j in clip(y, src.height(), dst.height()) {
+        for i in cols.clone() {
+            let (dx, dy) = ((i as i32 + x) as u32, (j as i32 + y) as u32);
             // NOTE: Undeprecate in https://github.com/image-rs/image/pull/1008
             #[allow(deprecated)]
             unsafe {
                 let s = src.unsafe_get_pixel(i, j);
-                let mut d = dst.unsafe_get_pixel(i + x, j + y);
+                let mut d = dst.unsafe_get_pixel(dx, dy);
                 match s.0[3] {
                     255 => d = s,
                     0 => (/* do nothing */),
                     _ => d.blend(&s),
                 }
-                dst.unsafe_put_pixel(i + x, j + y, d);
+                dst.unsafe_put_pixel(dx, dy, d);
             }
         }
     }
 }
 
-/// Round the corner of the image
-pub(crate) fn round_corner(image: &mut RgbaImage, radius: u32) {
-    // draw a circle with given foreground on given background
-    // then split it into four pieces and paste them to the four corner of the image
+/// A set of corners of the image
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Corners(u8);
+
+impl Corners {
+    pub const NONE: Corners = Corners(0);
+    pub const TOP_LEFT: Corners = Corners(1);
+    pub const TOP_RIGHT: Corners = Corners(1 << 1);
+    pub const BOTTOM_LEFT: Corners = Corners(1 << 2);
+    pub const BOTTOM_RIGHT: Corners = Corners(1 << 3);
+    pub const ALL: Corners = Corners(0b1111);
+
+    /// Whether all the corners in `other` are in `self`
+    pub fn contains(self, other: Corners) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl Default for Corners {
+    fn default() -> Self {
+        Corners::ALL
+    }
+}
+
+impl std::ops::BitOr for Corners {
+    type Output = Corners;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Corners(self.0 | rhs.0)
+    }
+}
+
+/// Round the given corners of the image, making the outside of them transparent
+pub fn round_corner(image: &mut RgbaImage, radius: u32, corners: Corners) {
+    // draw a circle, then split it into four pieces and use them as the alpha masks
+    // of the four corners, so that the corners keep their own colors
     //
     // the circle is drawn on a bigger image to avoid the aliasing
     // later it will be scaled to the correct size
@@ -270,14 +678,11 @@ pub(crate) fn round_corner(image: &mut RgbaImage, radius: u32) {
     let width = image.width();
     let height = image.height();
 
-    // use the bottom right pixel to get the color of the foreground
-    let foreground = image.get_pixel(width - 1, height - 1);
-
     draw_filled_circle_mut(
         &mut circle,
         (((radius + 1) * 2) as i32, ((radius + 1) * 2) as i32),
         radius as i32 * 2,
-        *foreground,
+        Rgba([255, 255, 255, 255]),
     );
 
     // scale down the circle to the correct size
@@ -289,22 +694,36 @@ pub(crate) fn round_corner(image: &mut RgbaImage, radius: u32) {
     );
 
     // top left
-    let part = crop_imm(&circle, 1, 1, radius, radius);
-    image.copy_from(&*part, 0, 0).unwrap();
+    if corners.contains(Corners::TOP_LEFT) {
+        let part = crop_imm(&circle, 1, 1, radius, radius);
+        mask_alpha(image, &part.to_image(), 0, 0);
+    }
 
     // top right
-    let part = crop_imm(&circle, radius + 1, 1, radius, radius - 1);
-    image.copy_from(&*part, width - radius, 0).unwrap();
+    if corners.contains(Corners::TOP_RIGHT) {
+        let part = crop_imm(&circle, radius + 1, 1, radius, radius - 1);
+        mask_alpha(image, &part.to_image(), width - radius, 0);
+    }
 
     // bottom left
-    let part = crop_imm(&circle, 1, radius + 1, radius, radius);
-    image.copy_from(&*part, 0, height - radius).unwrap();
+    if corners.contains(Corners::BOTTOM_LEFT) {
+        let part = crop_imm(&circle, 1, radius + 1, radius, radius);
+        mask_alpha(image, &part.to_image(), 0, height - radius);
+    }
 
     // bottom right
-    let part = crop_imm(&circle, radius + 1, radius + 1, radius, radius);
-    image
-        .copy_from(&*part, width - radius, height - radius)
-        .unwrap();
+    if corners.contains(Corners::BOTTOM_RIGHT) {
+        let part = crop_imm(&circle, radius + 1, radius + 1, radius, radius);
+        mask_alpha(image, &part.to_image(), width - radius, height - radius);
+    }
+}
+
+/// Multiply the alpha of the image at `(x, y)` by the alpha of the mask
+fn mask_alpha(image: &mut RgbaImage, mask: &RgbaImage, x: u32, y: u32) {
+    for (i, j, m) in mask.enumerate_pixels() {
+        let pixel = image.get_pixel_mut(x + i, y + j);
+        pixel.0[3] = (u16::from(pixel.0[3]) * u16::from(m.0[3]) / 255) as u8;
+    }
 }
 
 // `draw_filled_circle_mut` doesn't work well with small radius in imageproc v0.18.0
@@ -365,8 +784,28 @@ pub(crate) fn draw_filled_circle_mut<I>(
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::ToRgba;
-    use image::Rgba;
+    use crate::utils::{
+        copy_alpha, fit_to_canvas, round_corner, Background, BackgroundFit, Corners, ShadowAdder,
+        ToHtml, ToRgba,
+    };
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn to_html() {
+        use syntect::highlighting::Color;
+
+        let color = Color {
+            r: 0xab,
+            g: 0xcd,
+            b: 0x0f,
+            a: 0xff,
+        };
+        assert_eq!(color.to_html(), "#abcd0f");
+        assert_eq!(
+            Color { a: 0, ..color }.to_html(),
+            "rgba(171, 205, 15, 0.000)"
+        );
+    }
 
     #[test]
     fn to_rgba() {
@@ -375,4 +814,287 @@ mod tests {
         assert_eq!("#abc".to_rgba(), Ok(Rgba([0xaa, 0xbb, 0xcc, 0xff])));
         assert_eq!("#abcd".to_rgba(), Ok(Rgba([0xaa, 0xbb, 0xcc, 0xdd])));
     }
+
+    #[test]
+    fn copy_alpha_clipped() {
+        let (white, black) = (Rgba([0xff; 4]), Rgba([0, 0, 0, 0xff]));
+        let src = RgbaImage::from_pixel(4, 4, white);
+
+        // partially off the left, right, top and bottom edge
+        for &(x, y) in &[(-2, 3), (8, 3), (3, -2), (3, 8)] {
+            let mut dst = RgbaImage::from_pixel(10, 10, black);
+            copy_alpha(&src, &mut dst, x, y);
+
+            for (i, j, pixel) in dst.enumerate_pixels() {
+                let (i, j) = (i as i32, j as i32);
+                let inside = (x..x + 4).contains(&i) && (y..y + 4).contains(&j);
+                assert_eq!(*pixel, if inside { white } else { black });
+            }
+        }
+
+        // completely out of the bounds
+        let mut dst = RgbaImage::from_pixel(10, 10, black);
+        copy_alpha(&src, &mut dst, -10, 20);
+        assert!(dst.pixels().all(|p| *p == black));
+    }
+
+    #[test]
+    fn transparent_background() {
+        let shadow_color = Rgba([0x55, 0x55, 0x55, 0xff]);
+        let image = RgbaImage::from_pixel(40, 20, Rgba([0xff; 4]));
+        let result = ShadowAdder::new()
+            .background(Background::Solid(Rgba([0, 0, 0, 0])))
+            .shadow_color(shadow_color)
+            .blur_radius(5.0)
+            .pad_horiz(20)
+            .pad_vert(20)
+            .apply_to(&image);
+
+        let path = std::env::temp_dir().join("silicon-transparent-background.png");
+        result.save(&path).unwrap();
+        let result = image::open(&path).unwrap().to_rgba8();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.get_pixel(0, 0).0[3], 0);
+        assert_eq!(*result.get_pixel(40, 30), Rgba([0xff; 4]));
+        // the soft edge of the shadow keeps its color and is translucent
+        let edge = result.get_pixel(17, 30);
+        assert!(edge.0[3] > 0 && edge.0[3] < 0xff);
+        assert_eq!(edge.0[..3], shadow_color.0[..3]);
+    }
+
+    #[test]
+    fn translucent_background() {
+        let background = Rgba([0xaa, 0xaa, 0xff, 0x80]);
+        let mut image = RgbaImage::from_pixel(40, 20, Rgba([0xff; 4]));
+        round_corner(&mut image, 8, Corners::ALL);
+        let adder = ShadowAdder::new()
+            .enabled(false)
+            .background(Background::Solid(background))
+            .pad_horiz(20)
+            .pad_vert(20);
+        let result = adder.apply_to(&image);
+        let result = fit_to_canvas(&result, 100, 80, &Background::Solid(background));
+
+        let path = std::env::temp_dir().join("silicon-translucent-background.png");
+        result.save(&path).unwrap();
+        let result = image::open(&path).unwrap().to_rgba8();
+        std::fs::remove_file(&path).unwrap();
+
+        // the padding, the canvas and the rounded corners of the panel keep the alpha
+        let (x, y) = adder.image_origin();
+        let (x, y) = (x as u32 + 10, y as u32 + 10);
+        assert_eq!(*result.get_pixel(0, 0), background);
+        assert_eq!(*result.get_pixel(15, 15), background);
+        assert_eq!(*result.get_pixel(x, y), background);
+        assert_eq!(*result.get_pixel(x + 20, y + 10), Rgba([0xff; 4]));
+    }
+
+    #[test]
+    fn large_shadow_offset() {
+        let shadow_color = Rgba([0, 0, 0, 0xff]);
+        let white = Rgba([0xff; 4]);
+        let image = RgbaImage::from_pixel(40, 20, white);
+        let adder = |x, y| {
+            ShadowAdder::new()
+                .background(Background::Solid(Rgba([0xff, 0, 0, 0xff])))
+                .shadow_color(shadow_color)
+                .blur_radius(1.0)
+                .pad_horiz(10)
+                .pad_vert(10)
+                .offset_x(x)
+                .offset_y(y)
+        };
+
+        // the shadow is fully inside the canvas, at the offset from the image
+        let result = adder(60, 30).apply_to(&image);
+        assert_eq!(result.dimensions(), (40 + 20 + 60, 20 + 20 + 30));
+        assert_eq!(*result.get_pixel(10, 10), white);
+        assert_eq!(*result.get_pixel(10 + 60 + 20, 10 + 30 + 10), shadow_color);
+
+        let result = adder(-60, -30).apply_to(&image);
+        assert_eq!(result.dimensions(), (40 + 20 + 60, 20 + 20 + 30));
+        assert_eq!(*result.get_pixel(10 + 60, 10 + 30), white);
+        assert_eq!(*result.get_pixel(10 + 20, 10 + 10), shadow_color);
+    }
+
+    #[test]
+    fn tint_background() {
+        let image = RgbaImage::from_pixel(2, 2, Rgba([200, 100, 0, 0x80]));
+        let black = Rgba([0, 0, 0, 0xff]);
+        match Background::Image(image, BackgroundFit::Tile).tint(black, 0.5) {
+            Background::Image(image, _) => {
+                assert_eq!(*image.get_pixel(1, 1), Rgba([100, 50, 0, 0x80]))
+            }
+            _ => unreachable!(),
+        }
+
+        let white = Rgba([0xff; 4]);
+        match Background::Solid(black).tint(white, 0.0) {
+            Background::Solid(color) => assert_eq!(color, white),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn empty_background_image() {
+        let expected = Background::default().to_image(6, 4);
+        for &fit in &[
+            BackgroundFit::Tile,
+            BackgroundFit::Stretch,
+            BackgroundFit::Center,
+            BackgroundFit::Cover,
+        ] {
+            let background = Background::Image(RgbaImage::new(0, 0), fit);
+            assert_eq!(background.to_image(6, 4), expected, "{:?}", fit);
+        }
+    }
+
+    #[test]
+    fn asymmetric_padding() {
+        let white = Rgba([0xff; 4]);
+        let background = Rgba([0xff, 0, 0, 0xff]);
+        let image = RgbaImage::from_pixel(40, 20, white);
+        let result = ShadowAdder::new()
+            .background(Background::Solid(background))
+            .enabled(false)
+            .pad_horiz(10)
+            .pad_vert(10)
+            .pad_left(5)
+            .pad_bottom(30)
+            .apply_to(&image);
+
+        assert_eq!(result.dimensions(), (5 + 40 + 10, 10 + 20 + 30));
+        assert_eq!(*result.get_pixel(5, 10), white);
+        assert_eq!(*result.get_pixel(4, 10), background);
+        assert_eq!(*result.get_pixel(5, 9), background);
+        assert_eq!(*result.get_pixel(5 + 39, 10 + 19), white);
+        assert_eq!(*result.get_pixel(5 + 40, 10 + 20), background);
+    }
+
+    #[test]
+    fn fit_image_to_canvas() {
+        let white = Rgba([0xff; 4]);
+        let red = Rgba([0xff, 0, 0, 0xff]);
+        let background = Background::Solid(red);
+
+        // centered on the canvas
+        let image = RgbaImage::from_pixel(40, 20, white);
+        let result = fit_to_canvas(&image, 100, 50, &background);
+        assert_eq!(result.dimensions(), (100, 50));
+        assert_eq!(*result.get_pixel(29, 25), red);
+        assert_eq!(*result.get_pixel(30, 15), white);
+        assert_eq!(*result.get_pixel(69, 34), white);
+        assert_eq!(*result.get_pixel(70, 25), red);
+
+        // scaled down to fit the width, keeping the aspect ratio
+        let image = RgbaImage::from_pixel(200, 20, white);
+        let result = fit_to_canvas(&image, 100, 50, &background);
+        assert_eq!(result.dimensions(), (100, 50));
+        assert_eq!(*result.get_pixel(50, 19), red);
+        assert_eq!(*result.get_pixel(0, 22), white);
+        assert_eq!(*result.get_pixel(99, 27), white);
+        assert_eq!(*result.get_pixel(50, 30), red);
+    }
+
+    #[test]
+    fn round_some_corners() {
+        let white = Rgba([0xff; 4]);
+        let (width, height) = (40, 30);
+        let corners = |which| {
+            let mut image = RgbaImage::from_pixel(width, height, white);
+            round_corner(&mut image, 8, which);
+            [
+                image.get_pixel(0, 0).0[3],
+                image.get_pixel(width - 1, 0).0[3],
+                image.get_pixel(0, height - 1).0[3],
+                image.get_pixel(width - 1, height - 1).0[3],
+            ]
+        };
+
+        assert_eq!(corners(Corners::ALL), [0; 4]);
+        assert_eq!(corners(Corners::NONE), [0xff; 4]);
+        assert_eq!(
+            corners(Corners::TOP_LEFT | Corners::BOTTOM_RIGHT),
+            [0, 0xff, 0xff, 0]
+        );
+        assert!(Corners::ALL.contains(Corners::TOP_RIGHT | Corners::BOTTOM_LEFT));
+        assert!(!Corners::TOP_LEFT.contains(Corners::ALL));
+    }
+
+    #[test]
+    fn disabled_shadow() {
+        let white = Rgba([0xff; 4]);
+        let background = Rgba([0xff, 0, 0, 0xff]);
+        let image = RgbaImage::from_pixel(40, 20, white);
+        let adder = |enabled| {
+            ShadowAdder::new()
+                .background(Background::Solid(background))
+                .shadow_color(Rgba([0, 0, 0, 0xff]))
+                .blur_radius(2.0)
+                .enabled(enabled)
+                .pad_horiz(10)
+                .pad_vert(10)
+                .apply_to(&image)
+        };
+
+        // only the image over the plain background, with the same paddings
+        let result = adder(false);
+        assert_eq!(result.dimensions(), adder(true).dimensions());
+        for (x, y, pixel) in result.enumerate_pixels() {
+            let inside = (10..50).contains(&x) && (10..30).contains(&y);
+            assert_eq!(*pixel, if inside { white } else { background });
+        }
+        assert_ne!(*adder(true).get_pixel(9, 10), background);
+    }
+
+    #[test]
+    fn sharp_shadow() {
+        let white = Rgba([0xff; 4]);
+        let black = Rgba([0, 0, 0, 0xff]);
+        let background = Rgba([0xff, 0, 0, 0xff]);
+        let result = ShadowAdder::new()
+            .background(Background::Solid(background))
+            .shadow_color(black)
+            .blur_radius(0.0)
+            .offset_x(5)
+            .offset_y(5)
+            .pad_horiz(10)
+            .pad_vert(10)
+            .apply_to(&RgbaImage::from_pixel(40, 20, white));
+
+        // the shadow isn't blurred, but drawn with hard edges
+        assert_eq!(*result.get_pixel(12, 12), white);
+        assert_eq!(*result.get_pixel(52, 32), black);
+        assert_eq!(*result.get_pixel(55, 32), background);
+        assert_eq!(*result.get_pixel(52, 35), background);
+        assert_eq!(*result.get_pixel(14, 32), background);
+    }
+
+    #[test]
+    fn soft_shadow_mask() {
+        let mask = |softness| {
+            ShadowAdder::new()
+                .shadow_color(Rgba([0, 0, 0, 200]))
+                .shadow_softness(softness)
+                .soft_shadow_mask(41, 41)
+        };
+        let alpha = |mask: &RgbaImage, x, y| mask.get_pixel(x, y).0[3];
+
+        // opaque at the center, almost transparent at the corners
+        let soft = mask(1.0);
+        assert_eq!(alpha(&soft, 20, 20), 200);
+        assert!(alpha(&soft, 0, 0) < 10);
+        assert!((20..40).all(|x| alpha(&soft, x, 20) > alpha(&soft, x + 1, 20)));
+        // radial: the same at the same distance from the center
+        assert_eq!(alpha(&soft, 25, 20), alpha(&soft, 23, 24));
+        assert_eq!(alpha(&soft, 25, 20), alpha(&soft, 20, 15));
+        assert_ne!(alpha(&soft, 25, 20), alpha(&soft, 25, 25));
+
+        // only the outer part fades with a lower softness
+        let hard = mask(0.25);
+        assert_eq!(alpha(&hard, 40, 20), 200);
+        assert!(alpha(&hard, 0, 0) < alpha(&hard, 2, 2));
+        assert!(alpha(&hard, 2, 2) < 200);
+    }
 }
@@ -0,0 +1,225 @@
+//! Convert VS Code color themes (`.json`) into syntect themes
+//!
+//! # Example
+//!
+//! ```no_run
+//! use silicon::vscode::load_theme;
+//!
+//! let theme = load_theme("monokai-color-theme.json").unwrap();
+//! ```
+use crate::utils::ToRgba;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::path::Path;
+use std::str::FromStr;
+use syntect::highlighting::{
+    Color, FontStyle, ScopeSelectors, StyleModifier, Theme, ThemeItem, ThemeSettings,
+};
+
+/// Load a VS Code theme from a `.json` file
+pub fn load_theme<P: AsRef<Path>>(path: P) -> Result<Theme> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Cannot read the theme: {}", path.display()))?;
+    let mut theme = theme_from_str(&content)
+        .with_context(|| format!("Cannot load the theme: {}", path.display()))?;
+    if theme.name.is_none() {
+        theme.name = path.file_stem().map(|s| s.to_string_lossy().into_owned());
+    }
+    Ok(theme)
+}
+
+/// Convert the content of a VS Code theme into a syntect theme
+pub fn theme_from_str(s: &str) -> Result<Theme> {
+    let json: Value = serde_json::from_str(&strip_trailing_commas(&strip_comments(s)))?;
+    let colors = &json["colors"];
+
+    let mut settings = ThemeSettings {
+        foreground: parse_color(&colors["editor.foreground"]),
+        background: parse_color(&colors["editor.background"]),
+        caret: parse_color(&colors["editorCursor.foreground"]),
+        line_highlight: parse_color(&colors["editor.lineHighlightBackground"]),
+        selection: parse_color(&colors["editor.selectionBackground"]),
+        gutter_foreground: parse_color(&colors["editorLineNumber.foreground"]),
+        ..Default::default()
+    };
+
+    let mut scopes = vec![];
+    for item in json["tokenColors"].as_array().into_iter().flatten() {
+        let style = &item["settings"];
+        let scope = match &item["scope"] {
+            Value::String(scope) => scope.clone(),
+            Value::Array(list) => list
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+                .join(", "),
+            // an item without scope sets the global colors
+            _ => {
+                settings.foreground = settings.foreground.or(parse_color(&style["foreground"]));
+                settings.background = settings.background.or(parse_color(&style["background"]));
+                continue;
+            }
+        };
+        let scope = match ScopeSelectors::from_str(&scope) {
+            Ok(scope) => scope,
+            Err(e) => {
+                warn!("Skip invalid scope `{}`: {:?}", scope, e);
+                continue;
+            }
+        };
+        scopes.push(ThemeItem {
+            scope,
+            style: StyleModifier {
+                foreground: parse_color(&style["foreground"]),
+                background: parse_color(&style["background"]),
+                font_style: style["fontStyle"].as_str().map(parse_font_style),
+            },
+        });
+    }
+
+    // the formatter requires both of them, fallback to the default colors of VS Code
+    let light = json["type"].as_str() == Some("light");
+    let (fg, bg) = if light {
+        ("#000000", "#FFFFFF")
+    } else {
+        ("#D4D4D4", "#1E1E1E")
+    };
+    settings.foreground = settings.foreground.or_else(|| to_color(fg));
+    settings.background = settings.background.or_else(|| to_color(bg));
+
+    Ok(Theme {
+        name: json["name"].as_str().map(str::to_owned),
+        author: json["author"].as_str().map(str::to_owned),
+        settings,
+        scopes,
+    })
+}
+
+fn to_color(s: &str) -> Option<Color> {
+    if s.is_empty() {
+        return None;
+    }
+    s.to_rgba().ok().map(|c| Color {
+        r: c.0[0],
+        g: c.0[1],
+        b: c.0[2],
+        a: c.0[3],
+    })
+}
+
+fn parse_color(value: &Value) -> Option<Color> {
+    value.as_str().and_then(to_color)
+}
+
+fn parse_font_style(s: &str) -> FontStyle {
+    s.split_whitespace()
+        .fold(FontStyle::empty(), |style, word| match word {
+            "bold" => style | FontStyle::BOLD,
+            "italic" => style | FontStyle::ITALIC,
+            "underline" => style | FontStyle::UNDERLINE,
+            _ => style,
+        })
+}
+
+/// VS Code themes are JSONC, remove the `//` and `/* */` comments
+fn strip_comments(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            result.push(c);
+            match c {
+                '\\' => result.extend(chars.next()),
+                '"' => in_string = false,
+                _ => (),
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                result.push(c);
+            }
+            ('/', Some('/')) => while chars.next_if(|&c| c != '\n').is_some() {},
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Remove the trailing commas before `}` and `]`, which are allowed by JSONC
+fn strip_trailing_commas(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if in_string => {
+                result.push(c);
+                result.extend(chars.next());
+                continue;
+            }
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                let rest = chars.clone().find(|c| !c.is_whitespace());
+                if matches!(rest, Some('}') | Some(']')) {
+                    continue;
+                }
+            }
+            _ => (),
+        }
+        result.push(c);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_theme() {
+        let theme = theme_from_str(
+            r##"{
+                // comment
+                "name": "Test",
+                "colors": {
+                    "editor.background": "#112233",
+                    "editor.foreground": "#ffffff", /* comment */
+                },
+                "tokenColors": [
+                    { "settings": { "foreground": "#000000" } },
+                    {
+                        "scope": ["comment", "string.quoted"],
+                        "settings": { "foreground": "#00ff00", "fontStyle": "italic bold" }
+                    },
+                    { "scope": "keyword", "settings": { "foreground": "#ff0000" } },
+                ]
+            }"##,
+        )
+        .unwrap();
+
+        assert_eq!(theme.name.as_deref(), Some("Test"));
+        assert_eq!(to_color("#112233"), theme.settings.background);
+        assert_eq!(to_color("#ffffff"), theme.settings.foreground);
+        assert_eq!(theme.scopes.len(), 2);
+        assert_eq!(
+            theme.scopes[0].style.font_style,
+            Some(FontStyle::ITALIC | FontStyle::BOLD)
+        );
+    }
+}